@@ -0,0 +1,93 @@
+/*
+ * C API for the jibi Scheme interpreter.
+ *
+ * Build the shared library with:
+ *     make capi
+ * which runs:
+ *     cargo rustc --lib --release --features capi --crate-type cdylib
+ *
+ * Values returned by this API are owned by the caller and must be freed with
+ * jibi_value_free (or jibi_string_free for strings). Interpreters and values
+ * are not thread-safe.
+ */
+#ifndef JIBI_H
+#define JIBI_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct JibiInterpreter jibi_interpreter;
+typedef struct JState jibi_state;
+typedef struct JibiValue jibi_value;
+
+/* Builtin implemented in C. Receives the list of evaluated arguments, and must
+ * return a new value, or NULL after calling jibi_raise to raise an error. */
+typedef jibi_value *(*jibi_builtin_fn)(jibi_state *state, const jibi_value *args, void *userdata);
+
+enum jibi_type {
+    JIBI_NIL = 0,
+    JIBI_BOOL = 1,
+    JIBI_INT = 2,
+    JIBI_FLOAT = 3,
+    JIBI_STRING = 4,
+    JIBI_SYMBOL = 5,
+    JIBI_PAIR = 6,
+    JIBI_OTHER = 7,
+};
+
+/* Interpreter */
+jibi_interpreter *jibi_new(void);
+jibi_interpreter *jibi_new_bare(void);
+void jibi_free(jibi_interpreter *interp);
+jibi_state *jibi_get_state(jibi_interpreter *interp);
+
+/* Evaluation: return the value of the last expression, or NULL on error. */
+jibi_value *jibi_eval_str(jibi_interpreter *interp, const char *name, const char *program);
+jibi_value *jibi_eval_file(jibi_interpreter *interp, const char *path);
+jibi_value *jibi_call(jibi_interpreter *interp, const char *name, const jibi_value *const *args, size_t nargs);
+
+/* Last error message and traceback, or NULL.
+ * Valid until the next call with the same interpreter. */
+const char *jibi_error(const jibi_interpreter *interp);
+const char *jibi_traceback(const jibi_interpreter *interp);
+
+/* Globals */
+void jibi_def(jibi_interpreter *interp, const char *name, const jibi_value *val);
+jibi_value *jibi_lookup(jibi_interpreter *interp, const char *name);
+void jibi_register(jibi_interpreter *interp, const char *name, jibi_builtin_fn f, void *userdata);
+/* Kinds are named as in error messages, e.g. "TypeError"; other names are custom kinds. */
+void jibi_raise(const char *kind, const char *reason);
+
+/* Value construction */
+jibi_value *jibi_nil(jibi_state *state);
+jibi_value *jibi_bool(jibi_state *state, int b);
+jibi_value *jibi_int(jibi_state *state, int64_t n);
+jibi_value *jibi_float(jibi_state *state, double x);
+jibi_value *jibi_string(jibi_state *state, const char *s);
+jibi_value *jibi_symbol(jibi_state *state, const char *s);
+jibi_value *jibi_pair(jibi_state *state, const jibi_value *car, const jibi_value *cdr);
+jibi_value *jibi_list(jibi_state *state, const jibi_value *const *items, size_t len);
+
+/* Value inspection: conversions return 0 on success, -1 on type error. */
+int jibi_type(const jibi_value *val);
+int jibi_to_bool(const jibi_value *val, int *out);
+int jibi_to_int(const jibi_value *val, int64_t *out);
+int jibi_to_float(const jibi_value *val, double *out);
+char *jibi_to_string(const jibi_value *val);
+char *jibi_repr(const jibi_value *val);
+jibi_value *jibi_car(const jibi_value *val);
+jibi_value *jibi_cdr(const jibi_value *val);
+
+void jibi_value_free(jibi_value *val);
+jibi_value *jibi_value_clone(const jibi_value *val);
+void jibi_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
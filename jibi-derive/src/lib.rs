@@ -0,0 +1,247 @@
+//! Derive macros for [jibi](https://crates.io/crates/jibi), enabled by its `derive` feature.
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Fields, Generics, Ident, LitStr};
+
+/// Derive `ToJVal`, `FromJVal` and `JibiRecord` for a struct or enum.
+///
+/// Values are represented as `jibi` records: lists tagged by a type symbol,
+/// `(type/Name field ...)` for structs and `(type/Name/Variant field ...)` for
+/// enum variants.
+///
+/// `JibiRecord::register` defines, for a struct `HttpConfig { max_retries, .. }`:
+/// `make-http-config`, `http-config?` and `http-config-max-retries`; and for each
+/// variant `Circle` of an enum `Shape`: `make-shape-circle`, `shape-circle?` and field
+/// accessors `shape-circle-<field>`, plus `shape?` for any variant. Fields of tuple
+/// structs and variants are named by their index. `register` fails without defining
+/// anything if one of these names is already bound.
+///
+/// Type parameters must implement `ToJVal` and `FromJVal`.
+#[proc_macro_derive(JibiValue)]
+pub fn derive_jibi_value(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let res = match &input.data {
+        Data::Struct(data) => derive_struct(&input, &data.fields),
+        Data::Enum(data) => derive_enum(&input, data),
+        Data::Union(_) => Err(syn::Error::new_spanned(
+            &input,
+            "JibiValue cannot be derived for unions",
+        )),
+    };
+    match res {
+        Ok(ts) => ts.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// `HttpConfig` -> `http-config`, `max_retries` -> `max-retries`
+fn kebab_case(name: &str) -> String {
+    let mut out = String::new();
+    let mut prev_lower = false;
+    for c in name.chars() {
+        if c == '_' {
+            out.push('-');
+            prev_lower = false;
+        } else if c.is_uppercase() {
+            if prev_lower {
+                out.push('-');
+            }
+            out.extend(c.to_lowercase());
+            prev_lower = false;
+        } else {
+            out.push(c);
+            prev_lower = c.is_lowercase() || c.is_numeric();
+        }
+    }
+    out
+}
+
+fn field_names(fields: &Fields) -> Vec<String> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(n, f)| match &f.ident {
+            Some(ident) => kebab_case(&ident.to_string()),
+            None => n.to_string(),
+        })
+        .collect()
+}
+
+/// Bindings for the fields, a pattern destructuring them, and an expression
+/// constructing them from the bindings.
+fn field_bindings(path: TokenStream, fields: &Fields) -> (Vec<Ident>, TokenStream) {
+    let bindings: Vec<Ident> = (0..fields.len())
+        .map(|n| format_ident!("__field{}", n))
+        .collect();
+    let pattern = match fields {
+        Fields::Named(named) => {
+            let names = named.named.iter().map(|f| f.ident.as_ref().unwrap());
+            quote! { #path { #(#names: #bindings),* } }
+        }
+        Fields::Unnamed(_) => quote! { #path ( #(#bindings),* ) },
+        Fields::Unit => quote! { #path },
+    };
+    (bindings, pattern)
+}
+
+fn to_fields(bindings: &[Ident]) -> TokenStream {
+    quote! {
+        vec![#( ::jibi::ToJVal::to_jval(#bindings, state)? ),*]
+    }
+}
+
+fn from_fields(bindings: &[Ident], tag: &LitStr) -> TokenStream {
+    let n = bindings.len();
+    let indices = 0..n;
+    quote! {
+        let __fields = ::jibi::convert::record_fields(&val, #tag, #n)?;
+        #( let #bindings = ::jibi::FromJVal::from_jval(::std::rc::Rc::clone(&__fields[#indices]))?; )*
+    }
+}
+
+/// Generics with a bound on each type parameter.
+fn with_bound(generics: &Generics, bound: TokenStream) -> Generics {
+    let mut generics = generics.clone();
+    let params: Vec<Ident> = generics.type_params().map(|p| p.ident.clone()).collect();
+    let where_clause = generics.make_where_clause();
+    for param in params {
+        where_clause
+            .predicates
+            .push(parse_quote! { #param: #bound });
+    }
+    generics
+}
+
+fn derive_struct(input: &DeriveInput, fields: &Fields) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let to_generics = with_bound(&input.generics, quote! { ::jibi::ToJVal });
+    let from_generics = with_bound(&input.generics, quote! { ::jibi::FromJVal });
+    let record_generics = with_bound(
+        &input.generics,
+        quote! { ::jibi::ToJVal + ::jibi::FromJVal + 'static },
+    );
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+    let to_where = &to_generics.where_clause;
+    let from_where = &from_generics.where_clause;
+    let record_where = &record_generics.where_clause;
+    let tag = LitStr::new(&format!("type/{}", name), Span::call_site());
+    let prefix = kebab_case(&name.to_string());
+    let names = field_names(fields);
+    let (bindings, pattern) = field_bindings(quote! { #name }, fields);
+    let to = to_fields(&bindings);
+    let from = from_fields(&bindings, &tag);
+
+    Ok(quote! {
+        impl #impl_generics ::jibi::ToJVal for #name #ty_generics #to_where {
+            fn to_jval(&self, state: &mut ::jibi::JState) -> ::jibi::JResult {
+                let #pattern = self;
+                let __fields = #to;
+                Ok(::jibi::convert::make_record(#tag, __fields, state))
+            }
+        }
+
+        impl #impl_generics ::jibi::FromJVal for #name #ty_generics #from_where {
+            fn from_jval(val: ::jibi::JValRef) -> Result<Self, ::jibi::JError> {
+                #from
+                Ok(#pattern)
+            }
+        }
+
+        impl #impl_generics ::jibi::JibiRecord for #name #ty_generics #record_where {
+            fn register(interpreter: &mut ::jibi::Interpreter) -> Result<(), ::jibi::JError> {
+                ::jibi::convert::register_record(
+                    interpreter,
+                    #tag,
+                    #prefix,
+                    &[#(#names),*],
+                    |val| <Self as ::jibi::FromJVal>::from_jval(val).map(|_| ()),
+                )
+            }
+        }
+    })
+}
+
+fn derive_enum(input: &DeriveInput, data: &syn::DataEnum) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let to_generics = with_bound(&input.generics, quote! { ::jibi::ToJVal });
+    let from_generics = with_bound(&input.generics, quote! { ::jibi::FromJVal });
+    let record_generics = with_bound(
+        &input.generics,
+        quote! { ::jibi::ToJVal + ::jibi::FromJVal + 'static },
+    );
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+    let to_where = &to_generics.where_clause;
+    let from_where = &from_generics.where_clause;
+    let record_where = &record_generics.where_clause;
+    let family_tag = LitStr::new(&format!("type/{}/", name), Span::call_site());
+    let family_prefix = kebab_case(&name.to_string());
+
+    let mut to_arms = vec![];
+    let mut from_arms = vec![];
+    let mut registers = vec![];
+    let mut variant_names = vec![];
+    for variant in &data.variants {
+        let vname = &variant.ident;
+        let tag = LitStr::new(&format!("type/{}/{}", name, vname), Span::call_site());
+        let prefix = format!("{}-{}", family_prefix, kebab_case(&vname.to_string()));
+        let names = field_names(&variant.fields);
+        variant_names.push(quote! {
+            __names.extend(::jibi::convert::record_names(#prefix, &[#(#names),*]));
+        });
+        let (bindings, pattern) = field_bindings(quote! { #name::#vname }, &variant.fields);
+        let to = to_fields(&bindings);
+        let from = from_fields(&bindings, &tag);
+        to_arms.push(quote! {
+            #pattern => {
+                let __fields = #to;
+                Ok(::jibi::convert::make_record(#tag, __fields, state))
+            }
+        });
+        from_arms.push(quote! {
+            Some(#tag) => {
+                #from
+                Ok(#pattern)
+            }
+        });
+        registers.push(quote! {
+            ::jibi::convert::register_record(
+                interpreter,
+                #tag,
+                #prefix,
+                &[#(#names),*],
+                |val| <Self as ::jibi::FromJVal>::from_jval(val).map(|_| ()),
+            )?;
+        });
+    }
+    let expected = format!("expected a {} record", family_tag.value());
+
+    Ok(quote! {
+        impl #impl_generics ::jibi::ToJVal for #name #ty_generics #to_where {
+            fn to_jval(&self, state: &mut ::jibi::JState) -> ::jibi::JResult {
+                match self {
+                    #(#to_arms)*
+                }
+            }
+        }
+
+        impl #impl_generics ::jibi::FromJVal for #name #ty_generics #from_where {
+            fn from_jval(val: ::jibi::JValRef) -> Result<Self, ::jibi::JError> {
+                match ::jibi::convert::record_tag(&val).as_deref() {
+                    #(#from_arms)*
+                    _ => Err(::jibi::JError::new(::jibi::JErrorKind::TypeError, #expected)),
+                }
+            }
+        }
+
+        impl #impl_generics ::jibi::JibiRecord for #name #ty_generics #record_where {
+            fn register(interpreter: &mut ::jibi::Interpreter) -> Result<(), ::jibi::JError> {
+                let mut __names = vec![format!("{}?", #family_prefix)];
+                #(#variant_names)*
+                ::jibi::convert::check_unbound(interpreter, &__names)?;
+                ::jibi::convert::register_record_family(interpreter, #family_tag, #family_prefix)?;
+                #(#registers)*
+                Ok(())
+            }
+        }
+    })
+}
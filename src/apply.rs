@@ -5,9 +5,9 @@ use crate::*;
 impl JVal {
     pub fn apply(&self, args: JValRef, env: JEnvRef, state: &mut JState) -> JResult {
         let res = match self {
-            JVal::Builtin(b) => apply_builtin(b, args, Rc::clone(&env), state),
+            JVal::Builtin(b) => apply_builtin(self, b, args, Rc::clone(&env), state),
             JVal::SpecialForm(b) => apply_special_form(b, args, Rc::clone(&env), state),
-            JVal::Lambda(l) => apply_lambda(l, args, Rc::clone(&env), state),
+            JVal::Lambda(l) => apply_traced_lambda(self, l, args, Rc::clone(&env), state),
             JVal::Macro(l) => apply_proc_macro(l, args, Rc::clone(&env), state),
             _ => return Err(JError::new(TypeError, "expected a callable")),
         };
@@ -30,22 +30,70 @@ fn eval_args(args: JValRef, env: JEnvRef, state: &mut JState) -> JResult {
     Ok(state.list(evaluated))
 }
 
-fn apply_builtin(b: &JBuiltin, args: JValRef, env: JEnvRef, state: &mut JState) -> JResult {
+fn apply_builtin(
+    val: &JVal,
+    b: &JBuiltin,
+    args: JValRef,
+    env: JEnvRef,
+    state: &mut JState,
+) -> JResult {
     let args = eval_args(args, Rc::clone(&env), state)?;
-    (b.f)(args, env, state)
+    call(val, args, state, |args, state| (b.f)(args, env, state))
 }
 
 fn apply_special_form(b: &JBuiltin, args: JValRef, env: JEnvRef, state: &mut JState) -> JResult {
     (b.f)(args, env, state)
 }
 
+/// Apply a lambda which is not a value, e.g. the handler of a reader or char
+/// macro, to unevaluated `args`. The call is not traced nor profiled, since both
+/// record calls by the value of the procedure.
 pub fn apply_lambda(lambda: &JLambda, args: JValRef, env: JEnvRef, state: &mut JState) -> JResult {
-    let invoke_env = JEnv::new(Some(Rc::clone(&lambda.closure))).into_ref();
     let args = eval_args(args, env, state)?;
+    call_lambda(lambda, args, state)
+}
+
+fn apply_traced_lambda(
+    val: &JVal,
+    lambda: &JLambda,
+    args: JValRef,
+    env: JEnvRef,
+    state: &mut JState,
+) -> JResult {
+    let args = eval_args(args, env, state)?;
+    call(val, args, state, |args, state| {
+        call_lambda(lambda, args, state)
+    })
+}
+
+/// Call `f` with evaluated `args`, profiling and tracing the call.
+fn call<F>(val: &JVal, args: JValRef, state: &mut JState, f: F) -> JResult
+where
+    F: FnOnce(JValRef, &mut JState) -> JResult,
+{
+    profiler::call(val, args, state, |args, state| {
+        trace::call(val, args, state, f)
+    })
+}
+
+/// Apply a lambda to evaluated arguments.
+fn call_lambda(lambda: &JLambda, args: JValRef, state: &mut JState) -> JResult {
+    let invoke_env = JEnv::new(Some(Rc::clone(&lambda.closure))).into_ref();
     lambda.params.bind(args, Rc::clone(&invoke_env))?;
+    if let Some(name) = &lambda.name {
+        debugger::enter_frame(state, name, &invoke_env);
+    }
+    let res = eval_body(&lambda.code, invoke_env, state);
+    if lambda.name.is_some() {
+        debugger::exit_frame(state);
+    }
+    res
+}
+
+fn eval_body(code: &[JValRef], env: JEnvRef, state: &mut JState) -> JResult {
     let mut last_res = state.nil();
-    for expr in &lambda.code {
-        last_res = eval(Rc::clone(expr), Rc::clone(&invoke_env), state)?;
+    for expr in code {
+        last_res = eval(Rc::clone(expr), Rc::clone(&env), state)?;
     }
     Ok(last_res)
 }
@@ -53,9 +101,6 @@ pub fn apply_lambda(lambda: &JLambda, args: JValRef, env: JEnvRef, state: &mut J
 fn apply_proc_macro(lambda: &JLambda, args: JValRef, env: JEnvRef, state: &mut JState) -> JResult {
     let invoke_env = JEnv::new(Some(Rc::clone(&lambda.closure))).into_ref();
     lambda.params.bind(args, Rc::clone(&invoke_env))?;
-    let mut last_res = state.nil();
-    for expr in &lambda.code {
-        last_res = eval(Rc::clone(expr), Rc::clone(&invoke_env), state)?;
-    }
-    eval(last_res, env, state)
+    let expansion = eval_body(&lambda.code, invoke_env, state)?;
+    eval(expansion, env, state)
 }
@@ -0,0 +1,428 @@
+//! Benchmarks, used by `stl/bench` and `jibi bench`.
+//!
+//! The `run-bench` special form times its expressions over many iterations,
+//! after warmup iterations. [`BenchRunner`] runs benchmark files in fresh
+//! interpreters, reports the [`Stats`] of each benchmark, and compares them
+//! with a [`Baseline`] saved by a previous run.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::testing::{discover_prefixed, run_captured, write_report};
+use crate::*;
+
+/// Statistics of the iterations of a benchmark.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub iterations: usize,
+    pub mean: Duration,
+    pub median: Duration,
+    /// Population standard deviation.
+    pub stddev: Duration,
+}
+
+impl Stats {
+    /// Statistics of the durations of iterations, which must not be empty.
+    pub fn new(samples: &[Duration]) -> Self {
+        let n = samples.len();
+        let mut sorted = samples.to_vec();
+        sorted.sort();
+        let mean = samples.iter().sum::<Duration>() / n as u32;
+        let median = match n % 2 {
+            0 => (sorted[n / 2 - 1] + sorted[n / 2]) / 2,
+            _ => sorted[n / 2],
+        };
+        let variance = samples
+            .iter()
+            .map(|s| (s.as_secs_f64() - mean.as_secs_f64()).powi(2))
+            .sum::<f64>()
+            / n as f64;
+        Self {
+            iterations: n,
+            mean,
+            median,
+            stddev: Duration::from_secs_f64(variance.sqrt()),
+        }
+    }
+}
+
+/// Duration in the largest unit under which it is at least 1.
+fn format_duration(d: Duration) -> String {
+    let ns = d.as_nanos();
+    match ns {
+        0..=999 => format!("{} ns", ns),
+        1_000..=999_999 => format!("{:.3} µs", ns as f64 / 1e3),
+        1_000_000..=999_999_999 => format!("{:.3} ms", ns as f64 / 1e6),
+        _ => format!("{:.3} s", ns as f64 / 1e9),
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "mean {}, median {}, stddev {} ({} iterations)",
+            format_duration(self.mean),
+            format_duration(self.median),
+            format_duration(self.stddev),
+            self.iterations
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchResult {
+    pub name: String,
+    pub stats: Stats,
+}
+
+/// Benchmarks run in a file, see [`run_bench`].
+pub(crate) struct BenchSession {
+    filter: Option<String>,
+    warmup: Option<usize>,
+    iterations: Option<usize>,
+    results: Vec<BenchResult>,
+    filtered: usize,
+}
+
+fn time<F>(warmup: usize, iterations: usize, state: &mut JState, mut f: F) -> Result<Stats, JError>
+where
+    F: FnMut(&mut JState) -> JResult,
+{
+    if iterations == 0 {
+        return Err(JError::new(ApplyError, "expected at least one iteration"));
+    }
+    for _ in 0..warmup {
+        f(state)?;
+    }
+    let mut samples = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let start = Instant::now();
+        f(state)?;
+        samples.push(start.elapsed());
+    }
+    Ok(Stats::new(&samples))
+}
+
+/// Time the benchmark `name`, evaluated by `f`, over `iterations` after
+/// `warmup` iterations (unless `jibi bench` sets them): `None` if it is
+/// filtered out.
+pub(crate) fn run_bench<F>(
+    name: &str,
+    mut warmup: usize,
+    mut iterations: usize,
+    state: &mut JState,
+    f: F,
+) -> Option<Result<Stats, JError>>
+where
+    F: FnMut(&mut JState) -> JResult,
+{
+    if let Some(session) = state.bench_session_mut() {
+        if let Some(filter) = &session.filter {
+            if !name.contains(filter.as_str()) {
+                session.filtered += 1;
+                return None;
+            }
+        }
+        warmup = session.warmup.unwrap_or(warmup);
+        iterations = session.iterations.unwrap_or(iterations);
+    }
+    let res = time(warmup, iterations, state, f);
+    if let (Ok(stats), Some(session)) = (&res, state.bench_session_mut()) {
+        session.results.push(BenchResult {
+            name: name.to_string(),
+            stats: *stats,
+        });
+    }
+    Some(res)
+}
+
+/// Benchmark files in `paths`: the files themselves, and the `bench-*.jibi`
+/// files in directories and their subdirectories, sorted by path. Hidden
+/// directories are skipped.
+pub fn discover(paths: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    discover_prefixed(paths, "bench-")
+}
+
+/// Results of the benchmarks of a file.
+pub struct BenchFileResult {
+    pub path: PathBuf,
+    pub benches: Vec<BenchResult>,
+    /// Number of benchmarks not run because their name does not match the filter.
+    pub filtered: usize,
+    /// Traceback and error if the file raised an error.
+    pub error: Option<String>,
+    /// Output printed by the file.
+    pub output: String,
+}
+
+/// Path of a benchmark file in baselines: paths naming the same file (e.g.
+/// `benches` and `./benches`) are the same once canonicalized.
+fn normalize(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn bench_key(path: &Path, name: &str) -> String {
+    format!("{}: {}", normalize(path).display(), name)
+}
+
+/// Mean durations of benchmarks, by file and name, to compare later runs with.
+///
+/// Saved as one line per benchmark: the mean in nanoseconds, a space, then the
+/// file and name of the benchmark separated by `: `. Files are saved relative
+/// to the directory of the baseline when they are in it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Baseline(BTreeMap<String, Duration>);
+
+impl Baseline {
+    pub fn new(results: &[BenchFileResult]) -> Self {
+        let mut baseline = Self::default();
+        for result in results {
+            for bench in &result.benches {
+                baseline
+                    .0
+                    .insert(bench_key(&result.path, &bench.name), bench.stats.mean);
+            }
+        }
+        baseline
+    }
+
+    /// Parse a baseline whose relative paths are relative to the current directory.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::parse_in(s, Path::new(""))
+    }
+
+    fn parse_in(s: &str, dir: &Path) -> Result<Self, String> {
+        let mut baseline = Self::default();
+        for (n, line) in s.lines().enumerate() {
+            let (mean, path, name) = line
+                .split_once(' ')
+                .and_then(|(mean, key)| {
+                    let (path, name) = key.split_once(": ")?;
+                    Some((mean.parse().ok()?, path, name))
+                })
+                .ok_or_else(|| {
+                    format!("line {}: expected nanoseconds, a file and a name", n + 1)
+                })?;
+            baseline
+                .0
+                .insert(bench_key(&dir.join(path), name), Duration::from_nanos(mean));
+        }
+        Ok(baseline)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::parse_in(&std::fs::read_to_string(path)?, &base_dir(path))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let dir = normalize(&base_dir(path));
+        let mut lines = String::new();
+        for (key, mean) in &self.0 {
+            let key = match key.strip_prefix(&format!("{}/", dir.display())) {
+                Some(relative) => relative,
+                None => key,
+            };
+            lines.push_str(&format!("{} {}\n", mean.as_nanos(), key));
+        }
+        std::fs::write(path, lines)
+    }
+
+    /// Mean of the benchmark `name` of the file `path`.
+    pub fn get(&self, path: &Path, name: &str) -> Option<Duration> {
+        self.0.get(&bench_key(path, name)).copied()
+    }
+}
+
+/// Directory of the file `path`, which relative paths in it are relative to.
+fn base_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(dir) if dir != Path::new("") => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+impl fmt::Display for Baseline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, mean) in &self.0 {
+            writeln!(f, "{} {}", mean.as_nanos(), key)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct BenchRunner {
+    filter: Option<String>,
+    warmup: Option<usize>,
+    iterations: Option<usize>,
+    baseline: Option<Baseline>,
+    threshold: f64,
+}
+
+impl Default for BenchRunner {
+    fn default() -> Self {
+        Self {
+            filter: None,
+            warmup: None,
+            iterations: None,
+            baseline: None,
+            threshold: 10.0,
+        }
+    }
+}
+
+impl BenchRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only run the benchmarks whose name contains `filter`.
+    pub fn filter(mut self, filter: &str) -> Self {
+        self.filter = Some(filter.to_string());
+        self
+    }
+
+    /// Run `warmup` iterations before timing, instead of the number given by
+    /// each benchmark.
+    pub fn warmup(mut self, warmup: usize) -> Self {
+        self.warmup = Some(warmup);
+        self
+    }
+
+    /// Time `iterations` iterations, instead of the number given by each
+    /// benchmark.
+    pub fn iterations(mut self, iterations: usize) -> Self {
+        self.iterations = Some(iterations);
+        self
+    }
+
+    /// Compare the means of benchmarks with `baseline`.
+    pub fn baseline(mut self, baseline: Baseline) -> Self {
+        self.baseline = Some(baseline);
+        self
+    }
+
+    /// Percentage by which a mean must exceed the baseline to be a regression
+    /// (default: 10).
+    pub fn threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Change of the mean of a benchmark from the baseline, in percent.
+    pub fn change(&self, path: &Path, bench: &BenchResult) -> Option<f64> {
+        let base = self.baseline.as_ref()?.get(path, &bench.name)?;
+        Some((bench.stats.mean.as_secs_f64() / base.as_secs_f64() - 1.0) * 100.0)
+    }
+
+    /// Whether there is a baseline, which has no mean for a benchmark.
+    pub fn missing(&self, path: &Path, bench: &BenchResult) -> bool {
+        matches!(&self.baseline, Some(baseline) if baseline.get(path, &bench.name).is_none())
+    }
+
+    /// Whether a benchmark is slower than the baseline by more than the
+    /// threshold.
+    pub fn regressed(&self, path: &Path, bench: &BenchResult) -> bool {
+        self.change(path, bench)
+            .is_some_and(|change| change > self.threshold)
+    }
+
+    /// Run a benchmark file in a fresh interpreter.
+    pub fn run_file(&self, path: &Path) -> BenchFileResult {
+        let mut run = run_captured(path, |interpreter, _| {
+            interpreter.state.set_bench_session(Some(BenchSession {
+                filter: self.filter.clone(),
+                warmup: self.warmup,
+                iterations: self.iterations,
+                results: vec![],
+                filtered: 0,
+            }))
+        });
+        let session = run.interpreter.state.take_bench_session().unwrap();
+        BenchFileResult {
+            path: path.to_path_buf(),
+            benches: session.results,
+            filtered: session.filtered,
+            error: run.error,
+            output: run.output,
+        }
+    }
+
+    /// Run benchmark files, writing the statistics of each benchmark to `out`
+    /// as they complete, with their change from the baseline, then the output
+    /// and tracebacks of failed files and a summary.
+    pub fn run<W: Write>(&self, files: &[PathBuf], mut out: W) -> io::Result<Vec<BenchFileResult>> {
+        let start = Instant::now();
+        let mut results = vec![];
+        for file in files {
+            let result = self.run_file(file);
+            for bench in &result.benches {
+                write!(
+                    out,
+                    "bench {}: {} ... {}",
+                    file.display(),
+                    bench.name,
+                    bench.stats
+                )?;
+                match self.change(file, bench) {
+                    Some(change) if self.regressed(file, bench) => {
+                        writeln!(out, ", {:+.1}% REGRESSED", change)?
+                    }
+                    Some(change) => writeln!(out, ", {:+.1}%", change)?,
+                    None if self.missing(file, bench) => writeln!(out, ", not in baseline")?,
+                    None => writeln!(out)?,
+                }
+            }
+            if result.error.is_some() {
+                writeln!(out, "bench {} ... FAILED", file.display())?;
+            }
+            results.push(result);
+        }
+
+        let failures: Vec<(String, String)> = results
+            .iter()
+            .filter_map(|r| {
+                let error = r.error.as_ref()?;
+                Some((
+                    r.path.display().to_string(),
+                    format!("{}{}", r.output, error),
+                ))
+            })
+            .collect();
+        let benches = || {
+            results
+                .iter()
+                .flat_map(|r| r.benches.iter().map(move |b| (&r.path, b)))
+        };
+        let regressed = benches()
+            .filter(|(path, bench)| self.regressed(path, bench))
+            .count();
+        let mut counts = vec![
+            format!("{} benchmarks", benches().count()),
+            format!("{} regressed", regressed),
+            format!("{} failed", failures.len()),
+            format!(
+                "{} filtered out",
+                results.iter().map(|r| r.filtered).sum::<usize>()
+            ),
+        ];
+        if self.baseline.is_some() {
+            let missing = benches()
+                .filter(|(path, bench)| self.missing(path, bench))
+                .count();
+            counts.push(format!("{} not in baseline", missing));
+        }
+        write_report(
+            out,
+            "bench",
+            &failures,
+            failures.is_empty() && regressed == 0,
+            &counts,
+            start,
+        )?;
+        Ok(results)
+    }
+}
@@ -1,23 +1,147 @@
+use std::convert::TryFrom;
 use std::rc::Rc;
 
-use crate::builtin::get_n_args;
+use crate::builtin::{get_n_args, get_n_plus_args};
+use crate::profiler::Profiler;
 use crate::*;
 
 pub fn jbuiltin_display_debug(args: JValRef, _env: JEnvRef, state: &mut JState) -> JResult {
     let [val] = get_n_args(args)?;
-    println!("{:?}", val);
+    state.println(&format!("{:?}", val));
     Ok(state.nil())
 }
 
 pub fn jbuiltin_display_debug_pretty(args: JValRef, _env: JEnvRef, state: &mut JState) -> JResult {
     let [val] = get_n_args(args)?;
-    println!("{:#?}", val);
+    state.println(&format!("{:#?}", val));
     Ok(state.nil())
 }
 
+pub fn jbuiltin_breakpoint(args: JValRef, env: JEnvRef, state: &mut JState) -> JResult {
+    let [] = get_n_args(args)?;
+    crate::debugger::breakpoint(&env, state)?;
+    Ok(state.nil())
+}
+
+pub fn jbuiltin_trace(args: JValRef, _env: JEnvRef, state: &mut JState) -> JResult {
+    let [val] = get_n_args(args)?;
+    state.tracer_mut().trace(&val)?;
+    Ok(state.nil())
+}
+
+pub fn jbuiltin_untrace(args: JValRef, _env: JEnvRef, state: &mut JState) -> JResult {
+    let vals: Vec<JValRef> = args.iter_list()?.collect();
+    if vals.is_empty() {
+        state.tracer_mut().untrace_all();
+    }
+    for val in vals {
+        state.tracer_mut().untrace(&val);
+    }
+    Ok(state.nil())
+}
+
+pub fn jspecial_with_profiling(args: JValRef, env: JEnvRef, state: &mut JState) -> JResult {
+    let mut outer = state.take_profiler();
+    state.set_profiler(Some(Profiler::new()));
+    let mut res = Ok(state.nil());
+    for expr in args.iter_list()? {
+        res = eval(expr, Rc::clone(&env), state);
+        if res.is_err() {
+            break;
+        }
+    }
+    let profiler = state.take_profiler();
+    if let Some(profiler) = profiler {
+        state.println(profiler.report().trim_end());
+        if let Some(outer) = &mut outer {
+            outer.merge(profiler);
+        }
+    }
+    state.set_profiler(outer);
+    res
+}
+
+/// Name of the suite of a test, `nil` outside of suites.
+fn test_suite(suite: &JVal) -> Result<Option<String>, JError> {
+    match suite {
+        JVal::Nil => Ok(None),
+        suite => Ok(Some(suite.to_str()?.to_string())),
+    }
+}
+
+pub fn jspecial_run_test(args: JValRef, env: JEnvRef, state: &mut JState) -> JResult {
+    let ([suite, name, expect_fail], body) = get_n_plus_args(args)?;
+    let suite = eval(suite, Rc::clone(&env), state)?;
+    let suite = test_suite(&suite)?;
+    let name = eval(name, Rc::clone(&env), state)?;
+    let name = name.to_str()?.to_string();
+    let expect_fail = eval(expect_fail, Rc::clone(&env), state)?.to_bool()?;
+    let res =
+        crate::testing::run_test(suite.as_deref(), &name, expect_fail, &env, state, |state| {
+            let mut res = Ok(state.nil());
+            for expr in body {
+                res = Ok(eval(expr, Rc::clone(&env), state)?);
+            }
+            res
+        });
+    match res {
+        None => Ok(state.nil()),
+        Some(Ok(())) => Ok(state.bool(true)),
+        Some(Err(err)) => Ok(JVal::Error(err).into_ref()),
+    }
+}
+
+pub fn jbuiltin_skip_test(args: JValRef, _env: JEnvRef, state: &mut JState) -> JResult {
+    let [suite, name, reason] = get_n_args(args)?;
+    let suite = test_suite(&suite)?;
+    let skipped =
+        crate::testing::skip_test(suite.as_deref(), name.to_str()?, reason.to_str()?, state);
+    Ok(if skipped {
+        state.bool(true)
+    } else {
+        state.nil()
+    })
+}
+
+pub fn jbuiltin_running_tests(args: JValRef, _env: JEnvRef, state: &mut JState) -> JResult {
+    let [] = get_n_args(args)?;
+    Ok(state.bool(state.running_tests()))
+}
+
+/// A number of iterations of a benchmark.
+fn iterations(n: &JVal) -> Result<usize, JError> {
+    usize::try_from(n.to_int()?).map_err(|_| JError::new(TypeError, "expected a natural number"))
+}
+
+pub fn jspecial_run_bench(args: JValRef, env: JEnvRef, state: &mut JState) -> JResult {
+    let ([name, warmup, count], body) = get_n_plus_args(args)?;
+    let name = eval(name, Rc::clone(&env), state)?;
+    let name = name.to_str()?.to_string();
+    let warmup = iterations(&*eval(warmup, Rc::clone(&env), state)?)?;
+    let count = iterations(&*eval(count, Rc::clone(&env), state)?)?;
+    let res = crate::bench::run_bench(&name, warmup, count, state, |state| {
+        let mut res = Ok(state.nil());
+        for expr in &body {
+            res = Ok(eval(Rc::clone(expr), Rc::clone(&env), state)?);
+        }
+        res
+    });
+    match res {
+        None => Ok(state.nil()),
+        Some(Ok(stats)) => {
+            let nanos = [stats.mean, stats.median, stats.stddev]
+                .iter()
+                .map(|d| state.int(d.as_nanos() as JTInt))
+                .collect();
+            Ok(state.list(nanos))
+        }
+        Some(Err(err)) => Err(err),
+    }
+}
+
 pub fn jbuiltin_display_ptr(args: JValRef, _env: JEnvRef, state: &mut JState) -> JResult {
     let [val] = get_n_args(args)?;
-    println!("{:p}", val);
+    state.println(&format!("{:p}", val));
     Ok(state.nil())
 }
 
@@ -35,7 +159,7 @@ pub fn jspecial_display_debug_macro(args: JValRef, env: JEnvRef, state: &mut JSt
     for expr in &lambda.code {
         last_res = eval(Rc::clone(expr), Rc::clone(&invoke_env), state)?;
     }
-    println!("{}", last_res);
+    state.println(&last_res.to_string());
     Ok(state.nil())
 }
 
@@ -51,7 +175,7 @@ pub fn jbuiltin_display_code(args: JValRef, _env: JEnvRef, state: &mut JState) -
             ))
         }
     };
-    println!(
+    let code = format!(
         "({} {} {})",
         t,
         params,
@@ -60,5 +184,6 @@ pub fn jbuiltin_display_code(args: JValRef, _env: JEnvRef, state: &mut JState) -
             .collect::<Vec<String>>()
             .join(" ")
     );
+    state.println(&code);
     Ok(state.nil())
 }
@@ -0,0 +1,28 @@
+use crate::builtin::{get_n_args, get_n_plus_args};
+use crate::*;
+
+pub fn jbuiltin_foreign_call(args: JValRef, _env: JEnvRef, state: &mut JState) -> JResult {
+    let ([obj, method], rest) = get_n_plus_args(args)?;
+    let obj = obj.to_foreign()?;
+    let method = method.to_symbol()?;
+    let args = state.list(rest);
+    obj.call_method(method, args, state)
+}
+
+pub fn jbuiltin_foreign_type(args: JValRef, _env: JEnvRef, state: &mut JState) -> JResult {
+    let [obj] = get_n_args(args)?;
+    let obj = obj.to_foreign()?;
+    Ok(state.symbol(obj.type_name().to_string()))
+}
+
+pub fn jbuiltin_foreign_methods(args: JValRef, _env: JEnvRef, state: &mut JState) -> JResult {
+    let [obj] = get_n_args(args)?;
+    let obj = obj.to_foreign()?;
+    let names = obj
+        .ftype
+        .method_names()
+        .into_iter()
+        .map(|n| state.symbol(n.to_string()))
+        .collect();
+    Ok(state.list(names))
+}
@@ -1,13 +1,15 @@
 use std::rc::Rc;
 
 use crate::*;
-use args::*;
+pub(crate) use args::*;
 use debug::*;
 use env::*;
 use error::*;
+use foreign::*;
 use list::*;
 use math::*;
 use readermacro::*;
+use readtable::*;
 use string::*;
 use sys::*;
 use vector::*;
@@ -16,9 +18,11 @@ mod args;
 mod debug;
 mod env;
 mod error;
+mod foreign;
 mod list;
 mod math;
 mod readermacro;
+mod readtable;
 mod string;
 mod sys;
 mod vector;
@@ -62,7 +66,7 @@ fn jspecial_or(args: JValRef, env: JEnvRef, state: &mut JState) -> JResult {
 fn jbuiltin_print(args: JValRef, _env: JEnvRef, state: &mut JState) -> JResult {
     let [s] = get_n_args(args)?;
     let s = s.to_str()?;
-    println!("{}", s);
+    state.println(s);
     Ok(state.nil())
 }
 
@@ -173,6 +177,7 @@ fn jbuiltin_type(args: JValRef, _env: JEnvRef, state: &mut JState) -> JResult {
             JVal::Env(_) => "env",
             JVal::Token(_) => "token",
             JVal::TokenMatcher(_) => "tokenmatcher",
+            JVal::Foreign(_) => "foreign",
         }
         .to_string(),
     ))
@@ -321,14 +326,48 @@ pub fn add_builtins(env: JEnvRef, state: &mut JState) {
     add_builtin("dda", jbuiltin_display_ptr, &env, state);
     add_builtin("ddc", jbuiltin_display_code, &env, state);
     add_special_form("ddm", jspecial_display_debug_macro, &env, state);
+    add_builtin("breakpoint", jbuiltin_breakpoint, &env, state);
+    add_builtin("trace", jbuiltin_trace, &env, state);
+    add_builtin("untrace", jbuiltin_untrace, &env, state);
+    add_special_form("with-profiling", jspecial_with_profiling, &env, state);
+    add_special_form("run-test", jspecial_run_test, &env, state);
+    add_builtin("skip-test", jbuiltin_skip_test, &env, state);
+    add_builtin("running-tests?", jbuiltin_running_tests, &env, state);
+    add_special_form("run-bench", jspecial_run_bench, &env, state);
 
     // Reader macros
     add_builtin("token", jbuiltin_token, &env, state);
     add_builtin("token-match", jbuiltin_tokenmatcher, &env, state);
+    add_builtin("token-match-or", jbuiltin_tokenmatcher_or, &env, state);
+    add_builtin("token-match-seq", jbuiltin_tokenmatcher_seq, &env, state);
+    add_builtin(
+        "token-match-repeat",
+        jbuiltin_tokenmatcher_repeat,
+        &env,
+        state,
+    );
     add_builtin("token-value", jbuiltin_token_value, &env, state);
     add_builtin("token-type", jbuiltin_token_type, &env, state);
     add_builtin("reader-macro!", jbuiltin_install_reader_macro, &env, state);
 
+    // Readtable
+    add_builtin("char-macro!", jbuiltin_char_macro, &env, state);
+    add_builtin(
+        "char-macro-remove!",
+        jbuiltin_char_macro_remove,
+        &env,
+        state,
+    );
+    add_builtin("read-char", jbuiltin_read_char, &env, state);
+    add_builtin("peek-char", jbuiltin_peek_char, &env, state);
+    add_builtin("read", jbuiltin_read, &env, state);
+    add_builtin("read-delimited", jbuiltin_read_delimited, &env, state);
+
+    // Foreign objects
+    add_builtin("foreign-call", jbuiltin_foreign_call, &env, state);
+    add_builtin("foreign-type", jbuiltin_foreign_type, &env, state);
+    add_builtin("foreign-methods", jbuiltin_foreign_methods, &env, state);
+
     // Vector module
     env.define("vec", vector_mod(Rc::clone(&env), state));
 }
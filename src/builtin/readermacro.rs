@@ -1,5 +1,7 @@
-use crate::builtin::get_n_args;
-use crate::reader::readermacro::{Matcher, ReaderMacro, TokenMatcher};
+use std::rc::Rc;
+
+use crate::builtin::{get_n_args, get_n_plus_args};
+use crate::reader::readermacro::{Matcher, ReaderMacro, TokenMatcher, TokenRegex};
 use crate::*;
 
 fn str_to_char(s: &str) -> Result<char, JError> {
@@ -27,9 +29,11 @@ pub fn jbuiltin_token(args: JValRef, _env: JEnvRef, state: &mut JState) -> JResu
         ("string", JVal::String(s)) => state.token(TokenValue::String(s.clone())),
         ("ident", JVal::Symbol(s)) => state.token(TokenValue::Ident(s.clone())),
         ("ident", JVal::String(s)) => state.token(TokenValue::Ident(s.clone())),
+        ("bool", JVal::Bool(b)) => state.token(TokenValue::Bool(*b)),
         ("int", JVal::Int(n)) => state.token(TokenValue::Int(*n)),
         ("float", JVal::Float(x)) => state.token(TokenValue::Float(*x)),
         ("eof", JVal::Nil) => state.token(TokenValue::Eof),
+        ("datum", _) => state.token(TokenValue::Datum(v2)),
         _ => Err(JError::new(TypeError, "invalid token definition")),
     }
 }
@@ -54,16 +58,59 @@ pub fn jbuiltin_tokenmatcher(args: JValRef, _env: JEnvRef, state: &mut JState) -
         ("ident", JVal::Symbol(s)) => TokenMatcher::Ident(Matcher::Exact(s.clone())),
         ("ident", JVal::String(s)) => TokenMatcher::Ident(Matcher::Exact(s.clone())),
         ("ident", JVal::Nil) => TokenMatcher::Ident(Matcher::Any),
+        ("bool", JVal::Bool(b)) => TokenMatcher::Bool(Matcher::Exact(*b)),
+        ("bool", JVal::Nil) => TokenMatcher::Bool(Matcher::Any),
         ("int", JVal::Int(n)) => TokenMatcher::Int(Matcher::Exact(*n)),
         ("int", JVal::Nil) => TokenMatcher::Int(Matcher::Any),
         ("float", JVal::Float(x)) => TokenMatcher::Float(Matcher::Exact(*x)),
         ("float", JVal::Nil) => TokenMatcher::Float(Matcher::Any),
         ("char", JVal::String(c)) => TokenMatcher::Char(str_to_char(c)?),
+        ("regex", JVal::String(s)) => TokenMatcher::Regex(TokenRegex::new(s)?),
+        ("group", JVal::Nil) => TokenMatcher::Group,
         _ => return Err(JError::new(TypeError, "invalid token matcher definition")),
     })
     .into_ref())
 }
 
+fn to_tokenmatchers(vals: &[JValRef]) -> Result<Vec<TokenMatcher>, JError> {
+    vals.iter().map(|v| v.to_tokenmatcher().cloned()).collect()
+}
+
+pub fn jbuiltin_tokenmatcher_or(args: JValRef, _env: JEnvRef, _state: &mut JState) -> JResult {
+    let ([first], rest) = get_n_plus_args(args)?;
+    let tm = to_tokenmatchers(&rest)?
+        .into_iter()
+        .fold(first.to_tokenmatcher()?.clone(), |tm1, tm2| tm1.or(tm2));
+    Ok(JVal::TokenMatcher(tm).into_ref())
+}
+
+pub fn jbuiltin_tokenmatcher_seq(args: JValRef, _env: JEnvRef, _state: &mut JState) -> JResult {
+    let tms: Vec<JValRef> = args.iter_list()?.collect();
+    Ok(JVal::TokenMatcher(TokenMatcher::Seq(to_tokenmatchers(&tms)?)).into_ref())
+}
+
+fn to_count(val: &JVal) -> Result<usize, JError> {
+    match val.to_int()? {
+        n if n >= 0 => Ok(n as usize),
+        _ => Err(JError::new(TypeError, "expected a positive integer")),
+    }
+}
+
+pub fn jbuiltin_tokenmatcher_repeat(args: JValRef, _env: JEnvRef, _state: &mut JState) -> JResult {
+    let ([tm, min], rest) = get_n_plus_args(args)?;
+    let max = match rest.as_slice() {
+        [] => None,
+        [max] => Some(to_count(max)?),
+        _ => return Err(JError::new(ApplyError, "expected 2 or 3 arguments")),
+    };
+    Ok(JVal::TokenMatcher(TokenMatcher::Repeat(
+        Box::new(tm.to_tokenmatcher()?.clone()),
+        to_count(&min)?,
+        max,
+    ))
+    .into_ref())
+}
+
 pub fn jbuiltin_token_type(args: JValRef, _env: JEnvRef, state: &mut JState) -> JResult {
     let [tok] = get_n_args(args)?;
     let tok = tok.to_token()?;
@@ -72,9 +119,12 @@ pub fn jbuiltin_token_type(args: JValRef, _env: JEnvRef, state: &mut JState) ->
             TokenValue::Eof => "eof",
             TokenValue::String(_) => "string",
             TokenValue::Ident(_) => "ident",
+            TokenValue::Bool(_) => "bool",
             TokenValue::Int(_) => "int",
             TokenValue::Float(_) => "float",
             TokenValue::Char(_) => "char",
+            TokenValue::DatumComment => "datum-comment",
+            TokenValue::Datum(_) => "datum",
         }
         .to_string(),
     ))
@@ -86,9 +136,11 @@ pub fn jbuiltin_token_value(args: JValRef, _env: JEnvRef, state: &mut JState) ->
     Ok(match &tok.value {
         TokenValue::String(s) => state.string(s.clone()),
         TokenValue::Ident(s) => state.symbol(s.clone()),
+        TokenValue::Bool(b) => state.bool(*b),
         TokenValue::Int(n) => state.int(*n),
         TokenValue::Float(x) => state.float(*x),
         TokenValue::Char(c) => state.string(c.to_string()),
+        TokenValue::Datum(v) => Rc::clone(v),
         _ => state.nil(),
     })
 }
@@ -101,7 +153,7 @@ pub fn jbuiltin_install_reader_macro(args: JValRef, env: JEnvRef, state: &mut JS
     let transformer = matchers.pop().unwrap().to_lambda()?.clone();
     let matchers = matchers
         .iter()
-        .map(|v| v.to_tokenmatcher().map(|t| t.clone()))
+        .map(|v| v.to_tokenmatcher().cloned())
         .collect::<Result<Vec<TokenMatcher>, JError>>()?;
 
     state.add_reader_macro(ReaderMacro::new(matchers, transformer, env));
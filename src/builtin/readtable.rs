@@ -0,0 +1,61 @@
+use std::rc::Rc;
+
+use crate::builtin::get_n_args;
+use crate::reader::readtable::{CharMacro, CharStream};
+use crate::*;
+
+fn to_char_stream(val: &JVal) -> Result<Rc<CharStream>, JError> {
+    val.to_foreign()?
+        .downcast_rc::<CharStream>()
+        .ok_or_else(|| JError::new(TypeError, "expected a char-stream"))
+}
+
+fn char_or_nil(c: Option<char>, state: &mut JState) -> JValRef {
+    match c {
+        Some(c) => state.string(c.to_string()),
+        None => state.nil(),
+    }
+}
+
+pub fn jbuiltin_char_macro(args: JValRef, env: JEnvRef, state: &mut JState) -> JResult {
+    let [key, handler] = get_n_args(args)?;
+    let cm = CharMacro::new(key.to_str()?, handler.to_lambda()?.clone(), env)?;
+    state.readtable_mut().set(cm);
+    Ok(state.nil())
+}
+
+pub fn jbuiltin_char_macro_remove(args: JValRef, _env: JEnvRef, state: &mut JState) -> JResult {
+    let [key] = get_n_args(args)?;
+    let removed = state.readtable_mut().remove(key.to_str()?).is_some();
+    Ok(state.bool(removed))
+}
+
+pub fn jbuiltin_read_char(args: JValRef, _env: JEnvRef, state: &mut JState) -> JResult {
+    let [stream] = get_n_args(args)?;
+    let c = to_char_stream(&stream)?.read_char();
+    Ok(char_or_nil(c, state))
+}
+
+pub fn jbuiltin_peek_char(args: JValRef, _env: JEnvRef, state: &mut JState) -> JResult {
+    let [stream] = get_n_args(args)?;
+    let c = to_char_stream(&stream)?.peek_char();
+    Ok(char_or_nil(c, state))
+}
+
+pub fn jbuiltin_read(args: JValRef, _env: JEnvRef, state: &mut JState) -> JResult {
+    let [stream] = get_n_args(args)?;
+    match to_char_stream(&stream)?.read(state)? {
+        Some(val) => Ok(val),
+        None => Err(JError::new(SyntaxError, "unexpected end of input")),
+    }
+}
+
+pub fn jbuiltin_read_delimited(args: JValRef, _env: JEnvRef, state: &mut JState) -> JResult {
+    let [stream, close] = get_n_args(args)?;
+    let close = match close.to_str()? {
+        s if s.chars().count() == 1 => s.chars().next().unwrap(),
+        _ => return Err(JError::new(TypeError, "expected a single char")),
+    };
+    let items = to_char_stream(&stream)?.read_delimited(close, state)?;
+    Ok(state.list(items))
+}
@@ -20,7 +20,7 @@ pub fn jbuiltin_exit(args: JValRef, _env: JEnvRef, _state: &mut JState) -> JResu
 pub fn jbuiltin_paths(args: JValRef, _env: JEnvRef, state: &mut JState) -> JResult {
     let [] = get_n_args(args)?;
     for p in crate::import::JIBI_PATHS.iter() {
-        println!("{}", p.display());
+        state.println(&p.display().to_string());
     }
     Ok(state.nil())
 }
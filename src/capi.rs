@@ -0,0 +1,386 @@
+//! C ABI for embedding `jibi` (`capi` feature), see `include/jibi.h`. Build the
+//! shared library with `make capi`.
+//!
+//! All pointers passed to these functions must be valid pointers obtained from this
+//! API (or NUL-terminated strings), and must not be used after being freed. Values
+//! and interpreters are not thread-safe.
+#![allow(clippy::missing_safety_doc)]
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::ffi::{c_void, CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::rc::Rc;
+
+use crate::*;
+
+pub struct JibiInterpreter {
+    interpreter: Interpreter,
+    error: Option<CString>,
+    traceback: Option<CString>,
+}
+
+pub struct JibiValue(JValRef);
+
+pub type JibiBuiltinFn = extern "C" fn(
+    state: *mut JState,
+    args: *const JibiValue,
+    userdata: *mut c_void,
+) -> *mut JibiValue;
+
+pub const JIBI_NIL: c_int = 0;
+pub const JIBI_BOOL: c_int = 1;
+pub const JIBI_INT: c_int = 2;
+pub const JIBI_FLOAT: c_int = 3;
+pub const JIBI_STRING: c_int = 4;
+pub const JIBI_SYMBOL: c_int = 5;
+pub const JIBI_PAIR: c_int = 6;
+pub const JIBI_OTHER: c_int = 7;
+
+thread_local! {
+    // Error raised by a C builtin with jibi_raise
+    static RAISED: RefCell<Option<JError>> = const { RefCell::new(None) };
+}
+
+fn new_value(val: JValRef) -> *mut JibiValue {
+    Box::into_raw(Box::new(JibiValue(val)))
+}
+
+fn to_cstring(s: String) -> CString {
+    CString::new(s.replace('\0', "\\0")).unwrap()
+}
+
+unsafe fn to_str<'a>(s: *const c_char) -> &'a str {
+    CStr::from_ptr(s).to_str().unwrap_or("")
+}
+
+impl JibiInterpreter {
+    fn new(interpreter: Interpreter) -> *mut Self {
+        Box::into_raw(Box::new(Self {
+            interpreter,
+            error: None,
+            traceback: None,
+        }))
+    }
+    fn set_exc(&mut self, (pos, err, tb): JException) {
+        let mut lines = vec![];
+        for frame in tb.iter().rev() {
+            lines.push(format!("  {}", frame));
+        }
+        lines.push(format!("  File \"{}\", line {}", pos.filename, pos.lineno));
+        self.error = Some(to_cstring(format!("{}", err)));
+        self.traceback = Some(to_cstring(lines.join("\n")));
+    }
+    fn clear_error(&mut self) {
+        self.error = None;
+        self.traceback = None;
+    }
+    fn eval_result(&mut self, res: Result<Option<JValRef>, JException>) -> *mut JibiValue {
+        match res {
+            Ok(Some(val)) => new_value(val),
+            Ok(None) => new_value(self.interpreter.nil()),
+            Err(exc) => {
+                self.set_exc(exc);
+                ptr::null_mut()
+            }
+        }
+    }
+}
+
+/// Create an interpreter with builtins and the prelude.
+#[no_mangle]
+pub extern "C" fn jibi_new() -> *mut JibiInterpreter {
+    JibiInterpreter::new(Interpreter::default())
+}
+
+/// Create a bare interpreter, with empty globals.
+#[no_mangle]
+pub extern "C" fn jibi_new_bare() -> *mut JibiInterpreter {
+    JibiInterpreter::new(Interpreter::new())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn jibi_free(interp: *mut JibiInterpreter) {
+    if !interp.is_null() {
+        drop(Box::from_raw(interp));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn jibi_get_state(interp: *mut JibiInterpreter) -> *mut JState {
+    &mut (*interp).interpreter.state
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn jibi_eval_str(
+    interp: *mut JibiInterpreter,
+    name: *const c_char,
+    program: *const c_char,
+) -> *mut JibiValue {
+    let interp = &mut *interp;
+    interp.clear_error();
+    let res = interp.interpreter.eval_str(to_str(name), to_str(program));
+    interp.eval_result(res)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn jibi_eval_file(
+    interp: *mut JibiInterpreter,
+    path: *const c_char,
+) -> *mut JibiValue {
+    let interp = &mut *interp;
+    interp.clear_error();
+    let res = interp.interpreter.eval_file(to_str(path));
+    interp.eval_result(res)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn jibi_call(
+    interp: *mut JibiInterpreter,
+    name: *const c_char,
+    args: *const *const JibiValue,
+    nargs: usize,
+) -> *mut JibiValue {
+    let interp = &mut *interp;
+    interp.clear_error();
+    let args = (0..nargs).map(|n| Rc::clone(&(**args.add(n)).0)).collect();
+    match interp.interpreter.call(to_str(name), args) {
+        Ok(val) => new_value(val),
+        Err(err) => {
+            let tb = interp.interpreter.state.traceback_take();
+            let pos = PositionTag::new("#CAPI", 0, 0);
+            interp.set_exc((pos, err, tb));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Message of the last error, or NULL. Valid until the next call with this interpreter.
+#[no_mangle]
+pub unsafe extern "C" fn jibi_error(interp: *const JibiInterpreter) -> *const c_char {
+    match &(*interp).error {
+        Some(s) => s.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Traceback of the last error, or NULL. Valid until the next call with this interpreter.
+#[no_mangle]
+pub unsafe extern "C" fn jibi_traceback(interp: *const JibiInterpreter) -> *const c_char {
+    match &(*interp).traceback {
+        Some(s) => s.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn jibi_def(
+    interp: *mut JibiInterpreter,
+    name: *const c_char,
+    val: *const JibiValue,
+) {
+    (*interp)
+        .interpreter
+        .def(to_str(name), Rc::clone(&(*val).0));
+}
+
+/// Value bound to a global name, or NULL if it is not defined.
+#[no_mangle]
+pub unsafe extern "C" fn jibi_lookup(
+    interp: *mut JibiInterpreter,
+    name: *const c_char,
+) -> *mut JibiValue {
+    match (*interp).interpreter.lookup(to_str(name)) {
+        Some(val) => new_value(val),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Define a global builtin procedure implemented by a C callback.
+///
+/// The callback receives the list of evaluated arguments, and must return a new value,
+/// or NULL after calling `jibi_raise` to raise an error.
+#[no_mangle]
+pub unsafe extern "C" fn jibi_register(
+    interp: *mut JibiInterpreter,
+    name: *const c_char,
+    f: JibiBuiltinFn,
+    userdata: *mut c_void,
+) {
+    let name = to_str(name).to_string();
+    (*interp)
+        .interpreter
+        .builtin(name.clone(), move |args, _env, state| {
+            let args = JibiValue(args);
+            // Drop errors raised by callbacks which returned a value anyway
+            RAISED.with(|r| r.borrow_mut().take());
+            let res = f(state, &args, userdata);
+            if res.is_null() {
+                let err = RAISED.with(|r| r.borrow_mut().take());
+                Err(err
+                    .unwrap_or_else(|| JError::new(Exception, &format!("builtin {} failed", name))))
+            } else {
+                Ok(Box::from_raw(res).0)
+            }
+        });
+}
+
+/// Set the error raised when a C builtin returns NULL, see [`JErrorKind::from_name`].
+#[no_mangle]
+pub unsafe extern "C" fn jibi_raise(kind: *const c_char, reason: *const c_char) {
+    let err = JError::new(JErrorKind::from_name(to_str(kind)), to_str(reason));
+    RAISED.with(|r| *r.borrow_mut() = Some(err));
+}
+
+// Values
+
+#[no_mangle]
+pub unsafe extern "C" fn jibi_value_free(val: *mut JibiValue) {
+    if !val.is_null() {
+        drop(Box::from_raw(val));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn jibi_value_clone(val: *const JibiValue) -> *mut JibiValue {
+    new_value(Rc::clone(&(*val).0))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn jibi_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn jibi_nil(state: *mut JState) -> *mut JibiValue {
+    new_value((*state).nil())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn jibi_bool(state: *mut JState, b: c_int) -> *mut JibiValue {
+    new_value((*state).bool(b != 0))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn jibi_int(state: *mut JState, n: i64) -> *mut JibiValue {
+    new_value((*state).int(n.into()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn jibi_float(state: *mut JState, x: f64) -> *mut JibiValue {
+    new_value((*state).float(x))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn jibi_string(state: *mut JState, s: *const c_char) -> *mut JibiValue {
+    new_value((*state).string(to_str(s).to_string()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn jibi_symbol(state: *mut JState, s: *const c_char) -> *mut JibiValue {
+    new_value((*state).symbol(to_str(s).to_string()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn jibi_pair(
+    state: *mut JState,
+    car: *const JibiValue,
+    cdr: *const JibiValue,
+) -> *mut JibiValue {
+    new_value((*state).pair(Rc::clone(&(*car).0), Rc::clone(&(*cdr).0)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn jibi_list(
+    state: *mut JState,
+    items: *const *const JibiValue,
+    len: usize,
+) -> *mut JibiValue {
+    let items = (0..len).map(|n| Rc::clone(&(**items.add(n)).0)).collect();
+    new_value((*state).list(items))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn jibi_type(val: *const JibiValue) -> c_int {
+    match &*(*val).0 {
+        JVal::Nil => JIBI_NIL,
+        JVal::Bool(_) => JIBI_BOOL,
+        JVal::Int(_) => JIBI_INT,
+        JVal::Float(_) => JIBI_FLOAT,
+        JVal::String(_) => JIBI_STRING,
+        JVal::Symbol(_) => JIBI_SYMBOL,
+        JVal::Pair(_) => JIBI_PAIR,
+        _ => JIBI_OTHER,
+    }
+}
+
+/// Returns 0 on success, -1 if the value is not a bool.
+#[no_mangle]
+pub unsafe extern "C" fn jibi_to_bool(val: *const JibiValue, out: *mut c_int) -> c_int {
+    match (*val).0.to_bool() {
+        Ok(b) => {
+            *out = b as c_int;
+            0
+        }
+        Err(_) => -1,
+    }
+}
+
+/// Returns 0 on success, -1 if the value is not an int or does not fit in 64 bits.
+#[no_mangle]
+pub unsafe extern "C" fn jibi_to_int(val: *const JibiValue, out: *mut i64) -> c_int {
+    match (*val).0.to_int().map(i64::try_from) {
+        Ok(Ok(n)) => {
+            *out = n;
+            0
+        }
+        _ => -1,
+    }
+}
+
+/// Returns 0 on success, -1 if the value is not a float.
+#[no_mangle]
+pub unsafe extern "C" fn jibi_to_float(val: *const JibiValue, out: *mut f64) -> c_int {
+    match (*val).0.to_float() {
+        Ok(x) => {
+            *out = x;
+            0
+        }
+        Err(_) => -1,
+    }
+}
+
+/// Contents of a string or symbol (free with `jibi_string_free`), or NULL.
+#[no_mangle]
+pub unsafe extern "C" fn jibi_to_string(val: *const JibiValue) -> *mut c_char {
+    match &*(*val).0 {
+        JVal::String(s) | JVal::Symbol(s) => to_cstring(s.clone()).into_raw(),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// `repr` of a value (free with `jibi_string_free`).
+#[no_mangle]
+pub unsafe extern "C" fn jibi_repr(val: *const JibiValue) -> *mut c_char {
+    to_cstring(repr(&(*val).0)).into_raw()
+}
+
+/// First element of a pair, or NULL.
+#[no_mangle]
+pub unsafe extern "C" fn jibi_car(val: *const JibiValue) -> *mut JibiValue {
+    match (*val).0.to_pair() {
+        Ok(p) => new_value(p.car()),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Second element of a pair, or NULL.
+#[no_mangle]
+pub unsafe extern "C" fn jibi_cdr(val: *const JibiValue) -> *mut JibiValue {
+    match (*val).0.to_pair() {
+        Ok(p) => new_value(p.cdr()),
+        Err(_) => ptr::null_mut(),
+    }
+}
@@ -0,0 +1,275 @@
+//! Typed conversions between Rust and `jibi` values, and records.
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+use crate::builtin::get_n_args;
+use crate::*;
+
+/// Conversion of a Rust value to a `jibi` value.
+pub trait ToJVal {
+    fn to_jval(&self, state: &mut JState) -> JResult;
+}
+
+/// Conversion of a `jibi` value to a Rust value.
+pub trait FromJVal: Sized {
+    fn from_jval(val: JValRef) -> Result<Self, JError>;
+}
+
+/// A Rust type represented as a `jibi` record, usually implemented with
+/// `#[derive(JibiValue)]` (`derive` feature).
+///
+/// Records are lists tagged by a type symbol: `(type/Point 1 2)`.
+pub trait JibiRecord: ToJVal + FromJVal {
+    /// Define the record constructor, predicate and field accessors as builtins.
+    /// Fails without defining anything if one of their names is already bound.
+    fn register(interpreter: &mut Interpreter) -> Result<(), JError>;
+}
+
+impl ToJVal for JValRef {
+    fn to_jval(&self, _state: &mut JState) -> JResult {
+        Ok(Rc::clone(self))
+    }
+}
+
+impl FromJVal for JValRef {
+    fn from_jval(val: JValRef) -> Result<Self, JError> {
+        Ok(val)
+    }
+}
+
+impl ToJVal for bool {
+    fn to_jval(&self, state: &mut JState) -> JResult {
+        Ok(state.bool(*self))
+    }
+}
+
+impl FromJVal for bool {
+    fn from_jval(val: JValRef) -> Result<Self, JError> {
+        val.to_bool()
+    }
+}
+
+macro_rules! int_conversions {
+    ( $( $t:ty ),* ) => {
+        $(
+            impl ToJVal for $t {
+                fn to_jval(&self, state: &mut JState) -> JResult {
+                    let n = JTInt::try_from(*self)
+                        .map_err(|_| JError::new(Other("IntError".to_string()), "overflow"))?;
+                    Ok(state.int(n))
+                }
+            }
+
+            impl FromJVal for $t {
+                fn from_jval(val: JValRef) -> Result<Self, JError> {
+                    <$t>::try_from(val.to_int()?)
+                        .map_err(|_| JError::new(Other("IntError".to_string()), "overflow"))
+                }
+            }
+        )*
+    };
+}
+
+int_conversions!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+impl ToJVal for f64 {
+    fn to_jval(&self, state: &mut JState) -> JResult {
+        Ok(state.float(*self))
+    }
+}
+
+impl FromJVal for f64 {
+    fn from_jval(val: JValRef) -> Result<Self, JError> {
+        match &*val {
+            JVal::Int(n) => Ok(*n as JTFloat),
+            _ => val.to_float(),
+        }
+    }
+}
+
+impl ToJVal for f32 {
+    fn to_jval(&self, state: &mut JState) -> JResult {
+        Ok(state.float((*self).into()))
+    }
+}
+
+impl FromJVal for f32 {
+    fn from_jval(val: JValRef) -> Result<Self, JError> {
+        Ok(f64::from_jval(val)? as f32)
+    }
+}
+
+impl ToJVal for String {
+    fn to_jval(&self, state: &mut JState) -> JResult {
+        Ok(state.string(self.clone()))
+    }
+}
+
+impl ToJVal for &str {
+    fn to_jval(&self, state: &mut JState) -> JResult {
+        Ok(state.string(self.to_string()))
+    }
+}
+
+impl FromJVal for String {
+    fn from_jval(val: JValRef) -> Result<Self, JError> {
+        Ok(val.to_str()?.to_string())
+    }
+}
+
+impl<T: ToJVal> ToJVal for Option<T> {
+    fn to_jval(&self, state: &mut JState) -> JResult {
+        match self {
+            Some(v) => v.to_jval(state),
+            None => Ok(state.nil()),
+        }
+    }
+}
+
+impl<T: FromJVal> FromJVal for Option<T> {
+    fn from_jval(val: JValRef) -> Result<Self, JError> {
+        match &*val {
+            JVal::Nil => Ok(None),
+            _ => Ok(Some(T::from_jval(val)?)),
+        }
+    }
+}
+
+impl<T: ToJVal> ToJVal for Vec<T> {
+    fn to_jval(&self, state: &mut JState) -> JResult {
+        let vals = self
+            .iter()
+            .map(|v| v.to_jval(state))
+            .collect::<Result<Vec<JValRef>, JError>>()?;
+        Ok(state.list(vals))
+    }
+}
+
+impl<T: FromJVal> FromJVal for Vec<T> {
+    fn from_jval(val: JValRef) -> Result<Self, JError> {
+        match &*val {
+            JVal::Vector(v) => v
+                .borrow()
+                .iter()
+                .map(|v| T::from_jval(Rc::clone(v)))
+                .collect(),
+            _ => val.iter_list()?.map(T::from_jval).collect(),
+        }
+    }
+}
+
+/// Construct a record: a list of field values tagged by a type symbol.
+pub fn make_record(tag: &str, fields: Vec<JValRef>, state: &mut JState) -> JValRef {
+    let tag = state.symbol(tag.to_string());
+    let record = state.list(fields);
+    state.pair(tag, record)
+}
+
+/// Get the field values of a record, checking its tag and number of fields.
+pub fn record_fields(val: &JVal, tag: &str, nfields: usize) -> Result<Vec<JValRef>, JError> {
+    let record = val.to_pair().map_err(|_| expected_record(tag))?;
+    if record.car().to_symbol().ok() != Some(tag) {
+        return Err(expected_record(tag));
+    }
+    let fields: Vec<JValRef> = record.cdr().iter_list()?.collect();
+    if fields.len() != nfields {
+        return Err(JError::new(
+            TypeError,
+            &format!("expected {} field(s) in {}", nfields, tag),
+        ));
+    }
+    Ok(fields)
+}
+
+/// Get the tag of a record, if `val` is a record.
+pub fn record_tag(val: &JVal) -> Option<String> {
+    match val {
+        JVal::Pair(p) => match &*p.car() {
+            JVal::Symbol(s) if s.starts_with("type/") => Some(s.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn expected_record(tag: &str) -> JError {
+    JError::new(TypeError, &format!("expected a {} record", tag))
+}
+
+/// Names of the builtins defined by [`register_record`].
+pub fn record_names(prefix: &str, fields: &[&str]) -> Vec<String> {
+    let mut names = vec![format!("make-{}", prefix), format!("{}?", prefix)];
+    names.extend(fields.iter().map(|field| format!("{}-{}", prefix, field)));
+    names
+}
+
+/// Check that none of `names` is bound in the globals of `interpreter`.
+pub fn check_unbound<S: AsRef<str>>(interpreter: &Interpreter, names: &[S]) -> Result<(), JError> {
+    match names
+        .iter()
+        .find(|name| interpreter.lookup(name.as_ref()).is_some())
+    {
+        Some(name) => Err(JError::new(
+            EvalError,
+            &format!("cannot register {}, it is already defined", name.as_ref()),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Define builtins for a record type, unless one of their names is already bound:
+///
+/// * `(make-<prefix> field ...)`: constructor, `check` validates the new record
+/// * `(<prefix>? val)`: predicate
+/// * `(<prefix>-<field> record)`: accessor for each field
+pub fn register_record(
+    interpreter: &mut Interpreter,
+    tag: &'static str,
+    prefix: &str,
+    fields: &[&str],
+    check: fn(JValRef) -> Result<(), JError>,
+) -> Result<(), JError> {
+    check_unbound(interpreter, &record_names(prefix, fields))?;
+    let nfields = fields.len();
+    interpreter.builtin(format!("make-{}", prefix), move |args, _env, state| {
+        let record = make_record(tag, args.iter_list()?.collect(), state);
+        check(Rc::clone(&record))?;
+        Ok(record)
+    });
+    interpreter.builtin(format!("{}?", prefix), move |args, _env, state| {
+        let [val] = get_n_args(args)?;
+        Ok(state.bool(record_fields(&val, tag, nfields).is_ok()))
+    });
+    for (n, field) in fields.iter().enumerate() {
+        interpreter.builtin(
+            format!("{}-{}", prefix, field),
+            move |args, _env, _state| {
+                let [val] = get_n_args(args)?;
+                Ok(Rc::clone(&record_fields(&val, tag, nfields)?[n]))
+            },
+        );
+    }
+    Ok(())
+}
+
+/// Define a predicate `(<prefix>? val)` true for records with tags starting
+/// with `tag_prefix` (e.g. all variants of an enum), unless it is already bound.
+pub fn register_record_family(
+    interpreter: &mut Interpreter,
+    tag_prefix: &'static str,
+    prefix: &str,
+) -> Result<(), JError> {
+    check_unbound(interpreter, &[format!("{}?", prefix)])?;
+    interpreter.builtin(format!("{}?", prefix), move |args, _env, state| {
+        let [val] = get_n_args(args)?;
+        let is_member = match &*val {
+            JVal::Pair(p) => match &*p.car() {
+                JVal::Symbol(s) => s.starts_with(tag_prefix),
+                _ => false,
+            },
+            _ => false,
+        };
+        Ok(state.bool(is_member))
+    });
+    Ok(())
+}
@@ -0,0 +1,191 @@
+//! Line coverage.
+//!
+//! A [`Coverage`] attached with [`Interpreter::set_coverage`] records the list
+//! forms read while it is attached, and counts how many times each of them is
+//! evaluated. A line is covered when a form starting on it was evaluated. Lists
+//! which are not evaluated as forms (`cond` clauses, `let` bindings, parameter
+//! lists and quoted data) are not counted, so lines holding only those are not
+//! reported.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::rc::Rc;
+
+use crate::types::identity::IdentityMap;
+use crate::*;
+
+struct Form {
+    filename: String,
+    line: usize,
+    hits: u64,
+}
+
+#[derive(Default)]
+pub struct Coverage {
+    // Forms read while recording, kept after they are dropped for the report
+    forms: IdentityMap<Form>,
+}
+
+/// Coverage of a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileCoverage {
+    pub filename: String,
+    /// Number of evaluations by line, for lines where forms start.
+    pub lines: BTreeMap<usize, u64>,
+}
+
+impl FileCoverage {
+    pub fn total(&self) -> usize {
+        self.lines.len()
+    }
+    pub fn covered(&self) -> usize {
+        self.lines.values().filter(|hits| **hits > 0).count()
+    }
+    pub fn percent(&self) -> f64 {
+        percent(self.covered(), self.total())
+    }
+}
+
+fn percent(covered: usize, total: usize) -> f64 {
+    if total == 0 {
+        100.0
+    } else {
+        covered as f64 * 100.0 / total as f64
+    }
+}
+
+/// Lists of a form which are not evaluated as forms.
+fn data_lists(form: &JVal) -> Vec<JValRef> {
+    let items: Vec<JValRef> = match form.iter_list() {
+        Ok(items) => items.collect(),
+        Err(_) => return vec![],
+    };
+    let head = match items.first().map(|head| head.to_symbol()) {
+        Some(Ok(head)) => head,
+        _ => return vec![],
+    };
+    match head {
+        "cond" => items[1..].to_vec(),
+        "let" => match items.get(1) {
+            Some(bindings) => {
+                let mut lists = vec![Rc::clone(bindings)];
+                if let Ok(bindings) = bindings.iter_list() {
+                    lists.extend(bindings);
+                }
+                lists
+            }
+            None => vec![],
+        },
+        "fn" | "macro" => items.get(1).cloned().into_iter().collect(),
+        "nfn" | "nmacro" | "defn" | "defnglobal" | "defmacro" => {
+            items.get(2).cloned().into_iter().collect()
+        }
+        _ => vec![],
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn record(&mut self, form: &JValRef, pos: &PositionTag) {
+        for list in data_lists(form) {
+            self.forms.remove(&list);
+        }
+        self.forms.insert(
+            form,
+            Form {
+                filename: pos.filename.clone(),
+                line: pos.lineno,
+                hits: 0,
+            },
+        );
+    }
+
+    /// Coverage by file, sorted by filename.
+    pub fn files(&self) -> Vec<FileCoverage> {
+        let mut files: BTreeMap<&str, BTreeMap<usize, u64>> = BTreeMap::new();
+        for form in self.forms.values() {
+            let hits = files
+                .entry(&form.filename)
+                .or_default()
+                .entry(form.line)
+                .or_default();
+            *hits = (*hits).max(form.hits);
+        }
+        files
+            .into_iter()
+            .map(|(filename, lines)| FileCoverage {
+                filename: filename.to_string(),
+                lines,
+            })
+            .collect()
+    }
+
+    /// Percentage of covered lines in all files.
+    pub fn percent(&self) -> f64 {
+        let files = self.files();
+        percent(
+            files.iter().map(|f| f.covered()).sum(),
+            files.iter().map(|f| f.total()).sum(),
+        )
+    }
+
+    /// Table of covered lines by file.
+    pub fn report(&self) -> String {
+        let mut report = format!("{:>8} {:>8} {:>7}  file\n", "lines", "covered", "%");
+        let (mut total, mut covered) = (0, 0);
+        for file in self.files() {
+            let _ = writeln!(
+                report,
+                "{:>8} {:>8} {:>7.1}  {}",
+                file.total(),
+                file.covered(),
+                file.percent(),
+                file.filename
+            );
+            total += file.total();
+            covered += file.covered();
+        }
+        let _ = writeln!(
+            report,
+            "{:>8} {:>8} {:>7.1}  total",
+            total,
+            covered,
+            percent(covered, total)
+        );
+        report
+    }
+
+    /// Coverage in the lcov tracefile format, read by `genhtml` and coverage
+    /// services.
+    pub fn lcov(&self) -> String {
+        let mut lcov = String::new();
+        for file in self.files() {
+            let _ = writeln!(lcov, "SF:{}", file.filename);
+            for (line, hits) in &file.lines {
+                let _ = writeln!(lcov, "DA:{},{}", line, hits);
+            }
+            let _ = writeln!(lcov, "LF:{}", file.total());
+            let _ = writeln!(lcov, "LH:{}", file.covered());
+            let _ = writeln!(lcov, "end_of_record");
+        }
+        lcov
+    }
+}
+
+/// Called by the parser for lists read outside of quoted data.
+pub(crate) fn record(state: &mut JState, form: &JValRef, pos: &PositionTag) {
+    if let Some(coverage) = state.coverage_mut() {
+        coverage.record(form, pos);
+    }
+}
+
+/// Called before evaluating a list form.
+pub(crate) fn hit(form: &JVal, state: &mut JState) {
+    if let Some(coverage) = state.coverage_mut() {
+        if let Some(form) = coverage.forms.get_mut(form) {
+            form.hits += 1;
+        }
+    }
+}
@@ -0,0 +1,470 @@
+//! Debug adapter for `jibi` programs, speaking the
+//! [debug adapter protocol](https://microsoft.github.io/debug-adapter-protocol/)
+//! over stdio, on top of the [`debugger`].
+//!
+//! Supports launching a program, line breakpoints, stepping, the call stack,
+//! variables of each frame's scopes and evaluating expressions in a frame.
+//! Program output is sent as `output` events.
+use std::cell::RefCell;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+use crate::debugger::{
+    Breakpoint, DebugFrame, DebugFrontend, DebugSession, Debugger, PauseReason, Resume,
+};
+use crate::protocol::{read_message, write_message, Json};
+use crate::*;
+
+const THREAD_ID: usize = 1;
+
+struct Transport {
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    seq: usize,
+    // Set by requests to stop the program (`terminate`) or the session (`disconnect`)
+    quit: bool,
+    disconnected: bool,
+}
+
+type TransportRef = Rc<RefCell<Transport>>;
+
+impl Transport {
+    /// Read the next message, skipping invalid ones, `None` at the end of the input.
+    fn read(&mut self) -> io::Result<Option<Json>> {
+        while let Some(body) = read_message(&mut self.input)? {
+            if let Ok(message) = Json::parse(&body) {
+                return Ok(Some(message));
+            }
+        }
+        Ok(None)
+    }
+    fn send(&mut self, mut members: Vec<(&str, Json)>) -> io::Result<()> {
+        self.seq += 1;
+        members.insert(0, ("seq", self.seq.into()));
+        write_message(&mut self.output, &Json::object(members))
+    }
+    fn respond(&mut self, request: &Json, body: Result<Json, String>) -> io::Result<()> {
+        let mut members = vec![
+            ("type", "response".into()),
+            ("request_seq", request["seq"].clone()),
+            ("command", request["command"].clone()),
+        ];
+        match body {
+            Ok(body) => {
+                members.push(("success", true.into()));
+                if !body.is_null() {
+                    members.push(("body", body));
+                }
+            }
+            Err(message) => {
+                members.push(("success", false.into()));
+                members.push(("message", message.into()));
+            }
+        }
+        self.send(members)
+    }
+    fn event(&mut self, event: &str, body: Json) -> io::Result<()> {
+        let mut members = vec![("type", "event".into()), ("event", event.into())];
+        if !body.is_null() {
+            members.push(("body", body));
+        }
+        self.send(members)
+    }
+}
+
+/// Sends program output as `output` events.
+struct OutputEvents(TransportRef);
+
+impl Write for OutputEvents {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let output = String::from_utf8_lossy(buf).to_string();
+        self.0.borrow_mut().event(
+            "output",
+            Json::object(vec![
+                ("category", "stdout".into()),
+                ("output", output.into()),
+            ]),
+        )?;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn source_breakpoints(arguments: &Json) -> (String, Vec<Breakpoint>) {
+    let path = arguments["source"]["path"]
+        .as_str()
+        .unwrap_or("")
+        .to_string();
+    let breakpoints = arguments["breakpoints"]
+        .as_array()
+        .unwrap_or(&[])
+        .iter()
+        .filter_map(|b| b["line"].as_u64())
+        .map(|line| Breakpoint::new(&path, line as usize))
+        .collect();
+    (path, breakpoints)
+}
+
+fn set_breakpoints_body(breakpoints: &[Breakpoint]) -> Json {
+    let breakpoints = breakpoints
+        .iter()
+        .map(|b| Json::object(vec![("verified", true.into()), ("line", b.line.into())]))
+        .collect::<Vec<Json>>();
+    Json::object(vec![("breakpoints", breakpoints.into())])
+}
+
+/// Answers requests while the program is paused.
+struct Frontend {
+    transport: TransportRef,
+    entry: bool,
+    // Variables of each reference (from 1), valid until the program resumes
+    references: Vec<Vec<(String, JValRef)>>,
+}
+
+impl Frontend {
+    fn reference(&mut self, variables: Vec<(String, JValRef)>) -> usize {
+        self.references.push(variables);
+        self.references.len()
+    }
+
+    /// Reference to the items of lists and vectors, 0 for other values.
+    fn value_reference(&mut self, val: &JValRef) -> usize {
+        let items: Vec<JValRef> = match &**val {
+            JVal::Pair(_) => match val.iter_list() {
+                Ok(items) => items.collect(),
+                Err(_) => return 0,
+            },
+            JVal::Vector(v) => v.borrow().clone(),
+            _ => return 0,
+        };
+        let variables = items
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| (format!("[{}]", i), v))
+            .collect();
+        self.reference(variables)
+    }
+
+    fn frame<'a>(session: &'a DebugSession, arguments: &Json) -> Result<&'a DebugFrame, String> {
+        let frames = session.frames();
+        let id = arguments["frameId"].as_u64().unwrap_or(0) as usize;
+        frames
+            .len()
+            .checked_sub(id + 1)
+            .map(|i| &frames[i])
+            .ok_or_else(|| format!("invalid frame {}", id))
+    }
+
+    fn stack_trace(session: &DebugSession) -> Json {
+        let frames = session
+            .frames()
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(id, frame)| {
+                let mut members = vec![("id", id.into()), ("name", frame.name.clone().into())];
+                match &frame.pos {
+                    Some(pos) => {
+                        let name = std::path::Path::new(&pos.filename)
+                            .file_name()
+                            .map_or(pos.filename.clone(), |n| n.to_string_lossy().to_string());
+                        members.push((
+                            "source",
+                            Json::object(vec![
+                                ("name", name.into()),
+                                ("path", pos.filename.clone().into()),
+                            ]),
+                        ));
+                        members.push(("line", pos.lineno.into()));
+                        members.push(("column", pos.col.into()));
+                    }
+                    None => {
+                        members.push(("line", 0.into()));
+                        members.push(("column", 0.into()));
+                    }
+                }
+                Json::object(members)
+            })
+            .collect::<Vec<Json>>();
+        Json::object(vec![
+            ("totalFrames", frames.len().into()),
+            ("stackFrames", frames.into()),
+        ])
+    }
+
+    fn scopes(&mut self, session: &DebugSession, arguments: &Json) -> Result<Json, String> {
+        let frame = Self::frame(session, arguments)?;
+        let mut scopes = vec![];
+        for (i, variables) in frame.scopes().into_iter().enumerate() {
+            let name = if i == 0 {
+                "Locals".to_string()
+            } else {
+                format!("Enclosing {}", i)
+            };
+            scopes.push((name, self.reference(variables), false));
+        }
+        let mut globals = Rc::clone(&frame.env);
+        while let Some(parent) = globals.parent.clone() {
+            globals = parent;
+        }
+        scopes.push((
+            "Globals".to_string(),
+            self.reference(globals.bindings()),
+            true,
+        ));
+        let scopes = scopes
+            .into_iter()
+            .map(|(name, reference, expensive)| {
+                Json::object(vec![
+                    ("name", name.into()),
+                    ("variablesReference", reference.into()),
+                    ("expensive", expensive.into()),
+                ])
+            })
+            .collect::<Vec<Json>>();
+        Ok(Json::object(vec![("scopes", scopes.into())]))
+    }
+
+    fn variables(&mut self, arguments: &Json) -> Result<Json, String> {
+        let reference = arguments["variablesReference"].as_u64().unwrap_or(0) as usize;
+        let variables = match reference
+            .checked_sub(1)
+            .and_then(|i| self.references.get(i))
+        {
+            Some(variables) => variables.clone(),
+            None => return Err(format!("invalid variables reference {}", reference)),
+        };
+        let variables = variables
+            .into_iter()
+            .map(|(name, val)| {
+                Json::object(vec![
+                    ("name", name.into()),
+                    ("value", val.to_string().into()),
+                    ("variablesReference", self.value_reference(&val).into()),
+                ])
+            })
+            .collect::<Vec<Json>>();
+        Ok(Json::object(vec![("variables", variables.into())]))
+    }
+
+    fn evaluate(&mut self, session: &mut DebugSession, arguments: &Json) -> Result<Json, String> {
+        let env = Rc::clone(&Self::frame(session, arguments)?.env);
+        let expression = arguments["expression"].as_str().unwrap_or("");
+        match session.eval_str(expression, &env) {
+            Ok(val) => {
+                let val = val.unwrap_or_else(|| JVal::Nil.into_ref());
+                Ok(Json::object(vec![
+                    ("result", val.to_string().into()),
+                    ("variablesReference", self.value_reference(&val).into()),
+                ]))
+            }
+            Err((_, err, _)) => Err(err.to_string()),
+        }
+    }
+
+    fn paused(&mut self, session: &mut DebugSession) -> io::Result<Resume> {
+        let reason = match session.reason() {
+            _ if self.entry => "entry",
+            PauseReason::Step => "step",
+            PauseReason::Breakpoint => "breakpoint",
+        };
+        self.entry = false;
+        self.references.clear();
+        self.transport.borrow_mut().event(
+            "stopped",
+            Json::object(vec![
+                ("reason", reason.into()),
+                ("threadId", THREAD_ID.into()),
+                ("allThreadsStopped", true.into()),
+            ]),
+        )?;
+        loop {
+            let request = match self.transport.borrow_mut().read()? {
+                Some(request) => request,
+                None => return Ok(Resume::Quit),
+            };
+            let arguments = &request["arguments"];
+            let (body, resume) = match request["command"].as_str().unwrap_or("") {
+                "continue" => (
+                    Ok(Json::object(vec![("allThreadsContinued", true.into())])),
+                    Some(Resume::Continue),
+                ),
+                "next" => (Ok(Json::Null), Some(Resume::StepOver)),
+                "stepIn" => (Ok(Json::Null), Some(Resume::StepInto)),
+                "stepOut" => (Ok(Json::Null), Some(Resume::StepOut)),
+                command @ ("disconnect" | "terminate") => {
+                    let mut transport = self.transport.borrow_mut();
+                    transport.quit = true;
+                    transport.disconnected = command == "disconnect";
+                    (Ok(Json::Null), Some(Resume::Quit))
+                }
+                "threads" => (Ok(threads()), None),
+                "stackTrace" => (Ok(Self::stack_trace(session)), None),
+                "scopes" => (self.scopes(session, arguments), None),
+                "variables" => (self.variables(arguments), None),
+                "evaluate" => (self.evaluate(session, arguments), None),
+                "setBreakpoints" => {
+                    let (path, new) = source_breakpoints(arguments);
+                    let breakpoints = session.breakpoints_mut();
+                    breakpoints.retain(|b| b.file.as_os_str() != path.as_str());
+                    breakpoints.extend(new.iter().cloned());
+                    (Ok(set_breakpoints_body(&new)), None)
+                }
+                command => (Err(format!("unsupported request {}", command)), None),
+            };
+            self.transport.borrow_mut().respond(&request, body)?;
+            if let Some(resume) = resume {
+                return Ok(resume);
+            }
+        }
+    }
+}
+
+impl DebugFrontend for Frontend {
+    fn paused(&mut self, session: &mut DebugSession) -> Resume {
+        self.paused(session).unwrap_or(Resume::Quit)
+    }
+}
+
+fn threads() -> Json {
+    let thread = Json::object(vec![("id", THREAD_ID.into()), ("name", "main".into())]);
+    Json::object(vec![("threads", vec![thread].into())])
+}
+
+/// Debug adapter state, see the [module docs](self).
+pub struct DebugAdapter {
+    interpreter: Option<Interpreter>,
+    program: Option<String>,
+    stop_on_entry: bool,
+    no_debug: bool,
+    breakpoints: Vec<Breakpoint>,
+}
+
+impl Default for DebugAdapter {
+    fn default() -> Self {
+        Self::new(Interpreter::default())
+    }
+}
+
+impl DebugAdapter {
+    /// Run the launched program with `interpreter`.
+    pub fn new(interpreter: Interpreter) -> Self {
+        Self {
+            interpreter: Some(interpreter),
+            program: None,
+            stop_on_entry: false,
+            no_debug: false,
+            breakpoints: vec![],
+        }
+    }
+
+    /// Serve requests until the `disconnect` request or the end of the input.
+    pub fn run<R, W>(&mut self, input: R, output: W) -> io::Result<()>
+    where
+        R: BufRead + 'static,
+        W: Write + 'static,
+    {
+        let transport = Rc::new(RefCell::new(Transport {
+            input: Box::new(input),
+            output: Box::new(output),
+            seq: 0,
+            quit: false,
+            disconnected: false,
+        }));
+        loop {
+            let request = match transport.borrow_mut().read()? {
+                Some(request) => request,
+                None => return Ok(()),
+            };
+            let arguments = &request["arguments"];
+            let body = match request["command"].as_str().unwrap_or("") {
+                "initialize" => Ok(Json::object(vec![
+                    ("supportsConfigurationDoneRequest", true.into()),
+                    ("supportsEvaluateForHovers", true.into()),
+                    ("supportsTerminateRequest", true.into()),
+                ])),
+                "launch" => match arguments["program"].as_str() {
+                    Some(program) => {
+                        self.program = Some(program.to_string());
+                        self.stop_on_entry = arguments["stopOnEntry"] == true.into();
+                        self.no_debug = arguments["noDebug"] == true.into();
+                        Ok(Json::Null)
+                    }
+                    None => Err("missing program to launch".to_string()),
+                },
+                "setBreakpoints" => {
+                    let (path, new) = source_breakpoints(arguments);
+                    self.breakpoints
+                        .retain(|b| b.file.as_os_str() != path.as_str());
+                    self.breakpoints.extend(new.iter().cloned());
+                    Ok(set_breakpoints_body(&new))
+                }
+                "setExceptionBreakpoints" => Ok(Json::Null),
+                "threads" => Ok(threads()),
+                "configurationDone" => Ok(Json::Null),
+                "disconnect" => {
+                    transport.borrow_mut().respond(&request, Ok(Json::Null))?;
+                    return Ok(());
+                }
+                "terminate" => Ok(Json::Null),
+                command => Err(format!("unsupported request {}", command)),
+            };
+            transport.borrow_mut().respond(&request, body)?;
+            match request["command"].as_str() {
+                Some("initialize") => transport.borrow_mut().event("initialized", Json::Null)?,
+                Some("configurationDone") => {
+                    self.launch(&transport)?;
+                    if transport.borrow().disconnected {
+                        return Ok(());
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn launch(&mut self, transport: &TransportRef) -> io::Result<()> {
+        let (program, mut interpreter) = match (&self.program, self.interpreter.take()) {
+            (Some(program), Some(interpreter)) => (program.clone(), interpreter),
+            _ => return transport.borrow_mut().event("terminated", Json::Null),
+        };
+        interpreter.set_output(Some(Box::new(OutputEvents(Rc::clone(transport)))));
+        if !self.no_debug {
+            let mut debugger = Debugger::new(Frontend {
+                transport: Rc::clone(transport),
+                entry: self.stop_on_entry,
+                references: vec![],
+            })
+            .stop_on_entry(self.stop_on_entry);
+            for breakpoint in self.breakpoints.drain(..) {
+                debugger = debugger.with_breakpoint(breakpoint);
+            }
+            interpreter.set_debugger(Some(debugger));
+        }
+        let exit_code: usize = match interpreter.eval_file(&program) {
+            Ok(_) => 0,
+            Err(exc) => {
+                if !transport.borrow().quit {
+                    transport.borrow_mut().event(
+                        "output",
+                        Json::object(vec![
+                            ("category", "stderr".into()),
+                            ("output", Interpreter::format_exc(&exc).into()),
+                        ]),
+                    )?;
+                }
+                1
+            }
+        };
+        interpreter.set_debugger(None);
+        interpreter.set_output(None);
+        let mut transport = transport.borrow_mut();
+        if transport.disconnected {
+            return Ok(());
+        }
+        transport.event("exited", Json::object(vec![("exitCode", exit_code.into())]))?;
+        transport.event("terminated", Json::Null)
+    }
+}
@@ -0,0 +1,461 @@
+//! Step debugger.
+//!
+//! A [`Debugger`] attached with [`Interpreter::set_debugger`] pauses evaluation
+//! before list forms, at line [`Breakpoint`]s and at calls to the `breakpoint`
+//! builtin, and hands a [`DebugSession`] to its [`DebugFrontend`] which decides
+//! how to resume. [`DebugConsole`] is a line-oriented frontend, used by
+//! `jibi --debug`.
+//!
+//! Only forms read while the debugger is attached have source positions; other
+//! forms (e.g. in the prelude) are evaluated without pausing.
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::types::identity::IdentityMap;
+use crate::*;
+
+/// Why evaluation paused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseReason {
+    Step,
+    Breakpoint,
+}
+
+impl std::fmt::Display for PauseReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Step => write!(f, "step"),
+            Self::Breakpoint => write!(f, "breakpoint"),
+        }
+    }
+}
+
+/// How to resume evaluation after a pause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// Run until the next breakpoint.
+    Continue,
+    /// Pause at the next form, including forms in the body of called procedures.
+    StepInto,
+    /// Pause at the next form after the current form has been evaluated.
+    StepOver,
+    /// Pause at the next form after the current procedure returns.
+    StepOut,
+    /// Detach the debugger and abort evaluation with an error.
+    Quit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Continue,
+    StepInto,
+    StepOver(usize),
+    StepOut(usize),
+}
+
+/// Pause before the first form evaluated on a line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub file: PathBuf,
+    pub line: usize,
+}
+
+impl Breakpoint {
+    pub fn new<P: AsRef<Path>>(file: P, line: usize) -> Self {
+        Self {
+            file: file.as_ref().to_path_buf(),
+            line,
+        }
+    }
+    /// Files match if one path is a suffix of the other, so that relative and
+    /// absolute paths to the same file match.
+    pub fn matches(&self, pos: &PositionTag) -> bool {
+        let file = Path::new(&pos.filename);
+        pos.lineno == self.line && (file.ends_with(&self.file) || self.file.ends_with(file))
+    }
+}
+
+/// A named procedure being evaluated, or the top level.
+#[derive(Debug, Clone)]
+pub struct DebugFrame {
+    pub name: String,
+    /// Environment of the current form.
+    pub env: JEnvRef,
+    /// Form being evaluated.
+    pub form: Option<JValRef>,
+    pub pos: Option<PositionTag>,
+    // Number of forms being evaluated when the frame was entered
+    base: usize,
+}
+
+impl DebugFrame {
+    /// Bindings of each scope of the frame environment, innermost first,
+    /// excluding the global environment.
+    pub fn scopes(&self) -> Vec<Vec<(String, JValRef)>> {
+        let mut scopes = vec![];
+        let mut env = Rc::clone(&self.env);
+        while let Some(parent) = env.parent.clone() {
+            scopes.push(env.bindings());
+            env = parent;
+        }
+        scopes
+    }
+}
+
+/// Receives control when evaluation pauses.
+pub trait DebugFrontend {
+    fn paused(&mut self, session: &mut DebugSession) -> Resume;
+}
+
+/// Debugger state, see the [module docs](self).
+pub struct Debugger {
+    frontend: Option<Box<dyn DebugFrontend>>,
+    mode: Mode,
+    breakpoints: Vec<Breakpoint>,
+    frames: Vec<DebugFrame>,
+    // List forms being evaluated, innermost last
+    forms: Vec<(JValRef, Option<PositionTag>, JEnvRef)>,
+    last_line: Option<(usize, String, usize)>,
+    // Positions of forms read while debugging
+    positions: IdentityMap<PositionTag>,
+}
+
+impl Debugger {
+    pub fn new<F: DebugFrontend + 'static>(frontend: F) -> Self {
+        Self {
+            frontend: Some(Box::new(frontend)),
+            mode: Mode::Continue,
+            breakpoints: vec![],
+            frames: vec![],
+            forms: vec![],
+            last_line: None,
+            positions: IdentityMap::default(),
+        }
+    }
+    /// Pause before the first form, instead of running until a breakpoint.
+    pub fn stop_on_entry(mut self, stop: bool) -> Self {
+        self.mode = if stop { Mode::StepInto } else { Mode::Continue };
+        self
+    }
+    pub fn with_breakpoint(mut self, breakpoint: Breakpoint) -> Self {
+        self.breakpoints.push(breakpoint);
+        self
+    }
+
+    /// Point the innermost frame at the innermost form it is evaluating.
+    fn update_frame(&mut self) {
+        if let (Some(frame), Some((form, pos, env))) = (self.frames.last_mut(), self.forms.last()) {
+            if self.forms.len() > frame.base {
+                frame.form = Some(Rc::clone(form));
+                frame.pos = pos.clone();
+                frame.env = Rc::clone(env);
+            }
+        }
+    }
+
+    fn should_pause(&mut self, pos: &PositionTag) -> Option<PauseReason> {
+        let depth = self.frames.len();
+        let line = (depth, pos.filename.clone(), pos.lineno);
+        let new_line = self.last_line.as_ref() != Some(&line);
+        self.last_line = Some(line);
+        let step = match self.mode {
+            Mode::Continue => false,
+            Mode::StepInto => true,
+            Mode::StepOver(nesting) => self.forms.len() <= nesting,
+            Mode::StepOut(d) => depth < d,
+        };
+        if step {
+            Some(PauseReason::Step)
+        } else if new_line && self.breakpoints.iter().any(|b| b.matches(pos)) {
+            Some(PauseReason::Breakpoint)
+        } else {
+            None
+        }
+    }
+}
+
+/// State of a paused evaluation, given to the [`DebugFrontend`].
+pub struct DebugSession<'a> {
+    state: &'a mut JState,
+    debugger: &'a mut Debugger,
+    reason: PauseReason,
+}
+
+impl DebugSession<'_> {
+    pub fn reason(&self) -> PauseReason {
+        self.reason
+    }
+    /// Call stack, innermost frame (where evaluation paused) last.
+    pub fn frames(&self) -> &[DebugFrame] {
+        &self.debugger.frames
+    }
+    pub fn frame(&self) -> &DebugFrame {
+        self.debugger.frames.last().unwrap()
+    }
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.debugger.breakpoints
+    }
+    pub fn breakpoints_mut(&mut self) -> &mut Vec<Breakpoint> {
+        &mut self.debugger.breakpoints
+    }
+    /// Evaluate a program in `env`, without pausing.
+    pub fn eval_str(&mut self, source: &str, env: &JEnvRef) -> Result<Option<JValRef>, JException> {
+        let pos = self.state.pos().clone();
+        let res = self.state.eval_str("#DEBUG", source, Rc::clone(env));
+        self.state.update_pos(Some(&pos));
+        res
+    }
+}
+
+/// Record the position of a form read while debugging.
+pub(crate) fn record(state: &mut JState, form: &JValRef, pos: PositionTag) {
+    if let Some(debugger) = state.debugger_mut() {
+        debugger.positions.remove_dropped();
+        debugger.positions.insert(form, pos);
+    }
+}
+
+/// Called before evaluating a list form; must be followed by [`leave`] if it
+/// succeeds.
+pub(crate) fn step(form: &JValRef, env: &JEnvRef, state: &mut JState) -> Result<(), JError> {
+    let debugger = match state.debugger_mut() {
+        Some(debugger) => debugger,
+        None => return Ok(()),
+    };
+    let pos = debugger.positions.get(form).cloned();
+    if debugger.frames.is_empty() {
+        enter(debugger, "top-level", env);
+    }
+    debugger
+        .forms
+        .push((Rc::clone(form), pos.clone(), Rc::clone(env)));
+    debugger.update_frame();
+    match pos.and_then(|pos| debugger.should_pause(&pos)) {
+        Some(reason) => pause(state, reason),
+        None => Ok(()),
+    }
+}
+
+pub(crate) fn leave(state: &mut JState) {
+    if let Some(debugger) = state.debugger_mut() {
+        debugger.forms.pop();
+        debugger.update_frame();
+    }
+}
+
+fn enter(debugger: &mut Debugger, name: &str, env: &JEnvRef) {
+    debugger.frames.push(DebugFrame {
+        name: name.to_string(),
+        env: Rc::clone(env),
+        form: None,
+        pos: None,
+        base: debugger.forms.len(),
+    });
+}
+
+/// Called when applying a named procedure.
+pub(crate) fn enter_frame(state: &mut JState, name: &str, env: &JEnvRef) {
+    if let Some(debugger) = state.debugger_mut() {
+        enter(debugger, name, env);
+    }
+}
+
+pub(crate) fn exit_frame(state: &mut JState) {
+    if let Some(debugger) = state.debugger_mut() {
+        debugger.frames.pop();
+    }
+}
+
+/// Pause at a call to the `breakpoint` builtin.
+pub(crate) fn breakpoint(env: &JEnvRef, state: &mut JState) -> Result<(), JError> {
+    let debugger = match state.debugger_mut() {
+        Some(debugger) => debugger,
+        None => return Ok(()),
+    };
+    if debugger.frames.is_empty() {
+        enter(debugger, "top-level", env);
+    }
+    debugger.frames.last_mut().unwrap().env = Rc::clone(env);
+    pause(state, PauseReason::Breakpoint)
+}
+
+fn pause(state: &mut JState, reason: PauseReason) -> Result<(), JError> {
+    let mut debugger = match state.take_debugger() {
+        Some(debugger) => debugger,
+        None => return Ok(()),
+    };
+    let mut frontend = debugger.frontend.take().unwrap();
+    let resume = frontend.paused(&mut DebugSession {
+        state,
+        debugger: &mut debugger,
+        reason,
+    });
+    debugger.frontend = Some(frontend);
+    debugger.mode = match resume {
+        Resume::Continue => Mode::Continue,
+        Resume::StepInto => Mode::StepInto,
+        Resume::StepOver => Mode::StepOver(debugger.forms.len()),
+        Resume::StepOut => Mode::StepOut(debugger.frames.len()),
+        Resume::Quit => return Err(JError::new(EvalError, "quit from debugger")),
+    };
+    state.set_debugger(Some(debugger));
+    Ok(())
+}
+
+const HELP: &str = "\
+s, step         Step into the next form
+n, next         Step over the current form
+o, out          Step out of the current procedure
+c, continue     Continue until a breakpoint
+l, locals       Show local bindings
+bt, where       Show the call stack
+p EXPR          Evaluate EXPR in the current environment
+b [FILE:]LINE   Set a breakpoint
+d [FILE:]LINE   Delete a breakpoint
+q, quit         Stop the program
+An empty line repeats the last step command.";
+
+/// Line-oriented debugger frontend, reading commands from `input` and writing to
+/// `output`. Detaches (continues without pausing) at the end of the input.
+pub struct DebugConsole<R: BufRead, W: Write> {
+    input: R,
+    output: W,
+    last: Resume,
+    eof: bool,
+}
+
+impl<R: BufRead, W: Write> DebugConsole<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Self {
+            input,
+            output,
+            last: Resume::StepInto,
+            eof: false,
+        }
+    }
+
+    fn show_form(&mut self, frame: &DebugFrame) -> std::io::Result<()> {
+        let mut form = frame.form.as_ref().map_or(String::new(), |f| repr(f));
+        if form.chars().count() > 72 {
+            form = form.chars().take(69).collect::<String>() + "...";
+        }
+        writeln!(self.output, "    {}", form)
+    }
+
+    fn breakpoint_arg(arg: &str, session: &DebugSession) -> Option<Breakpoint> {
+        let (file, line) = match arg.rsplit_once(':') {
+            Some((file, line)) => (file.to_string(), line),
+            None => (session.frame().pos.as_ref()?.filename.clone(), arg),
+        };
+        Some(Breakpoint::new(file, line.trim().parse().ok()?))
+    }
+
+    fn command(
+        &mut self,
+        line: &str,
+        session: &mut DebugSession,
+    ) -> std::io::Result<Option<Resume>> {
+        let (cmd, arg) = line.split_once(' ').unwrap_or((line, ""));
+        let arg = arg.trim();
+        match cmd {
+            "" => return Ok(Some(self.last)),
+            "s" | "step" => return Ok(Some(Resume::StepInto)),
+            "n" | "next" => return Ok(Some(Resume::StepOver)),
+            "o" | "out" => return Ok(Some(Resume::StepOut)),
+            "c" | "continue" => return Ok(Some(Resume::Continue)),
+            "q" | "quit" => return Ok(Some(Resume::Quit)),
+            "l" | "locals" => {
+                let scopes = session.frame().scopes();
+                if scopes.iter().all(|s| s.is_empty()) {
+                    writeln!(self.output, "no local bindings")?;
+                }
+                for (i, scope) in scopes.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(self.output, "-- enclosing scope")?;
+                    }
+                    for (name, val) in scope {
+                        writeln!(self.output, "{} = {}", name, val)?;
+                    }
+                }
+            }
+            "bt" | "where" => {
+                for (i, frame) in session.frames().iter().rev().enumerate() {
+                    match &frame.pos {
+                        Some(pos) => writeln!(self.output, "#{} {} at {}", i, frame.name, pos)?,
+                        None => writeln!(self.output, "#{} {}", i, frame.name)?,
+                    }
+                }
+            }
+            "p" | "print" => {
+                let env = Rc::clone(&session.frame().env);
+                match session.eval_str(arg, &env) {
+                    Ok(Some(val)) => writeln!(self.output, "{}", val)?,
+                    Ok(None) => (),
+                    Err((_, err, _)) => writeln!(self.output, "{}", err)?,
+                }
+            }
+            "b" | "break" | "d" | "delete" => match Self::breakpoint_arg(arg, session) {
+                Some(bp) if cmd.starts_with('b') => {
+                    writeln!(
+                        self.output,
+                        "breakpoint at {}:{}",
+                        bp.file.display(),
+                        bp.line
+                    )?;
+                    session.breakpoints_mut().push(bp);
+                }
+                Some(bp) => session.breakpoints_mut().retain(|b| *b != bp),
+                None => writeln!(self.output, "expected [FILE:]LINE")?,
+            },
+            "h" | "help" => writeln!(self.output, "{}", HELP)?,
+            _ => writeln!(self.output, "unknown command {}, type h for help", cmd)?,
+        }
+        Ok(None)
+    }
+
+    fn run(&mut self, session: &mut DebugSession) -> std::io::Result<Resume> {
+        let frame = session.frame().clone();
+        match &frame.pos {
+            Some(pos) => writeln!(
+                self.output,
+                "{} at {} in {}",
+                session.reason(),
+                pos,
+                frame.name
+            )?,
+            None => writeln!(self.output, "{} in {}", session.reason(), frame.name)?,
+        }
+        self.show_form(&frame)?;
+        loop {
+            write!(self.output, "debug> ")?;
+            self.output.flush()?;
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                self.eof = true;
+                return Ok(Resume::Continue);
+            }
+            if let Some(resume) = self.command(line.trim(), session)? {
+                if resume != Resume::Continue && resume != Resume::Quit {
+                    self.last = resume;
+                }
+                return Ok(resume);
+            }
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> DebugFrontend for DebugConsole<R, W> {
+    fn paused(&mut self, session: &mut DebugSession) -> Resume {
+        if self.eof {
+            return Resume::Continue;
+        }
+        match self.run(session) {
+            Ok(resume) => resume,
+            Err(_) => {
+                self.eof = true;
+                Resume::Continue
+            }
+        }
+    }
+}
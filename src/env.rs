@@ -50,6 +50,7 @@ impl JEnv {
     }
 
     /// Change existing binding.
+    #[allow(clippy::only_used_in_recursion)]
     pub fn set(&self, v: &str, val: JValRef, state: &mut JState) -> Result<(), JError> {
         if self.vars.borrow().contains_key(v) {
             self.vars.borrow_mut().insert(v.to_string(), val);
@@ -62,6 +63,28 @@ impl JEnv {
         }
     }
 
+    /// Bindings defined directly in this env (not its parents), sorted by name.
+    pub fn bindings(&self) -> Vec<(String, JValRef)> {
+        let mut bindings: Vec<(String, JValRef)> = self
+            .vars
+            .borrow()
+            .iter()
+            .map(|(k, v)| (k.clone(), Rc::clone(v)))
+            .collect();
+        bindings.sort_by(|a, b| a.0.cmp(&b.0));
+        bindings
+    }
+
+    /// Bindings defined directly in this env, see [`JEnv::set_vars`].
+    pub(crate) fn vars(&self) -> HashMap<String, JValRef> {
+        self.vars.borrow().clone()
+    }
+
+    /// Replace the bindings defined directly in this env.
+    pub(crate) fn set_vars(&self, vars: HashMap<String, JValRef>) {
+        *self.vars.borrow_mut() = vars;
+    }
+
     pub fn into_ref(self) -> JEnvRef {
         Rc::new(self)
     }
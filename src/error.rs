@@ -37,6 +37,23 @@ impl fmt::Display for JErrorKind {
     }
 }
 
+impl JErrorKind {
+    /// Kind named `name`, as displayed: unknown names are [`JErrorKind::Other`] kinds.
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "Exception" => Self::Exception,
+            "AssertionError" => Self::AssertionError,
+            "TypeError" => Self::TypeError,
+            "EvalError" => Self::EvalError,
+            "ApplyError" => Self::ApplyError,
+            "NotDefined" => Self::NotDefined,
+            "OsError" => Self::OsError,
+            "SyntaxError" => Self::SyntaxError,
+            _ => Self::Other(name.to_string()),
+        }
+    }
+}
+
 impl JError {
     pub fn new(kind: JErrorKind, reason: &str) -> Self {
         JError {
@@ -55,4 +72,6 @@ impl fmt::Display for JError {
     }
 }
 
+impl std::error::Error for JError {}
+
 pub type JResult = Result<JValRef, JError>;
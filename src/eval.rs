@@ -4,6 +4,13 @@ use crate::*;
 
 pub fn eval(expr: JValRef, env: JEnvRef, state: &mut JState) -> JResult {
     match &*expr {
+        JVal::Pair(list) if state.debugging() || state.covering() => {
+            coverage::hit(&expr, state);
+            debugger::step(&expr, &env, state)?;
+            let res = eval_list(list, env, state);
+            debugger::leave(state);
+            res
+        }
         JVal::Pair(list) => eval_list(list, env, state),
         JVal::Symbol(sym) => env.try_lookup(sym),
         JVal::Quote(val) => Ok(Rc::clone(val)),
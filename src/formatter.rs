@@ -0,0 +1,276 @@
+//! Source code formatter.
+//!
+//! Line breaks are kept as written, up to one blank line, and every line is
+//! re-indented with the usual Lisp rules:
+//! - the body of special forms (`defn`, `let`, `cond`, `test`, ...) is indented by
+//!   4 from the line where the form starts;
+//! - arguments of other calls are aligned with the first argument if it is on the
+//!   same line as the operator, otherwise indented like a body;
+//! - items of data lists (not starting with a symbol) and vectors are aligned with
+//!   the first item.
+//!
+//! Items on the same line are separated by one space, and closing parens follow the
+//! last item, except after a line comment.
+use crate::reader::cst::{CstKind, CstNode};
+use crate::*;
+
+const INDENT: usize = 4;
+
+/// Forms whose arguments are indented as a body.
+const SPECIAL_FORMS: &[&str] = &[
+    "and",
+    "begin",
+    "cond",
+    "fn",
+    "if",
+    "import",
+    "import-from",
+    "let",
+    "macro",
+    "nfn",
+    "nmacro",
+    "or",
+    "set!",
+    "setglobal!",
+    "test",
+    "test-suite",
+    "try",
+    "use",
+];
+
+fn is_special(name: &str) -> bool {
+    SPECIAL_FORMS.contains(&name) || name.starts_with("def") || name.starts_with("with-")
+}
+
+/// Children other than whitespace, with the number of newlines before each.
+fn elements(children: &[CstNode]) -> Vec<(usize, &CstNode)> {
+    let mut elems = vec![];
+    let mut newlines = 0;
+    let mut after_comment = false;
+    for child in children {
+        if child.kind == CstKind::Whitespace {
+            newlines += child.text.matches('\n').count();
+            continue;
+        }
+        if after_comment {
+            newlines = newlines.max(1);
+        }
+        elems.push((newlines, child));
+        newlines = 0;
+        after_comment = child.kind == CstKind::LineComment;
+    }
+    elems
+}
+
+struct Formatter {
+    out: String,
+    /// Column at the end of the output.
+    col: usize,
+    /// Indentation of the last line of the output.
+    line_indent: usize,
+}
+
+impl Formatter {
+    fn write(&mut self, s: &str) {
+        self.out.push_str(s);
+        match s.rfind('\n') {
+            Some(i) => {
+                let last = &s[i + 1..];
+                self.col = last.chars().count();
+                self.line_indent = last.len() - last.trim_start().len();
+            }
+            None => self.col += s.chars().count(),
+        }
+    }
+    fn newline(&mut self, blank: bool, indent: usize) {
+        self.out
+            .truncate(self.out.trim_end_matches([' ', '\t']).len());
+        self.out.push_str(if blank { "\n\n" } else { "\n" });
+        self.out.push_str(&" ".repeat(indent));
+        self.col = indent;
+        self.line_indent = indent;
+    }
+    fn node(&mut self, node: &CstNode) {
+        match node.kind {
+            CstKind::List | CstKind::Vector => self.list(node),
+            CstKind::Root => self.root(node),
+            CstKind::LineComment => self.write(node.text.trim_end()),
+            _ if node.is_leaf() => self.write(&node.text),
+            // Quotes and datum comments
+            _ => self.prefixed(node),
+        }
+    }
+    fn root(&mut self, node: &CstNode) {
+        for (i, (newlines, elem)) in elements(&node.children).into_iter().enumerate() {
+            if i > 0 {
+                match newlines {
+                    0 => self.write(" "),
+                    n => self.newline(n > 1, 0),
+                }
+            }
+            self.node(elem);
+        }
+        self.out.truncate(self.out.trim_end().len());
+        if !self.out.is_empty() {
+            self.out.push('\n');
+        }
+    }
+    fn prefixed(&mut self, node: &CstNode) {
+        let indent = self.col;
+        for (i, (newlines, elem)) in elements(&node.children).into_iter().enumerate() {
+            if i > 0 && newlines > 0 {
+                self.newline(false, indent);
+            }
+            self.node(elem);
+        }
+    }
+    fn list(&mut self, node: &CstNode) {
+        let open_line_indent = self.line_indent;
+        let mut open_col = self.col;
+        let mut indent = None;
+        let mut head: Option<&CstKind> = None;
+        let mut items = 0;
+        let mut prev: Option<&CstNode> = None;
+        for (newlines, elem) in elements(&node.children) {
+            let after_comment = prev.is_some_and(|p| p.kind == CstKind::LineComment);
+            match (&elem.kind, elem.text.as_str()) {
+                (CstKind::Punct, "#") => (),
+                (CstKind::Punct, "(") => {
+                    if after_comment {
+                        self.newline(false, open_col);
+                    }
+                    open_col = self.col;
+                }
+                (CstKind::Punct, ")") => {
+                    if after_comment {
+                        let indent = indent.unwrap_or(open_col + 1);
+                        self.newline(false, indent);
+                    }
+                }
+                _ => {
+                    let first = prev.is_some_and(|p| p.kind == CstKind::Punct);
+                    if newlines > 0 && (!first || after_comment) {
+                        let indent = *indent.get_or_insert_with(|| match head {
+                            Some(CstKind::Atom(TokenValue::Ident(_))) => open_line_indent + INDENT,
+                            _ => open_col + 1,
+                        });
+                        self.newline(newlines > 1, indent);
+                    } else if !first {
+                        self.write(" ");
+                    }
+                    if !elem.is_trivia() {
+                        if items == 1 && indent.is_none() && node.kind == CstKind::List {
+                            // First argument on the same line as the operator
+                            if let Some(CstKind::Atom(TokenValue::Ident(name))) = head {
+                                if !is_special(name) {
+                                    indent = Some(self.col);
+                                }
+                            }
+                        }
+                        if items == 0 {
+                            head = Some(&elem.kind);
+                        }
+                        items += 1;
+                    }
+                }
+            }
+            self.node(elem);
+            prev = Some(elem);
+        }
+    }
+}
+
+/// Format `jibi` source code. The result is stable: formatting it again
+/// gives the same text.
+///
+/// Readtable handlers are not called, so sources using character level reader
+/// macros cannot be formatted.
+pub fn format_source(filename: &str, source: &str) -> Result<String, JError> {
+    let cst = Parser::parse_cst(filename, source, &mut JState::new())?;
+    let mut formatter = Formatter {
+        out: String::with_capacity(source.len()),
+        col: 0,
+        line_indent: 0,
+    };
+    formatter.node(&cst);
+    Ok(formatter.out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::tokenizer::Tokenizer;
+
+    fn parse(state: &mut JState, source: &str) -> Vec<String> {
+        let tokens = Tokenizer::new("test".to_string(), source.to_string());
+        let mut parser = Parser::new(Box::new(tokens), state);
+        let forms = parser.parse_forms().unwrap();
+        forms.into_iter().map(|(_, val)| val.to_string()).collect()
+    }
+
+    fn test_format(source: &str, expected: &str) {
+        let formatted = format_source("test", source).unwrap();
+        assert_eq!(expected, formatted);
+        assert_eq!(expected, format_source("test", &formatted).unwrap());
+        let mut state = JState::default();
+        assert_eq!(parse(&mut state, source), parse(&mut state, &formatted));
+    }
+
+    #[test]
+    fn test_format_indent() {
+        test_format(
+            r#"
+(defn f (x)
+  (let ((a 1)
+  (b 2))
+(cond ((= x a) "one")
+           (else (+ a
+                b x)))))
+"#,
+            r#"(defn f (x)
+    (let ((a 1)
+          (b 2))
+        (cond ((= x a) "one")
+            (else (+ a
+                     b x)))))
+"#,
+        );
+        test_format(
+            "(list\n1 2)\n(test \"t\"\n(assert #(1\n2)))",
+            "(list\n    1 2)\n(test \"t\"\n    (assert #(1\n              2)))\n",
+        );
+    }
+
+    #[test]
+    fn test_format_spacing() {
+        test_format(
+            "(  a   b\n\n\n  c\n )   \n\n\n\n'( x  . y )\n",
+            "(a b\n\n   c)\n\n'(x . y)\n",
+        );
+        test_format("` ( a , b ,@ c )", "`(a ,b ,@c)\n");
+        test_format("", "");
+    }
+
+    #[test]
+    fn test_format_comments() {
+        test_format(
+            "; header\n(defn f () ; trailing   \n  #| block\n  comment |# 1 #;  2\n ; last\n )\n",
+            "; header\n(defn f () ; trailing\n    #| block\n  comment |# 1 #;2\n    ; last\n    )\n",
+        );
+    }
+
+    #[test]
+    fn test_format_sources() {
+        for source in [
+            PRELUDE,
+            include_str!("../stl/decimal.jibi"),
+            include_str!("../stl/unittest.jibi"),
+            include_str!("../tests/test-lang-bindings.jibi"),
+        ] {
+            let formatted = format_source("test", source).unwrap();
+            assert_eq!(formatted, format_source("test", &formatted).unwrap());
+            let mut state = JState::default();
+            assert_eq!(parse(&mut state, source), parse(&mut state, &formatted));
+        }
+    }
+}
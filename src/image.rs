@@ -0,0 +1,837 @@
+//! Binary images of interpreter state.
+//!
+//! An image holds the global env, the module cache, the reader macros, the
+//! readtable and the interned symbol and string tables. Values are written once each, in dependency
+//! order, so that sharing (`eq?`) and cycles through envs and vectors survive a
+//! round trip. Builtins cannot be serialized, they are written as keys into the
+//! builtin registry of [`JState`] and resolved against the builtins of the loading
+//! interpreter, which must define the same builtins in the same order.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::reader::readermacro::{Matcher, TokenRegex};
+use crate::reader::readtable::{CharMacro, Readtable};
+use crate::*;
+
+const MAGIC: &[u8] = b"JIBIIMG\0";
+const FORMAT_VERSION: u32 = 2;
+
+// Records
+const R_ENV: u8 = 1;
+const R_BIND: u8 = 2;
+const R_VAL: u8 = 3;
+const R_FILL: u8 = 4;
+const R_ROOTS: u8 = 5;
+
+// Values
+const V_NIL: u8 = 0;
+const V_TRUE: u8 = 1;
+const V_FALSE: u8 = 2;
+const V_INT: u8 = 3;
+const V_FLOAT: u8 = 4;
+const V_SYMBOL: u8 = 5;
+const V_STRING: u8 = 6;
+const V_VECTOR: u8 = 7;
+const V_PAIR: u8 = 8;
+const V_ERROR: u8 = 9;
+const V_QUOTE: u8 = 10;
+const V_QUASIQUOTE: u8 = 11;
+const V_UNQUOTE: u8 = 12;
+const V_UNQUOTE_SPLICE: u8 = 13;
+const V_LAMBDA: u8 = 14;
+const V_MACRO: u8 = 15;
+const V_BUILTIN: u8 = 16;
+const V_SPECIALFORM: u8 = 17;
+const V_ENV: u8 = 18;
+const V_TOKEN: u8 = 19;
+const V_TOKENMATCHER: u8 = 20;
+const V_DATUM_TOKEN: u8 = 21;
+
+fn image_error(reason: &str) -> JError {
+    JError::new(Other("ImageError".to_string()), reason)
+}
+
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, n: u8) {
+        self.buf.push(n);
+    }
+    fn u32(&mut self, n: u32) {
+        self.buf.extend_from_slice(&n.to_le_bytes());
+    }
+    fn len(&mut self, n: usize) {
+        self.u32(n as u32);
+    }
+    fn int(&mut self, n: JTInt) {
+        self.buf.extend_from_slice(&n.to_le_bytes());
+    }
+    fn float(&mut self, x: JTFloat) {
+        self.buf.extend_from_slice(&x.to_bits().to_le_bytes());
+    }
+    fn str(&mut self, s: &str) {
+        self.len(s.len());
+        self.buf.extend_from_slice(s.as_bytes());
+    }
+    fn opt_str(&mut self, s: Option<&str>) {
+        match s {
+            Some(s) => {
+                self.u8(1);
+                self.str(s);
+            }
+            None => self.u8(0),
+        }
+    }
+    fn pos(&mut self, pos: &PositionTag) {
+        self.str(&pos.filename);
+        self.len(pos.lineno);
+        self.len(pos.col);
+    }
+    fn token_value(&mut self, tv: &TokenValue) {
+        match tv {
+            TokenValue::Char(c) => {
+                self.u8(0);
+                self.u32(*c as u32);
+            }
+            TokenValue::Int(n) => {
+                self.u8(1);
+                self.int(*n);
+            }
+            TokenValue::Float(x) => {
+                self.u8(2);
+                self.float(*x);
+            }
+            TokenValue::Ident(s) => {
+                self.u8(3);
+                self.str(s);
+            }
+            TokenValue::String(s) => {
+                self.u8(4);
+                self.str(s);
+            }
+            TokenValue::Eof => self.u8(5),
+            TokenValue::Bool(b) => {
+                self.u8(6);
+                self.u8(*b as u8);
+            }
+            TokenValue::DatumComment => self.u8(7),
+            TokenValue::Datum(_) => unreachable!("datum tokens are encoded as values"),
+        }
+    }
+    fn matcher<T, F: Fn(&mut Self, &T)>(&mut self, m: &Matcher<T>, f: F) {
+        match m {
+            Matcher::Any => self.u8(0),
+            Matcher::Exact(v) => {
+                self.u8(1);
+                f(self, v);
+            }
+        }
+    }
+    fn tokenmatcher(&mut self, tm: &TokenMatcher) {
+        match tm {
+            TokenMatcher::Any => self.u8(0),
+            TokenMatcher::Char(c) => {
+                self.u8(1);
+                self.u32(*c as u32);
+            }
+            TokenMatcher::Int(m) => {
+                self.u8(2);
+                self.matcher(m, |w, n| w.int(*n));
+            }
+            TokenMatcher::Float(m) => {
+                self.u8(3);
+                self.matcher(m, |w, x| w.float(*x));
+            }
+            TokenMatcher::Ident(m) => {
+                self.u8(4);
+                self.matcher(m, |w, s| w.str(s));
+            }
+            TokenMatcher::String(m) => {
+                self.u8(5);
+                self.matcher(m, |w, s| w.str(s));
+            }
+            TokenMatcher::Eof => self.u8(6),
+            TokenMatcher::Or(tm1, tm2) => {
+                self.u8(7);
+                self.tokenmatcher(tm1);
+                self.tokenmatcher(tm2);
+            }
+            TokenMatcher::Bool(m) => {
+                self.u8(8);
+                self.matcher(m, |w, b| w.u8(*b as u8));
+            }
+            TokenMatcher::Regex(re) => {
+                self.u8(9);
+                self.str(re.pattern());
+            }
+            TokenMatcher::Group => self.u8(10),
+            TokenMatcher::Seq(tms) => {
+                self.u8(11);
+                self.len(tms.len());
+                tms.iter().for_each(|tm| self.tokenmatcher(tm));
+            }
+            TokenMatcher::Repeat(tm, min, max) => {
+                self.u8(12);
+                self.tokenmatcher(tm);
+                self.len(*min);
+                match max {
+                    Some(max) => {
+                        self.u8(1);
+                        self.len(*max);
+                    }
+                    None => self.u8(0),
+                }
+            }
+        }
+    }
+}
+
+struct Encoder<'a> {
+    state: &'a JState,
+    w: Writer,
+    vals: HashMap<*const JVal, u32>,
+    envs: HashMap<*const JEnv, u32>,
+    // Keep encoded values alive so their addresses are not reused
+    keep_vals: Vec<JValRef>,
+    keep_envs: Vec<JEnvRef>,
+    pending_envs: Vec<(u32, JEnvRef)>,
+}
+
+impl<'a> Encoder<'a> {
+    fn new(state: &'a JState) -> Self {
+        Self {
+            state,
+            w: Writer::default(),
+            vals: HashMap::new(),
+            envs: HashMap::new(),
+            keep_vals: vec![],
+            keep_envs: vec![],
+            pending_envs: vec![],
+        }
+    }
+
+    fn env(&mut self, env: &JEnvRef) -> u32 {
+        if let Some(idx) = self.envs.get(&Rc::as_ptr(env)) {
+            return *idx;
+        }
+        let parent = env.parent.as_ref().map(|p| self.env(p));
+        let idx = self.keep_envs.len() as u32;
+        self.envs.insert(Rc::as_ptr(env), idx);
+        self.keep_envs.push(Rc::clone(env));
+        self.w.u8(R_ENV);
+        match parent {
+            Some(p) => {
+                self.w.u8(1);
+                self.w.u32(p);
+            }
+            None => self.w.u8(0),
+        }
+        // Bindings are written later, so that envs never recurse into values
+        self.pending_envs.push((idx, Rc::clone(env)));
+        idx
+    }
+
+    fn flush_envs(&mut self) -> Result<(), JError> {
+        while let Some((idx, env)) = self.pending_envs.pop() {
+            for (name, val) in env.bindings() {
+                let vidx = self.val(&val)?;
+                self.w.u8(R_BIND);
+                self.w.u32(idx);
+                self.w.str(&name);
+                self.w.u32(vidx);
+            }
+        }
+        Ok(())
+    }
+
+    fn start_val(&mut self, val: &JValRef) -> u32 {
+        let idx = self.keep_vals.len() as u32;
+        self.vals.insert(Rc::as_ptr(val), idx);
+        self.keep_vals.push(Rc::clone(val));
+        self.w.u8(R_VAL);
+        idx
+    }
+
+    fn write_lambda(&mut self, l: &JLambda, closure: u32, code: &[u32]) {
+        self.w.u32(closure);
+        match &l.params {
+            JParams::Fixed(params) => {
+                self.w.u8(0);
+                self.w.len(params.len());
+                params.iter().for_each(|p| self.w.str(p));
+            }
+            JParams::Variadic(params, rest) => {
+                self.w.u8(1);
+                self.w.len(params.len());
+                params.iter().for_each(|p| self.w.str(p));
+                self.w.str(rest);
+            }
+        }
+        self.w.len(code.len());
+        code.iter().for_each(|c| self.w.u32(*c));
+        match &l.defpos {
+            Some(pos) => {
+                self.w.u8(1);
+                self.w.pos(pos);
+            }
+            None => self.w.u8(0),
+        }
+        self.w.opt_str(l.name.as_deref());
+    }
+
+    fn val(&mut self, val: &JValRef) -> Result<u32, JError> {
+        if let Some(idx) = self.vals.get(&Rc::as_ptr(val)) {
+            return Ok(*idx);
+        }
+        match &**val {
+            JVal::Nil => {
+                let idx = self.start_val(val);
+                self.w.u8(V_NIL);
+                Ok(idx)
+            }
+            JVal::Bool(b) => {
+                let idx = self.start_val(val);
+                self.w.u8(if *b { V_TRUE } else { V_FALSE });
+                Ok(idx)
+            }
+            JVal::Int(n) => {
+                let idx = self.start_val(val);
+                self.w.u8(V_INT);
+                self.w.int(*n);
+                Ok(idx)
+            }
+            JVal::Float(x) => {
+                let idx = self.start_val(val);
+                self.w.u8(V_FLOAT);
+                self.w.float(*x);
+                Ok(idx)
+            }
+            JVal::Symbol(s) => {
+                let idx = self.start_val(val);
+                self.w.u8(V_SYMBOL);
+                self.w.str(s);
+                Ok(idx)
+            }
+            JVal::String(s) => {
+                let idx = self.start_val(val);
+                self.w.u8(V_STRING);
+                self.w.str(s);
+                Ok(idx)
+            }
+            JVal::Error(e) => {
+                let idx = self.start_val(val);
+                self.w.u8(V_ERROR);
+                self.w.str(&e.kind.to_string());
+                self.w.str(&e.reason);
+                Ok(idx)
+            }
+            JVal::Vector(items) => {
+                // Vectors are mutable and may contain themselves: write an empty vector
+                // first, and fill it once its items are written.
+                let idx = self.start_val(val);
+                self.w.u8(V_VECTOR);
+                let items = items
+                    .borrow()
+                    .iter()
+                    .map(|v| self.val(v))
+                    .collect::<Result<Vec<u32>, JError>>()?;
+                self.w.u8(R_FILL);
+                self.w.u32(idx);
+                self.w.len(items.len());
+                items.iter().for_each(|i| self.w.u32(*i));
+                Ok(idx)
+            }
+            JVal::Pair(_) => {
+                // Walk the list iteratively to avoid recursing on long lists
+                let mut chain = vec![];
+                let mut cur = Rc::clone(val);
+                while let JVal::Pair(p) = &*cur {
+                    if self.vals.contains_key(&Rc::as_ptr(&cur)) {
+                        break;
+                    }
+                    let next = p.cdr();
+                    chain.push(cur);
+                    cur = next;
+                }
+                let mut tail = self.val(&cur)?;
+                for pair in chain.iter().rev() {
+                    let car = self.val(&pair.to_pair()?.car())?;
+                    // The car may have reached this pair through a vector
+                    if let Some(idx) = self.vals.get(&Rc::as_ptr(pair)) {
+                        tail = *idx;
+                        continue;
+                    }
+                    let idx = self.start_val(pair);
+                    self.w.u8(V_PAIR);
+                    self.w.u32(car);
+                    self.w.u32(tail);
+                    tail = idx;
+                }
+                Ok(tail)
+            }
+            JVal::Quote(v) | JVal::Quasiquote(v) | JVal::Unquote(v) | JVal::UnquoteSplice(v) => {
+                let inner = self.val(v)?;
+                let idx = self.start_val(val);
+                self.w.u8(match &**val {
+                    JVal::Quote(_) => V_QUOTE,
+                    JVal::Quasiquote(_) => V_QUASIQUOTE,
+                    JVal::Unquote(_) => V_UNQUOTE,
+                    _ => V_UNQUOTE_SPLICE,
+                });
+                self.w.u32(inner);
+                Ok(idx)
+            }
+            JVal::Lambda(l) | JVal::Macro(l) => {
+                let closure = self.env(&l.closure);
+                let code = l
+                    .code
+                    .iter()
+                    .map(|v| self.val(v))
+                    .collect::<Result<Vec<u32>, JError>>()?;
+                let idx = self.start_val(val);
+                self.w.u8(match &**val {
+                    JVal::Lambda(_) => V_LAMBDA,
+                    _ => V_MACRO,
+                });
+                self.write_lambda(l, closure, &code);
+                Ok(idx)
+            }
+            JVal::Builtin(b) | JVal::SpecialForm(b) => {
+                let key = match self.state.builtin_key(b) {
+                    Some(key) => key.to_string(),
+                    None => {
+                        return Err(image_error(&format!(
+                            "builtin {} is not registered",
+                            b.name
+                        )))
+                    }
+                };
+                let idx = self.start_val(val);
+                self.w.u8(match &**val {
+                    JVal::Builtin(_) => V_BUILTIN,
+                    _ => V_SPECIALFORM,
+                });
+                self.w.str(&key);
+                Ok(idx)
+            }
+            JVal::Env(env) => {
+                let env = self.env(env);
+                let idx = self.start_val(val);
+                self.w.u8(V_ENV);
+                self.w.u32(env);
+                Ok(idx)
+            }
+            JVal::Token(Token {
+                value: TokenValue::Datum(v),
+                pos,
+            }) => {
+                let inner = self.val(v)?;
+                let idx = self.start_val(val);
+                self.w.u8(V_DATUM_TOKEN);
+                self.w.u32(inner);
+                self.w.pos(pos);
+                Ok(idx)
+            }
+            JVal::Token(t) => {
+                let idx = self.start_val(val);
+                self.w.u8(V_TOKEN);
+                self.w.token_value(&t.value);
+                self.w.pos(&t.pos);
+                Ok(idx)
+            }
+            JVal::TokenMatcher(tm) => {
+                let idx = self.start_val(val);
+                self.w.u8(V_TOKENMATCHER);
+                self.w.tokenmatcher(tm);
+                Ok(idx)
+            }
+            JVal::Foreign(f) => Err(image_error(&format!(
+                "cannot save foreign {} in an image",
+                f.type_name()
+            ))),
+        }
+    }
+}
+
+/// Serialize the globals and the state of an interpreter to an image.
+pub(crate) fn write_image(state: &JState, globals: &JEnvRef) -> Result<Vec<u8>, JError> {
+    let mut enc = Encoder::new(state);
+    enc.w.buf.extend_from_slice(MAGIC);
+    enc.w.u32(FORMAT_VERSION);
+    enc.w.str(env!("CARGO_PKG_VERSION"));
+
+    let globals_idx = enc.env(globals);
+    let mut modules: Vec<(&PathBuf, &JEnvRef)> = state.modules().iter().collect();
+    modules.sort_by(|a, b| a.0.cmp(b.0));
+    let modules: Vec<(String, u32)> = modules
+        .into_iter()
+        .map(|(path, env)| (path.to_string_lossy().to_string(), enc.env(env)))
+        .collect();
+    let mut macros = vec![];
+    for rm in state.reader_macros() {
+        let closure = enc.env(&rm.lambda().closure);
+        let code = rm
+            .lambda()
+            .code
+            .iter()
+            .map(|v| enc.val(v))
+            .collect::<Result<Vec<u32>, JError>>()?;
+        macros.push((closure, code, enc.env(&rm.env())));
+    }
+    let mut char_macros = vec![];
+    for cm in state.readtable().macros() {
+        let closure = enc.env(&cm.lambda().closure);
+        let code = cm
+            .lambda()
+            .code
+            .iter()
+            .map(|v| enc.val(v))
+            .collect::<Result<Vec<u32>, JError>>()?;
+        char_macros.push((cm, closure, code, enc.env(&cm.env())));
+    }
+    enc.flush_envs()?;
+
+    enc.w.u8(R_ROOTS);
+    enc.w.u32(globals_idx);
+    enc.w.len(modules.len());
+    for (path, env) in &modules {
+        enc.w.str(path);
+        enc.w.u32(*env);
+    }
+    enc.w.len(macros.len());
+    for (rm, (closure, code, env)) in state.reader_macros().iter().zip(macros.iter()) {
+        enc.w.len(rm.rule().len());
+        rm.rule().iter().for_each(|tm| enc.w.tokenmatcher(tm));
+        enc.write_lambda(rm.lambda(), *closure, code);
+        enc.w.u32(*env);
+    }
+    enc.w.len(char_macros.len());
+    for (cm, closure, code, env) in &char_macros {
+        enc.w.str(cm.key());
+        enc.write_lambda(cm.lambda(), *closure, code);
+        enc.w.u32(*env);
+    }
+    let mut symbols = state.interned_symbols();
+    symbols.sort();
+    let mut strings = state.interned_strings();
+    strings.sort();
+    for table in [symbols, strings] {
+        enc.w.len(table.len());
+        table.iter().for_each(|s| enc.w.str(s));
+    }
+    Ok(enc.w.buf)
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], JError> {
+        if self.pos + n > self.buf.len() {
+            return Err(image_error("unexpected end of image"));
+        }
+        let bytes = &self.buf[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+    fn u8(&mut self) -> Result<u8, JError> {
+        Ok(self.bytes(1)?[0])
+    }
+    fn u32(&mut self) -> Result<u32, JError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+    fn len(&mut self) -> Result<usize, JError> {
+        Ok(self.u32()? as usize)
+    }
+    fn int(&mut self) -> Result<JTInt, JError> {
+        Ok(JTInt::from_le_bytes(self.bytes(16)?.try_into().unwrap()))
+    }
+    fn float(&mut self) -> Result<JTFloat, JError> {
+        Ok(JTFloat::from_bits(u64::from_le_bytes(
+            self.bytes(8)?.try_into().unwrap(),
+        )))
+    }
+    fn char(&mut self) -> Result<char, JError> {
+        std::char::from_u32(self.u32()?).ok_or_else(|| image_error("invalid char"))
+    }
+    fn str(&mut self) -> Result<String, JError> {
+        let n = self.len()?;
+        String::from_utf8(self.bytes(n)?.to_vec()).map_err(|_| image_error("invalid string"))
+    }
+    fn opt_str(&mut self) -> Result<Option<String>, JError> {
+        Ok(match self.u8()? {
+            0 => None,
+            _ => Some(self.str()?),
+        })
+    }
+    fn pos(&mut self) -> Result<PositionTag, JError> {
+        Ok(PositionTag {
+            filename: self.str()?,
+            lineno: self.len()?,
+            col: self.len()?,
+        })
+    }
+    fn token_value(&mut self) -> Result<TokenValue, JError> {
+        Ok(match self.u8()? {
+            0 => TokenValue::Char(self.char()?),
+            1 => TokenValue::Int(self.int()?),
+            2 => TokenValue::Float(self.float()?),
+            3 => TokenValue::Ident(self.str()?),
+            4 => TokenValue::String(self.str()?),
+            5 => TokenValue::Eof,
+            6 => TokenValue::Bool(self.u8()? != 0),
+            7 => TokenValue::DatumComment,
+            _ => return Err(image_error("invalid token")),
+        })
+    }
+    fn matcher<T, F: Fn(&mut Self) -> Result<T, JError>>(
+        &mut self,
+        f: F,
+    ) -> Result<Matcher<T>, JError> {
+        Ok(match self.u8()? {
+            0 => Matcher::Any,
+            _ => Matcher::Exact(f(self)?),
+        })
+    }
+    fn tokenmatcher(&mut self) -> Result<TokenMatcher, JError> {
+        Ok(match self.u8()? {
+            0 => TokenMatcher::Any,
+            1 => TokenMatcher::Char(self.char()?),
+            2 => TokenMatcher::Int(self.matcher(|r| r.int())?),
+            3 => TokenMatcher::Float(self.matcher(|r| r.float())?),
+            4 => TokenMatcher::Ident(self.matcher(|r| r.str())?),
+            5 => TokenMatcher::String(self.matcher(|r| r.str())?),
+            6 => TokenMatcher::Eof,
+            7 => self.tokenmatcher()?.or(self.tokenmatcher()?),
+            8 => TokenMatcher::Bool(self.matcher(|r| Ok(r.u8()? != 0))?),
+            9 => TokenMatcher::Regex(TokenRegex::new(&self.str()?)?),
+            10 => TokenMatcher::Group,
+            11 => {
+                let n = self.len()?;
+                TokenMatcher::Seq(
+                    (0..n)
+                        .map(|_| self.tokenmatcher())
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            12 => {
+                let tm = self.tokenmatcher()?;
+                let min = self.len()?;
+                let max = match self.u8()? {
+                    0 => None,
+                    _ => Some(self.len()?),
+                };
+                TokenMatcher::Repeat(Box::new(tm), min, max)
+            }
+            _ => return Err(image_error("invalid token matcher")),
+        })
+    }
+}
+
+struct Decoder<'a, 'b> {
+    r: Reader<'a>,
+    state: &'b mut JState,
+    vals: Vec<JValRef>,
+    envs: Vec<JEnvRef>,
+}
+
+impl Decoder<'_, '_> {
+    fn val(&self, idx: u32) -> Result<JValRef, JError> {
+        self.vals
+            .get(idx as usize)
+            .map(Rc::clone)
+            .ok_or_else(|| image_error("invalid value reference"))
+    }
+    fn env(&self, idx: u32) -> Result<JEnvRef, JError> {
+        self.envs
+            .get(idx as usize)
+            .map(Rc::clone)
+            .ok_or_else(|| image_error("invalid env reference"))
+    }
+    fn next_val(&mut self) -> Result<JValRef, JError> {
+        let idx = self.r.u32()?;
+        self.val(idx)
+    }
+    fn next_env(&mut self) -> Result<JEnvRef, JError> {
+        let idx = self.r.u32()?;
+        self.env(idx)
+    }
+    fn lambda(&mut self) -> Result<JLambda, JError> {
+        let closure = self.next_env()?;
+        let params = match self.r.u8()? {
+            0 => {
+                let n = self.r.len()?;
+                JParams::Fixed((0..n).map(|_| self.r.str()).collect::<Result<_, _>>()?)
+            }
+            _ => {
+                let n = self.r.len()?;
+                let params = (0..n).map(|_| self.r.str()).collect::<Result<_, _>>()?;
+                JParams::Variadic(params, self.r.str()?)
+            }
+        };
+        let n = self.r.len()?;
+        let mut code = vec![];
+        for _ in 0..n {
+            code.push(self.next_val()?);
+        }
+        let defpos = match self.r.u8()? {
+            0 => None,
+            _ => Some(self.r.pos()?),
+        };
+        Ok(JLambda {
+            closure,
+            params,
+            code,
+            defpos,
+            name: self.r.opt_str()?,
+        })
+    }
+    fn read_val(&mut self) -> Result<JValRef, JError> {
+        Ok(match self.r.u8()? {
+            V_NIL => self.state.nil(),
+            V_TRUE => self.state.bool(true),
+            V_FALSE => self.state.bool(false),
+            V_INT => self.state.int(self.r.int()?),
+            V_FLOAT => self.state.float(self.r.float()?),
+            V_SYMBOL => self.state.symbol(self.r.str()?),
+            V_STRING => self.state.string(self.r.str()?),
+            V_VECTOR => JVal::Vector(RefCell::new(vec![])).into_ref(),
+            V_PAIR => {
+                let car = self.next_val()?;
+                let cdr = self.next_val()?;
+                self.state.pair(car, cdr)
+            }
+            V_ERROR => {
+                let kind = JErrorKind::from_name(&self.r.str()?);
+                self.state.error(kind, &self.r.str()?)
+            }
+            tag @ (V_QUOTE | V_QUASIQUOTE | V_UNQUOTE | V_UNQUOTE_SPLICE) => {
+                let v = self.next_val()?;
+                match tag {
+                    V_QUOTE => JVal::Quote(v),
+                    V_QUASIQUOTE => JVal::Quasiquote(v),
+                    V_UNQUOTE => JVal::Unquote(v),
+                    _ => JVal::UnquoteSplice(v),
+                }
+                .into_ref()
+            }
+            V_LAMBDA => JVal::Lambda(Box::new(self.lambda()?)).into_ref(),
+            V_MACRO => JVal::Macro(Box::new(self.lambda()?)).into_ref(),
+            V_BUILTIN | V_SPECIALFORM => {
+                let key = self.r.str()?;
+                self.state.builtin_by_key(&key).ok_or_else(|| {
+                    image_error(&format!(
+                        "builtin {} is not defined",
+                        key.split('#').next().unwrap()
+                    ))
+                })?
+            }
+            V_ENV => JVal::Env(self.next_env()?).into_ref(),
+            V_TOKEN => {
+                let value = self.r.token_value()?;
+                JVal::Token(Token::new(value, self.r.pos()?)).into_ref()
+            }
+            V_TOKENMATCHER => JVal::TokenMatcher(self.r.tokenmatcher()?).into_ref(),
+            V_DATUM_TOKEN => {
+                let value = TokenValue::Datum(self.next_val()?);
+                JVal::Token(Token::new(value, self.r.pos()?)).into_ref()
+            }
+            _ => return Err(image_error("invalid value")),
+        })
+    }
+}
+
+/// Restore an image created by [`write_image`]: replace the module cache and
+/// reader macros of `state`, and return the global env.
+pub(crate) fn read_image(bytes: &[u8], state: &mut JState) -> Result<JEnvRef, JError> {
+    let mut dec = Decoder {
+        r: Reader { buf: bytes, pos: 0 },
+        state,
+        vals: vec![],
+        envs: vec![],
+    };
+    if dec.r.bytes(MAGIC.len()).ok() != Some(MAGIC) {
+        return Err(image_error("not a jibi image"));
+    }
+    let version = dec.r.u32()?;
+    let crate_version = dec.r.str()?;
+    if version != FORMAT_VERSION || crate_version != env!("CARGO_PKG_VERSION") {
+        return Err(image_error(&format!(
+            "image was created by jibi v{}",
+            crate_version
+        )));
+    }
+    loop {
+        match dec.r.u8()? {
+            R_ENV => {
+                let parent = match dec.r.u8()? {
+                    0 => None,
+                    _ => Some(dec.next_env()?),
+                };
+                dec.envs.push(JEnv::new(parent).into_ref());
+            }
+            R_BIND => {
+                let env = dec.next_env()?;
+                let name = dec.r.str()?;
+                env.define(&name, dec.next_val()?);
+            }
+            R_VAL => {
+                let val = dec.read_val()?;
+                dec.vals.push(val);
+            }
+            R_FILL => {
+                let vec = dec.next_val()?;
+                let n = dec.r.len()?;
+                let mut items = vec![];
+                for _ in 0..n {
+                    items.push(dec.next_val()?);
+                }
+                *vec.to_vector()?.borrow_mut() = items;
+            }
+            R_ROOTS => break,
+            _ => return Err(image_error("invalid record")),
+        }
+    }
+
+    let globals = dec.next_env()?;
+    let mut modules = HashMap::new();
+    for _ in 0..dec.r.len()? {
+        let path = PathBuf::from(dec.r.str()?);
+        modules.insert(path, dec.next_env()?);
+    }
+    let mut macros = vec![];
+    for _ in 0..dec.r.len()? {
+        let n = dec.r.len()?;
+        let rule = (0..n)
+            .map(|_| dec.r.tokenmatcher())
+            .collect::<Result<Vec<TokenMatcher>, JError>>()?;
+        let lambda = dec.lambda()?;
+        macros.push(ReaderMacro::new(rule, lambda, dec.next_env()?));
+    }
+    let mut readtable = Readtable::default();
+    for _ in 0..dec.r.len()? {
+        let key = dec.r.str()?;
+        let lambda = dec.lambda()?;
+        readtable.set(CharMacro::new(&key, lambda, dec.next_env()?)?);
+    }
+    for _ in 0..dec.r.len()? {
+        let s = dec.r.str()?;
+        dec.state.symbol(s);
+    }
+    for _ in 0..dec.r.len()? {
+        let s = dec.r.str()?;
+        dec.state.string(s);
+    }
+    dec.state.set_modules(modules);
+    dec.state.set_reader_macros(macros);
+    dec.state.set_readtable(readtable);
+    Ok(globals)
+}
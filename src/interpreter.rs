@@ -1,7 +1,9 @@
+use std::io::BufRead;
 use std::path::Path;
 use std::rc::Rc;
 
 use crate::builtin::add_builtins;
+use crate::image::{read_image, write_image};
 use crate::*;
 
 pub const PRELUDE: &str = include_str!("prelude.jibi");
@@ -40,6 +42,9 @@ impl Interpreter {
     pub(crate) fn define_builtins(&mut self) {
         add_builtins(Rc::clone(&self.globals), &mut self.state);
     }
+    pub(crate) fn globals(&self) -> &JEnvRef {
+        &self.globals
+    }
     /// Execute the `jibi` [prelude](PRELUDE), which defines common constants, procedures
     /// and macros.
     pub(crate) fn exec_prelude(&mut self) {
@@ -49,6 +54,39 @@ impl Interpreter {
         }
     }
 
+    /// Save the global environment, module cache, reader macros and interned tables
+    /// to an image file, which can be restored with [`Interpreter::load_image`].
+    ///
+    /// Fails if a global is (or contains) a `foreign` value or a builtin not
+    /// created by this interpreter.
+    pub fn save_image<P: AsRef<Path>>(&self, path: P) -> Result<(), JError> {
+        let image = write_image(&self.state, &self.globals)?;
+        std::fs::write(path, image).map_err(|e| JError::new(OsError, &format!("{}", e)))
+    }
+    /// Create an interpreter from an image saved by [`Interpreter::save_image`],
+    /// without re-evaluating the [prelude](PRELUDE) or the code that built the image.
+    pub fn load_image<P: AsRef<Path>>(path: P) -> Result<Self, JError> {
+        Self::load_image_with(path, |_| ())
+    }
+    /// Like [`Interpreter::load_image`], for images saved by interpreters with
+    /// additional builtins (e.g. from [`Interpreter::builtin`] or
+    /// [`JibiRecord::register`](crate::JibiRecord::register)).
+    ///
+    /// * `define`: Defines the additional builtins, in the same order as in the
+    ///   interpreter that saved the image.
+    pub fn load_image_with<P, F>(path: P, define: F) -> Result<Self, JError>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(&mut Self),
+    {
+        let image = std::fs::read(path).map_err(|e| JError::new(OsError, &format!("{}", e)))?;
+        let mut interpreter = Self::new();
+        interpreter.define_builtins();
+        define(&mut interpreter);
+        interpreter.globals = read_image(&image, &mut interpreter.state)?;
+        Ok(interpreter)
+    }
+
     /// Evaluate a stream of tokens and return the value of the last expression,
     /// (or None if the program contains no expressions).
     pub fn eval_tokens(
@@ -66,6 +104,19 @@ impl Interpreter {
     pub fn eval_str(&mut self, name: &str, program: &str) -> Result<Option<JValRef>, JException> {
         self.state.eval_str(name, program, Rc::clone(&self.globals))
     }
+    /// Evaluate a `jibi` script read incrementally from `reader`, and return the value
+    /// of its last expression (or None if the program contains no expressions).
+    ///
+    /// Each expression is evaluated as soon as it has been read.
+    ///
+    /// * `name`: Name used to report errors in the program (e.g. filename, "stdin").
+    pub fn eval_reader<R: BufRead + 'static>(
+        &mut self,
+        name: &str,
+        reader: R,
+    ) -> Result<Option<JValRef>, JException> {
+        self.eval_tokens(Box::new(StreamTokenizer::new(name.to_string(), reader)))
+    }
     /// Evaluate a `jibi` script file, and return the value of the last expression
     /// or None if the file contains no expressions).
     //
@@ -92,20 +143,57 @@ impl Interpreter {
         eval(sexpr, Rc::clone(&self.globals), &mut self.state)
     }
 
+    /// Start profiling with `profiler`, or stop with `None`.
+    pub fn set_profiler(&mut self, profiler: Option<crate::profiler::Profiler>) {
+        self.state.set_profiler(profiler)
+    }
+    /// Stop profiling and return the [`Profiler`](crate::profiler::Profiler).
+    pub fn take_profiler(&mut self) -> Option<crate::profiler::Profiler> {
+        self.state.take_profiler()
+    }
+    /// Trace calls to all named lambdas defined in a file (the filename given to
+    /// [`Interpreter::eval_file`] or [`Interpreter::eval_str`]), see the `trace` builtin.
+    pub fn trace_file(&mut self, filename: &str) {
+        self.state.trace_file(filename)
+    }
+    /// Start recording line coverage with `coverage`, or stop with `None`.
+    pub fn set_coverage(&mut self, coverage: Option<crate::coverage::Coverage>) {
+        self.state.set_coverage(coverage)
+    }
+    /// Stop recording coverage and return the [`Coverage`](crate::coverage::Coverage).
+    pub fn take_coverage(&mut self) -> Option<crate::coverage::Coverage> {
+        self.state.take_coverage()
+    }
+    /// Attach a [`Debugger`](crate::debugger::Debugger) to pause evaluation, or
+    /// detach it with `None`.
+    pub fn set_debugger(&mut self, debugger: Option<crate::debugger::Debugger>) {
+        self.state.set_debugger(debugger)
+    }
+
+    /// See [`JState::set_output`].
+    pub fn set_output(&mut self, output: Option<Box<dyn std::io::Write>>) {
+        self.state.set_output(output)
+    }
+
     /// Print exception and traceback.
-    pub fn print_exc((pos, err, mut tb): JException) {
-        eprintln!("Traceback:");
-        while !tb.is_empty() {
-            eprintln!("  {}", tb.pop().unwrap());
+    pub fn print_exc(exc: JException) {
+        eprint!("{}", Self::format_exc(&exc));
+    }
+    /// Format exception and traceback, as printed by [`Interpreter::print_exc`].
+    pub fn format_exc((pos, err, tb): &JException) -> String {
+        let mut lines = vec!["Traceback:".to_string()];
+        for frame in tb.iter().rev() {
+            lines.push(format!("  {}", frame));
         }
-        eprintln!("  File \"{}\", line {}", pos.filename, pos.lineno);
-        eprintln!("{}", err);
+        lines.push(format!("  File \"{}\", line {}", pos.filename, pos.lineno));
+        lines.push(format!("{}\n", err));
+        lines.join("\n")
     }
 
     /// Create a global binding (variable definition).
     ///
     /// * `name`: Name to bind. It is possible to bind names which are not valid
-    ///           `jibi` symbols, they will not be accessible in `jibi` code.
+    ///   `jibi` symbols, they will not be accessible in `jibi` code.
     /// * `val`: A `jibi` value.
     pub fn def(&mut self, name: &str, val: JValRef) {
         self.globals.define(name, val)
@@ -113,7 +201,7 @@ impl Interpreter {
     /// Get value of binding in global environment.
     ///
     /// * `name`: Name to lookup. It is possible to bind names which are not valid
-    ///           `jibi` symbols, they will not be accessible in `jibi` code.
+    ///   `jibi` symbols, they will not be accessible in `jibi` code.
     pub fn lookup(&self, name: &str) -> Option<JValRef> {
         self.globals.lookup(name)
     }
@@ -162,6 +250,22 @@ impl Interpreter {
     pub fn error(&mut self, kind: JErrorKind, reason: &str) -> JValRef {
         self.state.error(kind, reason)
     }
+    /// Construct a `jibi` `foreign` value wrapping a host object.
+    ///
+    /// The wrapped object can be retrieved with [`JVal::downcast_foreign`].
+    pub fn foreign<T: 'static>(&mut self, ftype: &Rc<JForeignType>, obj: T) -> JValRef {
+        self.state.foreign(ftype, obj)
+    }
+    /// Convert a Rust value to a `jibi` value, see [`Serializer`](crate::Serializer).
+    #[cfg(feature = "serde")]
+    pub fn to_jval<T: serde::Serialize + ?Sized>(&mut self, value: &T) -> JResult {
+        crate::to_jval(value, &mut self.state)
+    }
+    /// Convert a `jibi` value to a Rust value, see [`Deserializer`](crate::Deserializer).
+    #[cfg(feature = "serde")]
+    pub fn from_jval<T: serde::de::DeserializeOwned>(&self, val: JValRef) -> Result<T, JError> {
+        crate::from_jval(val)
+    }
     /// Construct a `jibi` `lambda`.
     pub fn lambda(&mut self, params: Vec<String>, body: Vec<JValRef>) -> JResult {
         self.state
@@ -31,6 +31,54 @@
 //! an `error` created in `jibi` but not raised, and in [`Err`]`(`[`JError`]`)`
 //! when it is raised.
 //!
+//! ## Foreign Values
+//! Host objects can be handed to `jibi` code as `foreign` values ([`JVal::Foreign`]).
+//! A [`JForeignType`] gives them a type name, an optional `repr`, and methods callable
+//! from `jibi` with `foreign-call`. The wrapped object is retrieved with
+//! [`JVal::downcast_foreign`].
+//!
+//! ```
+//! use jibi::{Interpreter, JForeignType};
+//!
+//! struct Config { name: String }
+//!
+//! let mut jibi = Interpreter::default();
+//! let config_type = JForeignType::new("Config")
+//!     .with_method("name", |c: &Config, _args, state| Ok(state.string(c.name.clone())))
+//!     .into_ref();
+//! let config = jibi.foreign(&config_type, Config { name: "prod".to_string() });
+//! jibi.def("config", config);
+//! let res = jibi.eval_str("config.rs", "(foreign-call config 'name)").unwrap().unwrap();
+//! assert_eq!("prod", res.to_str().unwrap());
+//! ```
+//!
+//! ## Streaming Input
+//! [`Interpreter::eval_reader`] reads a program incrementally from any
+//! [`BufRead`](std::io::BufRead) with a [`StreamTokenizer`], evaluating each
+//! top-level form as soon as it is complete, e.g. from a pipe or a socket.
+//!
+//! ```
+//! use jibi::Interpreter;
+//!
+//! let mut jibi = Interpreter::default();
+//! let input = std::io::Cursor::new("(def x 20)\n(+ x 1)\n");
+//! let res = jibi.eval_reader("input", input).unwrap().unwrap();
+//! assert_eq!(21, res.to_int().unwrap());
+//! ```
+//!
+//! ## Serde
+//! With the `serde` feature, Rust values implementing `Serialize` and `Deserialize`
+//! can be converted to and from `jibi` values with `Interpreter::to_jval` and
+//! `Interpreter::from_jval`. Structs are represented as association lists, see
+//! `Serializer` for the full mapping and its limits.
+//!
+//! ## Records
+//! Rust types implementing [`ToJVal`] and [`FromJVal`] can be passed to and from
+//! `jibi`. With the `derive` feature, `#[derive(JibiValue)]` implements them for structs
+//! and enums, represented as records (lists tagged by a type symbol), and implements
+//! [`JibiRecord::register`] to define constructor, predicate and accessor builtins
+//! for the record.
+//!
 //! ## Example
 //! ```
 //! use jibi::Interpreter;
@@ -47,15 +95,32 @@
 //! println!("{}", res);
 //! ```
 mod apply;
+pub mod bench;
 mod builtin;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod convert;
+pub mod coverage;
+pub mod dap;
+pub mod debugger;
 mod env;
 mod error;
 mod eval;
+mod formatter;
+mod image;
 mod import;
 mod interpreter;
+pub mod lint;
+pub mod lsp;
+pub mod profiler;
+pub mod protocol;
 mod reader;
 mod repr;
+#[cfg(feature = "serde")]
+mod serialize;
 mod state;
+pub mod testing;
+mod trace;
 mod traceback;
 mod types;
 
@@ -64,16 +129,25 @@ use repr::repr;
 use types::*;
 
 // Exports
+pub use convert::{FromJVal, JibiRecord, ToJVal};
 pub use env::{JEnv, JEnvRef};
 pub(crate) use error::JErrorKind::*;
 pub use error::{JError, JErrorKind, JResult};
+pub use formatter::format_source;
 pub use interpreter::{Interpreter, PRELUDE};
+#[cfg(feature = "derive")]
+pub use jibi_derive::JibiValue;
+pub use reader::cst::{CstKind, CstNode, Span};
 pub use reader::parser::Parser;
-pub use reader::readermacro::{ReaderMacro, TokenMatcher};
+pub use reader::readermacro::{ReaderMacro, TokenMatcher, TokenRegex};
+pub use reader::readtable::{CharMacro, CharStream, Readtable};
 pub use reader::tokenizer::*;
 pub use reader::PositionTag;
+#[cfg(feature = "serde")]
+pub use serialize::{from_jval, to_jval, Deserializer, Serializer};
 pub use state::JState;
 pub use traceback::TracebackFrame;
+pub use types::foreign::{JForeign, JForeignMethodFn, JForeignReprFn, JForeignType};
 pub use types::{JPair, JVal, JValRef};
 
 pub type JException = (PositionTag, JError, Vec<TracebackFrame>);
@@ -0,0 +1,789 @@
+//! Static checks of source files, without evaluating them.
+//!
+//! Names are resolved against the globals of an interpreter (builtins and prelude),
+//! the definitions of the file and the top level definitions of the modules it
+//! imports. Arguments of macros are only checked for undefined symbols when they
+//! are known to be evaluated, since a macro can bind names in any way.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use crate::import::find_module;
+use crate::reader::cst::{CstKind, CstNode, Span};
+use crate::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub pos: PositionTag,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{}: {}: {}", self.pos, self.severity, self.message)
+    }
+}
+
+/// What is known of the value of a global.
+#[derive(Debug, Clone)]
+pub(crate) enum Def {
+    Value,
+    Function(Option<JParams>),
+    Macro(Option<JParams>),
+}
+
+impl Def {
+    pub(crate) fn params(&self) -> Option<&JParams> {
+        match self {
+            Self::Value => None,
+            Self::Function(params) | Self::Macro(params) => params.as_ref(),
+        }
+    }
+}
+
+/// Number of arguments of the special forms: name, required, variadic.
+const SPECIAL_FORMS: &[(&str, usize, bool)] = &[
+    ("and", 2, false),
+    ("cond", 0, true),
+    ("def", 2, false),
+    ("fn", 1, true),
+    ("import", 3, false),
+    ("macro", 1, true),
+    ("nfn", 2, true),
+    ("nmacro", 2, true),
+    ("or", 2, false),
+    ("quote", 1, false),
+    ("set!", 2, false),
+    ("try", 2, false),
+];
+
+/// Forms which evaluate (some of) their arguments in a new env, or not at all.
+const SCOPE_FORMS: &[&str] = &[
+    "defmacro",
+    "defn",
+    "defnglobal",
+    "fn",
+    "let",
+    "macro",
+    "nfn",
+    "nmacro",
+    "quote",
+];
+
+/// Macros and special forms whose arguments are all evaluated.
+const EXPR_FORMS: &[&str] = &["and", "assert-raise", "if", "or", "test", "test-suite"];
+
+pub(crate) fn ident(node: &CstNode) -> Option<&str> {
+    match &node.kind {
+        CstKind::Atom(TokenValue::Ident(name)) => Some(name),
+        _ => None,
+    }
+}
+
+pub(crate) fn string(node: &CstNode) -> Option<&str> {
+    match &node.kind {
+        CstKind::Atom(TokenValue::String(s)) => Some(s),
+        _ => None,
+    }
+}
+
+/// Name and items of a list starting with a symbol.
+pub(crate) fn form(node: &CstNode) -> Option<(&str, Vec<&CstNode>)> {
+    if node.kind != CstKind::List {
+        return None;
+    }
+    let items: Vec<&CstNode> = node.items().collect();
+    Some((ident(items.first()?)?, items))
+}
+
+fn params(node: Option<&&CstNode>) -> Option<JParams> {
+    let node = node?;
+    if node.kind != CstKind::List {
+        return None;
+    }
+    let names = node
+        .items()
+        .map(|n| ident(n).map(|s| s.to_string()))
+        .collect::<Option<Vec<String>>>()?;
+    JParams::new(names).ok()
+}
+
+fn value_def(node: Option<&&CstNode>) -> Def {
+    match node.and_then(|n| form(n)) {
+        Some(("fn", items)) => Def::Function(params(items.get(1))),
+        Some(("nfn", items)) => Def::Function(params(items.get(2))),
+        Some(("macro", items)) => Def::Macro(params(items.get(1))),
+        Some(("nmacro", items)) => Def::Macro(params(items.get(2))),
+        _ => Def::Value,
+    }
+}
+
+/// A name defined by a form.
+pub(crate) struct Definition<'a> {
+    pub(crate) name: &'a CstNode,
+    pub(crate) form: &'a CstNode,
+    pub(crate) def: Def,
+    /// Defined in the global env rather than the current one.
+    pub(crate) global: bool,
+    /// Module path of `import-from`.
+    pub(crate) module: Option<&'a str>,
+}
+
+impl<'a> Definition<'a> {
+    pub(crate) fn name(&self) -> &'a str {
+        ident(self.name).unwrap()
+    }
+    /// Docstring of a procedure or macro: a string followed by other expressions
+    /// at the start of its body.
+    pub(crate) fn doc(&self) -> Option<&'a str> {
+        let (name, items) = form(self.form)?;
+        let (items, start) = match name {
+            "defn" | "defnglobal" | "defmacro" => (items, 3),
+            "def" | "defglobal" => match items.get(2).and_then(|n| form(n)) {
+                Some(("fn" | "macro", items)) => (items, 2),
+                Some(("nfn" | "nmacro", items)) => (items, 3),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        match items.get(start) {
+            Some(node) if items.len() > start + 1 => string(node),
+            _ => None,
+        }
+    }
+}
+
+/// Names defined by a form, when evaluated.
+pub(crate) fn definitions(node: &CstNode) -> Vec<Definition<'_>> {
+    let (name, items) = match form(node) {
+        Some(form) => form,
+        None => return vec![],
+    };
+    fn def<'a>(
+        form: &'a CstNode,
+        name: Option<&&'a CstNode>,
+        def: Def,
+        global: bool,
+    ) -> Option<Definition<'a>> {
+        name.filter(|n| ident(n).is_some()).map(|name| Definition {
+            name,
+            form,
+            def,
+            global,
+            module: None,
+        })
+    }
+    let definition = match name {
+        "def" => def(node, items.get(1), value_def(items.get(2)), false),
+        "defglobal" => def(node, items.get(1), value_def(items.get(2)), true),
+        "defn" => def(
+            node,
+            items.get(1),
+            Def::Function(params(items.get(2))),
+            false,
+        ),
+        "defnglobal" => def(
+            node,
+            items.get(1),
+            Def::Function(params(items.get(2))),
+            true,
+        ),
+        "defmacro" => def(node, items.get(1), Def::Macro(params(items.get(2))), false),
+        "deferror" => {
+            let params = JParams::Fixed(vec!["reason".to_string()]);
+            def(node, items.get(1), Def::Function(Some(params)), false)
+        }
+        "import" => def(node, items.get(3), Def::Value, false),
+        "import-from" | "use" => {
+            let module = items.get(1).and_then(|n| string(n));
+            return items
+                .iter()
+                .skip(2)
+                .filter_map(|n| def(node, Some(n), Def::Value, true))
+                .map(|d| Definition { module, ..d })
+                .collect();
+        }
+        _ => None,
+    };
+    definition.into_iter().collect()
+}
+
+/// Definitions of a form and its subforms evaluated in the same env.
+pub(crate) fn scope_definitions<'a>(node: &'a CstNode, defs: &mut Vec<Definition<'a>>) {
+    defs.extend(definitions(node));
+    match form(node) {
+        Some((name, _)) if SCOPE_FORMS.contains(&name) => (),
+        _ if node.kind == CstKind::List => {
+            for item in node.items() {
+                scope_definitions(item, defs);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Global definitions anywhere in the tree.
+pub(crate) fn global_definitions<'a>(node: &'a CstNode, defs: &mut Vec<Definition<'a>>) {
+    if matches!(node.kind, CstKind::Quote | CstKind::Quasiquote) || node.is_trivia() {
+        return;
+    }
+    defs.extend(definitions(node).into_iter().filter(|d| d.global));
+    for child in &node.children {
+        global_definitions(child, defs);
+    }
+}
+
+/// Top level definitions of a module.
+pub(crate) struct Module {
+    pub(crate) path: PathBuf,
+    pub(crate) source: String,
+    pub(crate) defs: HashMap<String, Def>,
+    /// Span of the name and docstring of definitions.
+    pub(crate) locations: HashMap<String, (Span, Option<String>)>,
+}
+
+/// A module read by a [`Linter`], with the modification time of its file.
+type CachedModule = (Option<SystemTime>, Option<Rc<Module>>);
+
+/// Static checker, see the [module docs](self).
+pub struct Linter {
+    globals: HashMap<String, Def>,
+    modules: RefCell<HashMap<PathBuf, CachedModule>>,
+}
+
+impl Default for Linter {
+    /// Check against the globals of a default interpreter.
+    fn default() -> Self {
+        Self::new(&Interpreter::default())
+    }
+}
+
+impl Linter {
+    /// Check against the globals of `interpreter`.
+    pub fn new(interpreter: &Interpreter) -> Self {
+        let mut globals = HashMap::new();
+        for (name, val) in interpreter.globals().bindings() {
+            let def = match &*val {
+                JVal::Lambda(l) => Def::Function(Some(l.params.clone())),
+                JVal::Macro(l) => Def::Macro(Some(l.params.clone())),
+                JVal::Builtin(_) => Def::Function(None),
+                JVal::SpecialForm(_) => {
+                    Def::Macro(SPECIAL_FORMS.iter().find(|(n, _, _)| *n == name).map(
+                        |(_, nargs, variadic)| {
+                            let params = vec!["_".to_string(); *nargs];
+                            match variadic {
+                                true => JParams::Variadic(params, "_".to_string()),
+                                false => JParams::Fixed(params),
+                            }
+                        },
+                    ))
+                }
+                _ => Def::Value,
+            };
+            globals.insert(name, def);
+        }
+        Self {
+            globals,
+            modules: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Check a source file.
+    pub fn check_file<P: AsRef<Path>>(&self, path: P) -> Result<Vec<Diagnostic>, JError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| JError::new(OsError, &format!("{}: {}", path.display(), e)))?;
+        Ok(self.check_source(&path.to_string_lossy(), &source))
+    }
+
+    /// Check source code, diagnostics are sorted by position.
+    pub fn check_source(&self, filename: &str, source: &str) -> Vec<Diagnostic> {
+        let root = match Parser::parse_cst(filename, source, &mut JState::new()) {
+            Ok(root) => root,
+            Err(pe) => {
+                return vec![Diagnostic {
+                    pos: pe.pos,
+                    severity: Severity::Error,
+                    message: pe.reason,
+                }]
+            }
+        };
+        let mut checker = Checker {
+            linter: self,
+            globals: HashMap::new(),
+            modules: HashMap::new(),
+            scopes: vec![],
+            diagnostics: vec![],
+        };
+        checker.root(&root);
+        let mut diagnostics = checker.diagnostics;
+        diagnostics.sort_by_key(|d| (d.pos.lineno, d.pos.col));
+        diagnostics.dedup();
+        diagnostics
+    }
+
+    /// Find and read a module the way `import` does, `None` if it cannot be found
+    /// or parsed. Modules are read again when their file is modified.
+    pub(crate) fn module(&self, name: &str) -> Option<Rc<Module>> {
+        let path = find_module(format!("{}.jibi", name))?;
+        let mtime = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+        if let Some((modified, module)) = self.modules.borrow().get(&path) {
+            if *modified == mtime && mtime.is_some() {
+                return module.clone();
+            }
+        }
+        let module = std::fs::read_to_string(&path).ok().and_then(|source| {
+            let root =
+                Parser::parse_cst(&path.to_string_lossy(), &source, &mut JState::new()).ok()?;
+            let mut defs = vec![];
+            for node in root.items() {
+                scope_definitions(node, &mut defs);
+            }
+            global_definitions(&root, &mut defs);
+            let locations = defs
+                .iter()
+                .map(|d| {
+                    let doc = d.doc().map(|s| s.to_string());
+                    (d.name().to_string(), (d.name.span.clone(), doc))
+                })
+                .collect();
+            let defs = defs
+                .into_iter()
+                .map(|d| (d.name().to_string(), d.def))
+                .collect();
+            Some(Rc::new(Module {
+                path: path.clone(),
+                source,
+                defs,
+                locations,
+            }))
+        });
+        self.modules
+            .borrow_mut()
+            .insert(path, (mtime, module.clone()));
+        module
+    }
+}
+
+struct Binding {
+    name: String,
+    pos: PositionTag,
+    used: bool,
+    check_unused: bool,
+}
+
+struct Checker<'a> {
+    linter: &'a Linter,
+    /// Definitions of the file.
+    globals: HashMap<String, Def>,
+    /// Imported modules, by name.
+    modules: HashMap<String, Rc<Module>>,
+    scopes: Vec<Vec<Binding>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker<'_> {
+    fn report(&mut self, node: &CstNode, severity: Severity, message: String) {
+        self.diagnostics.push(Diagnostic {
+            pos: node.span.pos.clone(),
+            severity,
+            message,
+        });
+    }
+    fn error(&mut self, node: &CstNode, message: String) {
+        self.report(node, Severity::Error, message)
+    }
+    fn warning(&mut self, node: &CstNode, message: String) {
+        self.report(node, Severity::Warning, message)
+    }
+
+    fn is_local(&self, name: &str) -> bool {
+        self.scopes.iter().flatten().any(|b| b.name == name)
+    }
+    fn global(&self, name: &str) -> Option<&Def> {
+        self.globals
+            .get(name)
+            .or_else(|| self.linter.globals.get(name))
+    }
+    /// Mark a local as used, or find what is known of a global.
+    fn lookup(&mut self, name: &str) -> Option<Option<Def>> {
+        for binding in self
+            .scopes
+            .iter_mut()
+            .rev()
+            .flat_map(|s| s.iter_mut().rev())
+        {
+            if binding.name == name {
+                binding.used = true;
+                return Some(None);
+            }
+        }
+        self.global(name).map(|def| Some(def.clone()))
+    }
+    /// Resolve a symbol, including `module::name` references.
+    fn resolve(&mut self, node: &CstNode, name: &str, lenient: bool) -> Option<Def> {
+        let mut parts = name.split("::");
+        let first = parts.next().unwrap_or(name);
+        let (first, member) = match parts.next() {
+            Some(member) if !first.is_empty() => (first, Some(member)),
+            _ => (name, None),
+        };
+        let def = match self.lookup(first) {
+            Some(def) => def,
+            None => {
+                if !lenient {
+                    self.error(node, format!("undefined symbol `{}`", first));
+                }
+                return None;
+            }
+        };
+        let member = match member {
+            Some(member) => member,
+            None => return def,
+        };
+        let module = match self.modules.get(first) {
+            Some(module) if !self.is_local(first) => module,
+            _ => return None,
+        };
+        match module.defs.get(member) {
+            Some(def) => Some(def.clone()),
+            None => {
+                let message = format!("`{}` is not defined in module `{}`", member, first);
+                self.error(node, message);
+                None
+            }
+        }
+    }
+
+    fn shadows_global(&mut self, node: &CstNode, name: &str) {
+        if self.linter.globals.contains_key(name) {
+            self.warning(node, format!("`{}` shadows a global", name));
+        }
+    }
+    fn bind(&mut self, node: &CstNode, check_unused: bool) {
+        let name = match ident(node) {
+            Some(name) => name,
+            None => return,
+        };
+        if name != "." {
+            self.shadows_global(node, name);
+        }
+        self.scopes.last_mut().unwrap().push(Binding {
+            name: name.to_string(),
+            pos: node.span.pos.clone(),
+            used: false,
+            check_unused,
+        });
+    }
+    fn push_scope(&mut self) {
+        self.scopes.push(vec![]);
+    }
+    fn pop_scope(&mut self) {
+        for b in self.scopes.pop().unwrap() {
+            if b.check_unused && !b.used && !b.name.starts_with('_') {
+                self.diagnostics.push(Diagnostic {
+                    pos: b.pos,
+                    severity: Severity::Warning,
+                    message: format!("unused binding `{}`", b.name),
+                });
+            }
+        }
+    }
+
+    fn import_module(&mut self, node: &CstNode) -> Option<Rc<Module>> {
+        let name = string(node)?;
+        let module = self.linter.module(name);
+        if module.is_none() {
+            self.error(node, format!("module not found: {}", name));
+        }
+        module
+    }
+    fn check_members(&mut self, module: Option<Rc<Module>>, names: &[&CstNode], modname: &str) {
+        let module = match module {
+            Some(module) => module,
+            None => return,
+        };
+        for node in names {
+            match ident(node) {
+                Some(name) if !module.defs.contains_key(name) => {
+                    let message = format!("`{}` is not defined in module {}", name, modname);
+                    self.error(node, message);
+                }
+                Some(_) => (),
+                None => self.error(node, "expected a symbol".to_string()),
+            }
+        }
+    }
+
+    fn root(&mut self, root: &CstNode) {
+        self.globals.insert("#FILE".to_string(), Def::Value);
+        let mut defs = vec![];
+        for node in root.items() {
+            scope_definitions(node, &mut defs);
+        }
+        defs.retain(|d| !d.global);
+        global_definitions(root, &mut defs);
+        for d in defs {
+            let name = ident(d.name).unwrap();
+            if !d.global {
+                self.shadows_global(d.name, name);
+            }
+            let module = d.module.and_then(|m| self.linter.module(m));
+            let def = match module.and_then(|m| m.defs.get(name).cloned()) {
+                Some(def) => def,
+                None => d.def,
+            };
+            self.globals.insert(name.to_string(), def);
+        }
+        // Module names
+        for node in root.items() {
+            if let Some(("import", items)) = form(node) {
+                let module = items.get(1).and_then(|n| string(n));
+                let name = items.get(3).and_then(|n| ident(n));
+                if let (Some(module), Some(name)) = (module, name) {
+                    if let Some(module) = self.linter.module(module) {
+                        self.modules.insert(name.to_string(), module);
+                    }
+                }
+            }
+        }
+        for node in root.items() {
+            self.expr(node, false);
+        }
+    }
+
+    fn expr(&mut self, node: &CstNode, lenient: bool) {
+        match &node.kind {
+            CstKind::Atom(TokenValue::Ident(name)) => {
+                self.resolve(node, name, lenient);
+            }
+            CstKind::List => self.list(node, lenient),
+            CstKind::Quasiquote => {
+                for item in node.items() {
+                    self.quasiquote(item, 1, lenient);
+                }
+            }
+            CstKind::Unquote | CstKind::UnquoteSplice => {
+                self.error(node, "unquote outside of quasiquote".to_string())
+            }
+            _ => (),
+        }
+    }
+
+    fn quasiquote(&mut self, node: &CstNode, level: usize, lenient: bool) {
+        match node.kind {
+            CstKind::Unquote | CstKind::UnquoteSplice if level == 1 => {
+                for item in node.items() {
+                    self.expr(item, lenient);
+                }
+            }
+            CstKind::Unquote | CstKind::UnquoteSplice => {
+                for item in node.items() {
+                    self.quasiquote(item, level - 1, lenient);
+                }
+            }
+            CstKind::Quasiquote => {
+                for item in node.items() {
+                    self.quasiquote(item, level + 1, lenient);
+                }
+            }
+            _ => {
+                for item in node.items() {
+                    self.quasiquote(item, level, lenient);
+                }
+            }
+        }
+    }
+
+    fn list(&mut self, node: &CstNode, lenient: bool) {
+        let items: Vec<&CstNode> = node.items().collect();
+        let (head, args) = match items.split_first() {
+            Some(split) => split,
+            None => return,
+        };
+        let name = match ident(head) {
+            Some(name) => name,
+            None => {
+                for item in &items {
+                    self.expr(item, lenient);
+                }
+                return;
+            }
+        };
+        let local = self.is_local(name);
+        let def = self.resolve(head, name, lenient);
+        if let Some(params) = def.as_ref().and_then(Def::params) {
+            if !params.accepts(args.len()) {
+                let message = format!(
+                    "`{}` expects {} argument(s), got {}",
+                    name,
+                    params.nargs_long(),
+                    args.len()
+                );
+                self.error(head, message);
+            } else if !local && !self.globals.contains_key(name) && self.special_form(name, args) {
+                return;
+            }
+        }
+        let lenient = lenient || matches!(def, Some(Def::Macro(_))) && !EXPR_FORMS.contains(&name);
+        for arg in args {
+            self.expr(arg, lenient);
+        }
+    }
+
+    /// Check a form with known arity, returns false if it is a regular call.
+    fn special_form(&mut self, name: &str, args: &[&CstNode]) -> bool {
+        let rest = |i: usize| args.get(i..).unwrap_or_default();
+        match (name, args.first().copied(), args.get(1).copied()) {
+            ("quote", _, _) => (),
+            ("def" | "defglobal" | "set!" | "setglobal!", Some(target), Some(value)) => {
+                if ident(target).is_none() {
+                    self.error(target, "expected a symbol".to_string());
+                } else if name.starts_with("set") {
+                    self.expr(target, false);
+                }
+                self.expr(value, false);
+            }
+            ("defn" | "defnglobal" | "defmacro", _, Some(params)) => self.lambda(params, rest(2)),
+            ("fn" | "macro", Some(params), _) => self.lambda(params, rest(1)),
+            ("nfn" | "nmacro", Some(name), Some(params)) => {
+                self.expr(name, false);
+                self.lambda(params, rest(2));
+            }
+            ("let", Some(_), _) => self.let_form(args),
+            ("cond", _, _) => self.cond(args),
+            ("try", Some(body), Some(handler)) => {
+                self.expr(body, false);
+                self.push_scope();
+                self.scopes.last_mut().unwrap().push(Binding {
+                    name: "err".to_string(),
+                    pos: handler.span.pos.clone(),
+                    used: false,
+                    check_unused: false,
+                });
+                self.expr(handler, false);
+                self.pop_scope();
+            }
+            ("import", Some(module), Some(as_)) => {
+                if ident(as_) != Some("as") || args.get(2).and_then(|n| ident(n)).is_none() {
+                    self.error(as_, "expected `as` and a module name".to_string());
+                }
+                if string(module).is_some() {
+                    self.import_module(module);
+                } else {
+                    self.expr(module, false);
+                }
+            }
+            ("import-from", Some(module), _) if string(module).is_some() => {
+                let imported = self.import_module(module);
+                self.check_members(imported, rest(1), string(module).unwrap());
+            }
+            ("use", Some(module), _) => {
+                self.expr(module, false);
+                let imported = match ident(module) {
+                    Some(m) if !self.is_local(m) => self.modules.get(m).cloned(),
+                    _ => None,
+                };
+                self.check_members(imported, rest(1), &module.to_string());
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn lambda(&mut self, params: &CstNode, body: &[&CstNode]) {
+        self.push_scope();
+        if params.kind == CstKind::List && params.items().all(|n| ident(n).is_some()) {
+            for param in params.items() {
+                self.bind(param, false);
+            }
+        } else {
+            self.error(params, "invalid parameter list".to_string());
+        }
+        self.body(body);
+        self.pop_scope();
+    }
+
+    fn body(&mut self, body: &[&CstNode]) {
+        let mut defs = vec![];
+        for node in body {
+            scope_definitions(node, &mut defs);
+        }
+        for d in defs {
+            if !d.global {
+                self.bind(d.name, true);
+            }
+        }
+        for node in body {
+            self.expr(node, false);
+        }
+    }
+
+    fn let_form(&mut self, args: &[&CstNode]) {
+        let (bindings, body) = match args.split_first() {
+            Some(split) => split,
+            None => return,
+        };
+        self.push_scope();
+        if bindings.kind == CstKind::List {
+            for binding in bindings.items() {
+                let items: Vec<&CstNode> = binding.items().collect();
+                match items[..] {
+                    [name, val] if binding.kind == CstKind::List && ident(name).is_some() => {
+                        self.expr(val, false);
+                        self.bind(name, true);
+                    }
+                    _ => self.error(binding, "invalid let binding".to_string()),
+                }
+            }
+        } else {
+            self.error(bindings, "invalid let bindings".to_string());
+        }
+        self.body(body);
+        self.pop_scope();
+    }
+
+    fn cond(&mut self, clauses: &[&CstNode]) {
+        let mut reachable = true;
+        for clause in clauses {
+            let items: Vec<&CstNode> = clause.items().collect();
+            if clause.kind != CstKind::List || items.is_empty() {
+                self.error(clause, "invalid cond clause".to_string());
+                continue;
+            }
+            if !reachable {
+                self.warning(clause, "unreachable cond clause".to_string());
+            }
+            let always = match &items[0].kind {
+                CstKind::Atom(TokenValue::Bool(b)) => *b,
+                CstKind::Atom(TokenValue::Ident(name)) => {
+                    matches!(name.as_str(), "else" | "true") && !self.is_local(name)
+                }
+                _ => false,
+            };
+            for item in items {
+                self.expr(item, false);
+            }
+            reachable = reachable && !always;
+        }
+    }
+}
@@ -0,0 +1,626 @@
+//! Language server for `jibi` source files, speaking the
+//! [language server protocol](https://microsoft.github.io/language-server-protocol/)
+//! with JSON-RPC messages over stdio.
+//!
+//! Supports diagnostics (parse errors and [lint] warnings), go to
+//! definition, hover, completion and document symbols. Documents are analyzed
+//! without being evaluated.
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::lint::{definitions, form, ident, string, Def, Definition, Linter, Module, Severity};
+use crate::protocol::{read_message, write_message, Json};
+use crate::reader::cst::{CstKind, CstNode, Span};
+use crate::*;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let mut bytes = vec![];
+    let mut chars = path.bytes();
+    while let Some(b) = chars.next() {
+        if b == b'%' {
+            let hex: Vec<u8> = chars.by_ref().take(2).collect();
+            let hex = std::str::from_utf8(&hex).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = "file://".to_string();
+    for b in path.to_string_lossy().bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(b as char)
+            }
+            b => uri.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    uri
+}
+
+/// Source text with conversions between byte offsets and LSP positions (line and
+/// UTF-16 column, from 0).
+struct Document<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> Document<'a> {
+    fn new(text: &'a str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+        Self { text, line_starts }
+    }
+    fn position(&self, offset: usize) -> Json {
+        let line = self.line_starts.partition_point(|&s| s <= offset) - 1;
+        let start = self.line_starts[line];
+        let character = self.text[start..offset.min(self.text.len())]
+            .encode_utf16()
+            .count();
+        Json::object(vec![("line", line.into()), ("character", character.into())])
+    }
+    fn offset(&self, position: &Json) -> Option<usize> {
+        let line = position["line"].as_u64()? as usize;
+        let character = position["character"].as_u64()? as usize;
+        let start = *self.line_starts.get(line)?;
+        let mut units = 0;
+        for (i, c) in self.text[start..].char_indices() {
+            if units >= character || c == '\n' {
+                return Some(start + i);
+            }
+            units += c.len_utf16();
+        }
+        Some(self.text.len())
+    }
+    fn range(&self, start: usize, end: usize) -> Json {
+        Json::object(vec![
+            ("start", self.position(start)),
+            ("end", self.position(end)),
+        ])
+    }
+    fn span_range(&self, span: &Span) -> Json {
+        self.range(span.start, span.end)
+    }
+    /// Range of the token at a position of the tokenizer (line and byte column from 1).
+    fn token_range(&self, pos: &PositionTag) -> Json {
+        let line = pos.lineno.saturating_sub(1).min(self.line_starts.len() - 1);
+        let start = (self.line_starts[line] + pos.col.saturating_sub(1)).min(self.text.len());
+        let len = self.text[start..]
+            .find(|c: char| c.is_whitespace() || "()\"'`,".contains(c))
+            .unwrap_or(self.text.len() - start);
+        self.range(start, start + len.max(1).min(self.text.len() - start))
+    }
+}
+
+fn signature(name: &str, def: &Def) -> String {
+    match def.params() {
+        Some(JParams::Fixed(params)) if params.is_empty() => format!("({})", name),
+        Some(JParams::Fixed(params)) => format!("({} {})", name, params.join(" ")),
+        Some(JParams::Variadic(params, rest)) => {
+            let mut parts = vec![name.to_string()];
+            parts.extend(params.iter().cloned());
+            format!("({} . {})", parts.join(" "), rest)
+        }
+        None => name.to_string(),
+    }
+}
+
+fn hover_text(name: &str, def: &Def, kind: &str, doc: Option<&str>) -> String {
+    let mut text = format!("```jibi\n{}\n```\n{}", signature(name, def), kind);
+    if let Some(doc) = doc {
+        text.push_str("\n\n");
+        text.push_str(doc);
+    }
+    text
+}
+
+fn kind_name(def: &Def) -> &'static str {
+    match def {
+        Def::Value => "value",
+        Def::Function(_) => "procedure",
+        Def::Macro(_) => "macro",
+    }
+}
+
+/// Definitions anywhere in the tree, in source order.
+fn all_definitions<'a>(node: &'a CstNode, defs: &mut Vec<Definition<'a>>) {
+    if matches!(node.kind, CstKind::Quote | CstKind::Quasiquote) || node.is_trivia() {
+        return;
+    }
+    defs.extend(definitions(node));
+    for child in &node.children {
+        all_definitions(child, defs);
+    }
+}
+
+/// The identifier or string at or just before an offset.
+fn atom_at(root: &CstNode, offset: usize) -> Option<&CstNode> {
+    let before = offset.checked_sub(1);
+    [Some(offset), before]
+        .iter()
+        .flatten()
+        .filter_map(|&o| root.node_at(o))
+        .find(|n| ident(n).is_some() || string(n).is_some())
+}
+
+/// The module imported by a string in an `import` or `import-from` form.
+fn import_path_at<'a>(root: &'a CstNode, node: &CstNode) -> Option<&'a str> {
+    let mut imports = vec![];
+    find_forms(root, &["import", "import-from"], &mut imports);
+    imports
+        .into_iter()
+        .filter_map(|items| items.get(1).copied())
+        .find(|n| n.span == node.span)
+        .and_then(string)
+}
+
+fn find_forms<'a>(node: &'a CstNode, names: &[&str], found: &mut Vec<Vec<&'a CstNode>>) {
+    if let Some((name, items)) = form(node) {
+        if names.contains(&name) {
+            found.push(items);
+        }
+    }
+    if !matches!(node.kind, CstKind::Quote | CstKind::Quasiquote) {
+        for child in &node.children {
+            find_forms(child, names, found);
+        }
+    }
+}
+
+/// Language server state, see the [module docs](self).
+pub struct LanguageServer {
+    interpreter: Interpreter,
+    linter: Linter,
+    documents: HashMap<String, String>,
+    shutdown: bool,
+}
+
+impl Default for LanguageServer {
+    fn default() -> Self {
+        Self::new(Interpreter::default())
+    }
+}
+
+type RequestResult = Result<Json, (i64, String)>;
+
+impl LanguageServer {
+    /// Resolve globals in the environment of `interpreter`.
+    pub fn new(interpreter: Interpreter) -> Self {
+        Self {
+            linter: Linter::new(&interpreter),
+            interpreter,
+            documents: HashMap::new(),
+            shutdown: false,
+        }
+    }
+
+    /// Serve requests until the `exit` notification or the end of the input.
+    pub fn run<R: BufRead, W: Write>(&mut self, mut input: R, mut output: W) -> io::Result<()> {
+        while let Some(body) = read_message(&mut input)? {
+            let message = match Json::parse(&body) {
+                Ok(message) => message,
+                Err(e) => {
+                    write_message(&mut output, &error_response(Json::Null, PARSE_ERROR, e))?;
+                    continue;
+                }
+            };
+            let method = message["method"].as_str().unwrap_or("");
+            if method == "exit" {
+                break;
+            }
+            let params = &message["params"];
+            match message.get("id") {
+                Some(id) if !method.is_empty() => {
+                    let response = match self.request(method, params) {
+                        Ok(result) => Json::object(vec![
+                            ("jsonrpc", "2.0".into()),
+                            ("id", id.clone()),
+                            ("result", result),
+                        ]),
+                        Err((code, reason)) => error_response(id.clone(), code, reason),
+                    };
+                    write_message(&mut output, &response)?;
+                }
+                // Response to a request of the server
+                Some(_) => (),
+                None => {
+                    for notification in self.notification(method, params) {
+                        write_message(&mut output, &notification)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn request(&mut self, method: &str, params: &Json) -> RequestResult {
+        if self.shutdown {
+            return Err((INVALID_REQUEST, "server is shut down".to_string()));
+        }
+        match method {
+            "initialize" => Ok(Json::object(vec![
+                (
+                    "capabilities",
+                    Json::object(vec![
+                        ("textDocumentSync", 1.into()),
+                        ("hoverProvider", true.into()),
+                        ("definitionProvider", true.into()),
+                        (
+                            "completionProvider",
+                            Json::object(vec![("triggerCharacters", vec![":".into()].into())]),
+                        ),
+                        ("documentSymbolProvider", true.into()),
+                    ]),
+                ),
+                (
+                    "serverInfo",
+                    Json::object(vec![
+                        ("name", "jibi".into()),
+                        ("version", env!("CARGO_PKG_VERSION").into()),
+                    ]),
+                ),
+            ])),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Json::Null)
+            }
+            "textDocument/hover" => self.at_position(params, Self::hover),
+            "textDocument/definition" => self.at_position(params, Self::definition),
+            "textDocument/completion" => self.at_position(params, Self::completion),
+            "textDocument/documentSymbol" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+                let text = self.text(uri)?;
+                Ok(self.symbols(uri, &text))
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("unsupported method {}", method))),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        let text = match method {
+            "textDocument/didOpen" => params["textDocument"]["text"].as_str(),
+            // Full sync: the last change has the whole text
+            "textDocument/didChange" => params["contentChanges"]
+                .as_array()
+                .and_then(|changes| changes.last())
+                .and_then(|change| change["text"].as_str()),
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return vec![publish_diagnostics(uri, vec![])];
+            }
+            _ => None,
+        };
+        match text {
+            Some(text) => {
+                self.documents.insert(uri.to_string(), text.to_string());
+                vec![self.diagnostics(uri, text)]
+            }
+            None => vec![],
+        }
+    }
+
+    fn text(&self, uri: &str) -> Result<String, (i64, String)> {
+        match self.documents.get(uri) {
+            Some(text) => Ok(text.clone()),
+            None => uri_to_path(uri)
+                .and_then(|path| std::fs::read_to_string(path).ok())
+                .ok_or((INVALID_PARAMS, format!("unknown document {}", uri))),
+        }
+    }
+
+    fn filename(uri: &str) -> String {
+        match uri_to_path(uri) {
+            Some(path) => path.to_string_lossy().to_string(),
+            None => uri.to_string(),
+        }
+    }
+
+    fn at_position<F>(&self, params: &Json, f: F) -> RequestResult
+    where
+        F: Fn(&Self, &str, &Document, &CstNode, usize) -> Json,
+    {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        let text = self.text(uri)?;
+        let doc = Document::new(&text);
+        let offset = doc
+            .offset(&params["position"])
+            .ok_or((INVALID_PARAMS, "invalid position".to_string()))?;
+        match Parser::parse_cst(&Self::filename(uri), &text, &mut JState::new()) {
+            Ok(root) => Ok(f(self, uri, &doc, &root, offset)),
+            Err(_) => Ok(Json::Null),
+        }
+    }
+
+    fn diagnostics(&self, uri: &str, text: &str) -> Json {
+        let doc = Document::new(text);
+        let diagnostics = self
+            .linter
+            .check_source(&Self::filename(uri), text)
+            .into_iter()
+            .map(|d| {
+                let severity = match d.severity {
+                    Severity::Error => 1,
+                    Severity::Warning => 2,
+                };
+                Json::object(vec![
+                    ("range", doc.token_range(&d.pos)),
+                    ("severity", severity.into()),
+                    ("source", "jibi".into()),
+                    ("message", d.message.into()),
+                ])
+            })
+            .collect();
+        publish_diagnostics(uri, diagnostics)
+    }
+
+    /// Modules imported by the document, by name.
+    fn modules(&self, root: &CstNode) -> HashMap<String, Rc<Module>> {
+        let mut imports = vec![];
+        find_forms(root, &["import"], &mut imports);
+        imports
+            .into_iter()
+            .filter_map(|items| {
+                let path = string(items.get(1)?)?;
+                let name = ident(items.get(3)?)?;
+                Some((name.to_string(), self.linter.module(path)?))
+            })
+            .collect()
+    }
+
+    /// Definition of a member of an imported module, as `module::name`.
+    fn module_member(&self, root: &CstNode, name: &str) -> Option<(Rc<Module>, String)> {
+        let (module, member) = name.split_once("::")?;
+        let member = member.split("::").next()?;
+        let module = self.modules(root).remove(module)?;
+        module
+            .defs
+            .contains_key(member)
+            .then(|| (module, member.to_string()))
+    }
+
+    /// The definition of a name in the document closest before an offset.
+    fn document_definition<'a>(
+        root: &'a CstNode,
+        name: &str,
+        offset: usize,
+    ) -> Option<Definition<'a>> {
+        let mut defs = vec![];
+        all_definitions(root, &mut defs);
+        let mut defs: Vec<Definition> = defs.into_iter().filter(|d| d.name() == name).collect();
+        match defs.iter().rposition(|d| d.name.span.start <= offset) {
+            Some(i) => Some(defs.swap_remove(i)),
+            None if !defs.is_empty() => Some(defs.swap_remove(0)),
+            None => None,
+        }
+    }
+
+    fn global(&self, name: &str) -> Option<(Def, &'static str, Option<String>)> {
+        let doc = |code: &[JValRef]| match code {
+            [doc, _, ..] => match &**doc {
+                JVal::String(s) => Some(s.clone()),
+                _ => None,
+            },
+            _ => None,
+        };
+        let val = self.interpreter.lookup(name)?;
+        Some(match &*val {
+            JVal::Lambda(l) => (
+                Def::Function(Some(l.params.clone())),
+                "procedure",
+                doc(&l.code),
+            ),
+            JVal::Macro(l) => (Def::Macro(Some(l.params.clone())), "macro", doc(&l.code)),
+            JVal::Builtin(_) => (Def::Function(None), "builtin", None),
+            JVal::SpecialForm(_) => (Def::Macro(None), "special form", None),
+            _ => (Def::Value, "value", None),
+        })
+    }
+
+    fn hover(&self, _uri: &str, doc: &Document, root: &CstNode, offset: usize) -> Json {
+        let node = match atom_at(root, offset).filter(|n| ident(n).is_some()) {
+            Some(node) => node,
+            None => return Json::Null,
+        };
+        let name = ident(node).unwrap();
+        let text = if let Some((module, member)) = self.module_member(root, name) {
+            let (_, docstring) = &module.locations[&member];
+            let def = &module.defs[&member];
+            hover_text(name, def, kind_name(def), docstring.as_deref())
+        } else if let Some(d) = Self::document_definition(root, name, offset) {
+            hover_text(name, &d.def, kind_name(&d.def), d.doc())
+        } else if let Some((def, kind, docstring)) = self.global(name) {
+            hover_text(name, &def, kind, docstring.as_deref())
+        } else {
+            return Json::Null;
+        };
+        Json::object(vec![
+            (
+                "contents",
+                Json::object(vec![("kind", "markdown".into()), ("value", text.into())]),
+            ),
+            ("range", doc.span_range(&node.span)),
+        ])
+    }
+
+    fn module_location(&self, module: &Module, member: Option<&str>) -> Json {
+        let doc = Document::new(&module.source);
+        let range = match member.and_then(|m| module.locations.get(m)) {
+            Some((span, _)) => doc.span_range(span),
+            None => doc.range(0, 0),
+        };
+        Json::object(vec![
+            ("uri", path_to_uri(&module.path).into()),
+            ("range", range),
+        ])
+    }
+
+    fn definition(&self, uri: &str, doc: &Document, root: &CstNode, offset: usize) -> Json {
+        let node = match atom_at(root, offset) {
+            Some(node) => node,
+            None => return Json::Null,
+        };
+        if let Some(path) = import_path_at(root, node) {
+            return match self.linter.module(path) {
+                Some(module) => self.module_location(&module, None),
+                None => Json::Null,
+            };
+        }
+        let name = match ident(node) {
+            Some(name) => name,
+            None => return Json::Null,
+        };
+        if let Some((module, member)) = self.module_member(root, name) {
+            return self.module_location(&module, Some(&member));
+        }
+        match Self::document_definition(root, name, offset) {
+            Some(d) => match d.module.and_then(|m| self.linter.module(m)) {
+                Some(module) if module.defs.contains_key(name) => {
+                    self.module_location(&module, Some(name))
+                }
+                _ => Json::object(vec![
+                    ("uri", uri.into()),
+                    ("range", doc.span_range(&d.name.span)),
+                ]),
+            },
+            None => Json::Null,
+        }
+    }
+
+    fn completion(&self, _uri: &str, doc: &Document, root: &CstNode, offset: usize) -> Json {
+        let start = doc.text[..offset]
+            .rfind(|c: char| c.is_whitespace() || "()\"'`,;#".contains(c))
+            .map_or(0, |i| i + 1);
+        let prefix = &doc.text[start..offset];
+        let mut candidates: Vec<(String, usize, String)> = vec![];
+        let mut add = |name: String, def: &Def, kind: usize| {
+            if name.starts_with(prefix) {
+                let detail = signature(&name, def);
+                candidates.push((name, kind, detail));
+            }
+        };
+        let item_kind = |def: &Def| match def {
+            Def::Value => 6,
+            Def::Function(_) => 3,
+            Def::Macro(_) => 14,
+        };
+        if let Some((module, _)) = prefix.split_once("::") {
+            if let Some(m) = self.modules(root).get(module) {
+                for (name, def) in &m.defs {
+                    add(format!("{}::{}", module, name), def, item_kind(def));
+                }
+            }
+        } else {
+            for (name, _) in self.interpreter.globals().bindings() {
+                if let Some((def, _, _)) = self.global(&name) {
+                    add(name, &def, item_kind(&def));
+                }
+            }
+            let mut defs = vec![];
+            all_definitions(root, &mut defs);
+            for d in defs {
+                add(d.name().to_string(), &d.def, item_kind(&d.def));
+            }
+            for name in self.modules(root).keys() {
+                add(name.clone(), &Def::Value, 9);
+            }
+        }
+        candidates.sort();
+        candidates.dedup_by(|a, b| a.0 == b.0);
+        let range = doc.range(start, offset);
+        let items = candidates
+            .into_iter()
+            .map(|(name, kind, detail)| {
+                Json::object(vec![
+                    ("label", name.clone().into()),
+                    ("kind", kind.into()),
+                    ("detail", detail.into()),
+                    (
+                        "textEdit",
+                        Json::object(vec![("range", range.clone()), ("newText", name.into())]),
+                    ),
+                ])
+            })
+            .collect::<Vec<Json>>();
+        items.into()
+    }
+
+    fn symbols(&self, uri: &str, text: &str) -> Json {
+        let root = match Parser::parse_cst(&Self::filename(uri), text, &mut JState::new()) {
+            Ok(root) => root,
+            Err(_) => return Json::Null,
+        };
+        let doc = Document::new(text);
+        let defs: Vec<Definition> = root.items().flat_map(definitions).collect();
+        defs.iter()
+            .map(|d| document_symbol(&doc, d))
+            .collect::<Vec<Json>>()
+            .into()
+    }
+}
+
+fn document_symbol(doc: &Document, d: &Definition) -> Json {
+    let kind: usize = match (&d.def, form(d.form).map(|f| f.0)) {
+        (_, Some("import")) => 2,
+        (Def::Value, _) => 13,
+        _ => 12,
+    };
+    // Definitions in the body of procedures and macros
+    let mut children = vec![];
+    if let Some((_, items)) = form(d.form) {
+        let body = match &d.def {
+            Def::Function(_) | Def::Macro(_) => items.get(3..).unwrap_or(&[]),
+            Def::Value => &[],
+        };
+        for node in body {
+            children.extend(definitions(node).iter().map(|c| document_symbol(doc, c)));
+        }
+    }
+    Json::object(vec![
+        ("name", d.name().into()),
+        ("detail", signature(d.name(), &d.def).into()),
+        ("kind", kind.into()),
+        ("range", doc.span_range(&d.form.span)),
+        ("selectionRange", doc.span_range(&d.name.span)),
+        ("children", children.into()),
+    ])
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Json>) -> Json {
+    Json::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("method", "textDocument/publishDiagnostics".into()),
+        (
+            "params",
+            Json::object(vec![
+                ("uri", uri.into()),
+                ("diagnostics", diagnostics.into()),
+            ]),
+        ),
+    ])
+}
+
+fn error_response(id: Json, code: i64, message: String) -> Json {
+    Json::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("id", id),
+        (
+            "error",
+            Json::object(vec![
+                ("code", Json::Number(code as f64)),
+                ("message", message.into()),
+            ]),
+        ),
+    ])
+}
@@ -1,12 +1,22 @@
-use std::path::PathBuf;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use home::home_dir;
 use lazy_static::lazy_static;
 use rustyline::Editor;
+use structopt::clap::AppSettings;
 use structopt::StructOpt;
 
-use jibi::{Interpreter, Token, TokenError, TokenValidator};
+use jibi::bench::{discover as discover_benches, Baseline, BenchRunner};
+use jibi::coverage::Coverage;
+use jibi::dap::DebugAdapter;
+use jibi::debugger::{DebugConsole, Debugger};
+use jibi::lint::{Linter, Severity};
+use jibi::lsp::LanguageServer;
+use jibi::profiler::Profiler;
+use jibi::testing::{discover, junit, tap, ReportFormat, TestRunner};
+use jibi::{format_source, Interpreter, TokenError, TokenValidator, Tokenizer};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -24,60 +34,487 @@ lazy_static! {
 }
 
 #[derive(StructOpt, Debug)]
+#[structopt(setting = AppSettings::ArgsNegateSubcommands)]
 struct Opt {
+    /// Files to run, `-` to read a program from stdin
     #[structopt(parse(from_os_str))]
     files: Vec<PathBuf>,
     #[structopt(short, long)]
     interactive: bool,
     #[structopt(long)]
     stack_size_mb: Option<usize>,
+    /// Run files in the debugger, pausing at the first form; type h at the
+    /// debug> prompt for commands
+    #[structopt(long)]
+    debug: bool,
+    /// Print calls to, and results of, the procedures defined in files
+    #[structopt(long)]
+    trace: bool,
+    /// Profile running files and print calls, time and allocations by procedure
+    #[structopt(long)]
+    profile: bool,
+    /// Write the stacks of the profile in the folded format of flamegraph tools
+    #[structopt(long, parse(from_os_str))]
+    profile_output: Option<PathBuf>,
+    /// Record line coverage of running files and print it by file
+    #[structopt(long)]
+    coverage: bool,
+    /// Write the line coverage in the lcov format
+    #[structopt(long, parse(from_os_str))]
+    coverage_output: Option<PathBuf>,
+    /// Exit with an error if less than this percentage of lines is covered
+    #[structopt(long)]
+    coverage_min: Option<f64>,
+    /// Start from an image saved with --save-image instead of loading the prelude
+    #[structopt(long, parse(from_os_str))]
+    image: Option<PathBuf>,
+    /// Save an image of the interpreter after running files
+    #[structopt(long, parse(from_os_str))]
+    save_image: Option<PathBuf>,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+/// Subcommands, used when the first argument is one of their names; use `--`
+/// to run a file with the same name.
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Format source files in place, or stdin to stdout
+    Fmt {
+        /// Only list the files that are not formatted, exit with an error if any
+        #[structopt(long)]
+        check: bool,
+        #[structopt(parse(from_os_str))]
+        files: Vec<PathBuf>,
+    },
+    /// Report undefined symbols, arity mismatches and other mistakes, without
+    /// running the files
+    Check {
+        #[structopt(parse(from_os_str))]
+        files: Vec<PathBuf>,
+    },
+    /// Run test files: the given files, and the test-*.jibi files in the given
+    /// directories (default: the current directory)
+    Test {
+        /// Only run the tests whose name contains this string
+        #[structopt(short, long)]
+        filter: Option<String>,
+        /// Report format: text, tap or junit (default: $TEST_FORMAT, or text)
+        #[structopt(long)]
+        format: Option<ReportFormat>,
+        /// Write the report to a file; text results are still printed
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+        #[structopt(parse(from_os_str))]
+        paths: Vec<PathBuf>,
+    },
+    /// Run benchmark files: the given files, and the bench-*.jibi files in the
+    /// given directories (default: the current directory)
+    Bench {
+        /// Only run the benchmarks whose name contains this string
+        #[structopt(short, long)]
+        filter: Option<String>,
+        /// Warmup iterations of each benchmark, instead of their own
+        #[structopt(long)]
+        warmup: Option<usize>,
+        /// Timed iterations of each benchmark, instead of their own
+        #[structopt(long)]
+        iterations: Option<usize>,
+        /// Compare with a baseline, and fail if a benchmark regressed
+        #[structopt(long, parse(from_os_str))]
+        baseline: Option<PathBuf>,
+        /// Percentage by which a mean must exceed the baseline to regress
+        #[structopt(long, default_value = "10")]
+        threshold: f64,
+        /// Save the means of the benchmarks as a baseline
+        #[structopt(long, parse(from_os_str))]
+        save_baseline: Option<PathBuf>,
+        #[structopt(parse(from_os_str))]
+        paths: Vec<PathBuf>,
+    },
+    /// Run the language server, speaking the language server protocol on stdio
+    Lsp,
+    /// Run the debug adapter, speaking the debug adapter protocol on stdio
+    Dap,
 }
 
 fn main() {
-    let Opt {
-        files,
-        interactive,
-        stack_size_mb,
-    } = Opt::from_args();
+    let mut opt = Opt::from_args();
+    if let Some(command) = opt.command.take() {
+        let code = match command {
+            Command::Fmt { check, files } => fmt(check, &files),
+            Command::Check { files } => check(&files),
+            Command::Test {
+                filter,
+                format,
+                output,
+                paths,
+            } => test(filter.as_deref(), format, output.as_deref(), &paths),
+            Command::Bench {
+                filter,
+                warmup,
+                iterations,
+                baseline,
+                threshold,
+                save_baseline,
+                paths,
+            } => bench(
+                filter.as_deref(),
+                warmup,
+                iterations,
+                baseline.as_deref(),
+                threshold,
+                save_baseline.as_deref(),
+                &paths,
+            ),
+            Command::Lsp => lsp(),
+            Command::Dap => dap(),
+        };
+        std::process::exit(code);
+    }
 
-    match stack_size_mb {
+    match opt.stack_size_mb {
         // HACK: start new thread with the configured stack size
         // TODO: is there a way to modify stack size of current thread?
         Some(stack_size_mb) => {
             std::thread::Builder::new()
                 .stack_size(stack_size_mb * 1024 * 1024)
-                .spawn(move || run(files, interactive))
+                .spawn(move || run(opt))
                 .unwrap()
                 .join()
                 .unwrap();
         }
-        None => run(files, interactive),
+        None => run(opt),
+    }
+}
+
+/// Stop profiling, print the report and write the folded stacks as requested.
+fn write_profile(opt: &Opt, interpreter: &mut Interpreter) {
+    let profiler = match interpreter.take_profiler() {
+        Some(profiler) => profiler,
+        None => return,
+    };
+    if opt.profile {
+        eprint!("{}", profiler.report());
+    }
+    if let Some(path) = &opt.profile_output {
+        if let Err(e) = std::fs::write(path, profiler.folded()) {
+            eprintln!("could not write {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Stop recording coverage, print and write it as requested; false if below
+/// the minimum coverage.
+fn write_coverage(opt: &Opt, interpreter: &mut Interpreter) -> bool {
+    let coverage = match interpreter.take_coverage() {
+        Some(coverage) => coverage,
+        None => return true,
+    };
+    if opt.coverage {
+        eprint!("{}", coverage.report());
+    }
+    if let Some(path) = &opt.coverage_output {
+        if let Err(e) = std::fs::write(path, coverage.lcov()) {
+            eprintln!("could not write {}: {}", path.display(), e);
+        }
+    }
+    match opt.coverage_min {
+        Some(min) if coverage.percent() < min => {
+            eprintln!(
+                "coverage {:.1}% is below the minimum of {}%",
+                coverage.percent(),
+                min
+            );
+            false
+        }
+        _ => true,
     }
 }
 
-fn run(files: Vec<PathBuf>, interactive: bool) {
-    let mut interpreter = Interpreter::default();
+fn run(opt: Opt) {
+    let mut interpreter = match &opt.image {
+        Some(path) => match Interpreter::load_image(path) {
+            Ok(interpreter) => interpreter,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        None => Interpreter::default(),
+    };
+    if opt.debug {
+        let console = DebugConsole::new(BufReader::new(std::io::stdin()), std::io::stdout());
+        interpreter.set_debugger(Some(Debugger::new(console).stop_on_entry(true)));
+    }
+    if opt.profile || opt.profile_output.is_some() {
+        interpreter.set_profiler(Some(Profiler::new()));
+    }
+    if opt.coverage || opt.coverage_output.is_some() || opt.coverage_min.is_some() {
+        interpreter.set_coverage(Some(Coverage::new()));
+    }
 
-    for file in &files {
-        if let Err(exc) = interpreter.eval_file(&file) {
+    for file in &opt.files {
+        let res = if file.as_os_str() == "-" {
+            if opt.trace {
+                interpreter.trace_file("#STDIN");
+            }
+            interpreter.eval_reader("#STDIN", std::io::stdin().lock())
+        } else {
+            if opt.trace {
+                interpreter.trace_file(&file.to_string_lossy());
+            }
+            interpreter.eval_file(file)
+        };
+        if let Err(exc) = res {
             Interpreter::print_exc(exc);
+            write_profile(&opt, &mut interpreter);
+            write_coverage(&opt, &mut interpreter);
+            std::process::exit(1);
+        }
+    }
+    write_profile(&opt, &mut interpreter);
+    if !write_coverage(&opt, &mut interpreter) {
+        std::process::exit(1);
+    }
+
+    if let Some(path) = &opt.save_image {
+        if let Err(e) = interpreter.save_image(path) {
+            eprintln!("{}", e);
             std::process::exit(1);
         }
+        if !opt.interactive {
+            return;
+        }
     }
 
-    if interactive || files.is_empty() {
+    if opt.interactive || opt.files.is_empty() {
         repl(interpreter);
     }
 }
 
+fn fmt(check: bool, files: &[PathBuf]) -> i32 {
+    if files.is_empty() {
+        let mut source = String::new();
+        if let Err(e) = std::io::stdin().read_to_string(&mut source) {
+            eprintln!("{}", e);
+            return 1;
+        }
+        return match format_source("#STDIN", &source) {
+            Ok(formatted) if check => (formatted != source) as i32,
+            Ok(formatted) => {
+                print!("{}", formatted);
+                0
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                1
+            }
+        };
+    }
+    let mut code = 0;
+    for file in files {
+        let res = std::fs::read_to_string(file)
+            .map_err(|e| e.to_string())
+            .and_then(|source| {
+                let formatted =
+                    format_source(&file.to_string_lossy(), &source).map_err(|e| e.to_string())?;
+                Ok((formatted != source, formatted))
+            });
+        match res {
+            Ok((false, _)) => (),
+            Ok((true, _)) if check => {
+                println!("{}", file.display());
+                code = 1;
+            }
+            Ok((true, formatted)) => {
+                if let Err(e) = std::fs::write(file, formatted) {
+                    eprintln!("{}: {}", file.display(), e);
+                    code = 1;
+                }
+            }
+            Err(e) => {
+                eprintln!("{}: {}", file.display(), e);
+                code = 1;
+            }
+        }
+    }
+    code
+}
+
+fn check(files: &[PathBuf]) -> i32 {
+    let linter = Linter::default();
+    let mut diagnostics = vec![];
+    if files.is_empty() {
+        let mut source = String::new();
+        if let Err(e) = std::io::stdin().read_to_string(&mut source) {
+            eprintln!("{}", e);
+            return 1;
+        }
+        diagnostics = linter.check_source("#STDIN", &source);
+    }
+    for file in files {
+        match linter.check_file(file) {
+            Ok(d) => diagnostics.extend(d),
+            Err(e) => {
+                eprintln!("{}", e);
+                return 1;
+            }
+        }
+    }
+    for d in &diagnostics {
+        println!("{}", d);
+    }
+    diagnostics.iter().any(|d| d.severity == Severity::Error) as i32
+}
+
+fn test(
+    filter: Option<&str>,
+    format: Option<ReportFormat>,
+    output: Option<&Path>,
+    paths: &[PathBuf],
+) -> i32 {
+    let format = match format {
+        Some(format) => format,
+        None => match std::env::var("TEST_FORMAT") {
+            Ok(format) => match format.parse() {
+                Ok(format) => format,
+                Err(e) => {
+                    eprintln!("TEST_FORMAT: {}", e);
+                    return 1;
+                }
+            },
+            Err(_) => ReportFormat::Text,
+        },
+    };
+    let paths = match paths {
+        [] => vec![PathBuf::from(".")],
+        paths => paths.to_vec(),
+    };
+    let files = match discover(&paths) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    let mut runner = TestRunner::new();
+    if let Some(filter) = filter {
+        runner = runner.filter(filter);
+    }
+    // Text results go to stdout, unless the report does
+    let res = match (format, output) {
+        (ReportFormat::Text, Some(path)) => std::fs::File::create(path)
+            .and_then(|file| runner.run(&files, std::io::BufWriter::new(file))),
+        (_, None) if format != ReportFormat::Text => runner.run(&files, std::io::sink()),
+        _ => runner.run(&files, std::io::stdout()),
+    };
+    let results = match res {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    let report = match format {
+        ReportFormat::Text => None,
+        ReportFormat::Tap => Some(tap(&results)),
+        ReportFormat::Junit => Some(junit(&results)),
+    };
+    match (report, output) {
+        (Some(report), Some(path)) => {
+            if let Err(e) = std::fs::write(path, report) {
+                eprintln!("could not write {}: {}", path.display(), e);
+                return 1;
+            }
+        }
+        (Some(report), None) => print!("{}", report),
+        (None, _) => (),
+    }
+    !results.iter().all(|r| r.passed()) as i32
+}
+
+fn bench(
+    filter: Option<&str>,
+    warmup: Option<usize>,
+    iterations: Option<usize>,
+    baseline: Option<&Path>,
+    threshold: f64,
+    save_baseline: Option<&Path>,
+    paths: &[PathBuf],
+) -> i32 {
+    let mut runner = BenchRunner::new().threshold(threshold);
+    if let Some(filter) = filter {
+        runner = runner.filter(filter);
+    }
+    if let Some(warmup) = warmup {
+        runner = runner.warmup(warmup);
+    }
+    if let Some(iterations) = iterations {
+        runner = runner.iterations(iterations);
+    }
+    if let Some(path) = baseline {
+        match Baseline::load(path) {
+            Ok(baseline) => runner = runner.baseline(baseline),
+            Err(e) => {
+                eprintln!("could not read {}: {}", path.display(), e);
+                return 1;
+            }
+        }
+    }
+    let paths = match paths {
+        [] => vec![PathBuf::from(".")],
+        paths => paths.to_vec(),
+    };
+    let results =
+        match discover_benches(&paths).and_then(|files| runner.run(&files, std::io::stdout())) {
+            Ok(results) => results,
+            Err(e) => {
+                eprintln!("{}", e);
+                return 1;
+            }
+        };
+    if let Some(path) = save_baseline {
+        if let Err(e) = Baseline::new(&results).save(path) {
+            eprintln!("could not write {}: {}", path.display(), e);
+            return 1;
+        }
+    }
+    let regressed = results
+        .iter()
+        .any(|r| r.benches.iter().any(|b| runner.regressed(&r.path, b)));
+    (regressed || results.iter().any(|r| r.error.is_some())) as i32
+}
+
+fn lsp() -> i32 {
+    let stdin = std::io::stdin();
+    match LanguageServer::default().run(stdin.lock(), std::io::stdout()) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+fn dap() -> i32 {
+    match DebugAdapter::default().run(std::io::stdin().lock(), std::io::stdout()) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
 fn repl(mut interpreter: Interpreter) {
     println!("jibi v{}", VERSION);
     let mut rl = Editor::<()>::new();
     let _ = rl.load_history(&*HISTORY_FILE);
 
     loop {
-        match get_tokens(&mut rl, &mut interpreter) {
-            Ok(tokens) => match interpreter.eval_tokens(Box::new(tokens.into_iter())) {
+        match get_input(&mut rl, &mut interpreter) {
+            Ok(tokens) => match interpreter.eval_tokens(Box::new(tokens)) {
                 Ok(Some(val)) => {
                     interpreter.call("display", vec![val]).unwrap();
                 }
@@ -89,12 +526,9 @@ fn repl(mut interpreter: Interpreter) {
     }
 }
 
-/// Get tokens that looks like they form a complete expression (balanced parens)
+/// Get input that looks like it forms a complete expression (balanced parens)
 /// in multiple lines of input if necessary.
-fn get_tokens(
-    rl: &mut Editor<()>,
-    interpreter: &mut Interpreter,
-) -> Result<Vec<Token>, TokenError> {
+fn get_input(rl: &mut Editor<()>, interpreter: &mut Interpreter) -> Result<Tokenizer, TokenError> {
     let mut validator = TokenValidator::new("#STDIN", &mut interpreter.state);
     let mut input = readline(rl, ">>> ");
     loop {
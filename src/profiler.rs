@@ -0,0 +1,199 @@
+//! Profiler recording calls, time and allocations of lambdas and builtins.
+//!
+//! Attach a [`Profiler`] with [`Interpreter::set_profiler`], or use
+//! `(with-profiling ...)` or `jibi --profile`. Time spent in special forms and
+//! macros is attributed to the procedure evaluating them. Calls profiled by a
+//! nested `(with-profiling ...)` are also added to the enclosing profile.
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::types::{allocations, CountAllocations};
+use crate::*;
+
+/// Statistics of a procedure.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProfileEntry {
+    pub calls: u64,
+    /// Time in the procedure and the procedures it calls (counted once for
+    /// recursive calls).
+    pub total: Duration,
+    /// Time in the procedure itself.
+    pub own: Duration,
+    /// Values allocated by the procedure itself.
+    pub allocations: u64,
+}
+
+struct Activation {
+    key: Rc<str>,
+    start: Instant,
+    allocations: u64,
+    // Time and allocations of the procedures it called
+    children: Duration,
+    children_allocations: u64,
+}
+
+#[derive(Default)]
+pub struct Profiler {
+    entries: HashMap<Rc<str>, ProfileEntry>,
+    stack: Vec<Activation>,
+    // Own time by call stack
+    folded: HashMap<String, Duration>,
+    _counting: CountAllocations,
+}
+
+/// Name and definition position of a lambda, name of a builtin.
+fn key(val: &JVal) -> Option<String> {
+    match val {
+        JVal::Lambda(l) => {
+            let name = l.name.as_deref().unwrap_or("lambda");
+            Some(match &l.defpos {
+                Some(pos) => format!("{} ({})", name, pos),
+                None => name.to_string(),
+            })
+        }
+        JVal::Builtin(b) => Some(b.name.clone()),
+        _ => None,
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn enter(&mut self, key: String) {
+        let key = match self.entries.get_key_value(key.as_str()) {
+            Some((k, _)) => Rc::clone(k),
+            None => Rc::from(key),
+        };
+        self.stack.push(Activation {
+            key,
+            start: Instant::now(),
+            allocations: allocations(),
+            children: Duration::ZERO,
+            children_allocations: 0,
+        });
+    }
+
+    fn exit(&mut self) {
+        let activation = match self.stack.pop() {
+            Some(activation) => activation,
+            None => return,
+        };
+        let elapsed = activation.start.elapsed();
+        let allocated = allocations() - activation.allocations;
+        let own = elapsed.saturating_sub(activation.children);
+        let recursive = self.stack.iter().any(|a| a.key == activation.key);
+        let mut path: Vec<&str> = self.stack.iter().map(|a| &*a.key).collect();
+        path.push(&activation.key);
+        *self.folded.entry(path.join(";")).or_default() += own;
+
+        let entry = self.entries.entry(Rc::clone(&activation.key)).or_default();
+        entry.calls += 1;
+        entry.own += own;
+        entry.allocations += allocated - activation.children_allocations;
+        if !recursive {
+            entry.total += elapsed;
+        }
+        if let Some(parent) = self.stack.last_mut() {
+            parent.children += elapsed;
+            parent.children_allocations += allocated;
+        }
+    }
+
+    /// Add the calls recorded by `inner`, a profiler used while this one was
+    /// detached, as if they were called by the current procedure.
+    pub(crate) fn merge(&mut self, inner: Profiler) {
+        let prefix: Vec<&str> = self.stack.iter().map(|a| &*a.key).collect();
+        let prefix = prefix.join(";");
+        let mut elapsed = Duration::ZERO;
+        for (stack, own) in inner.folded {
+            elapsed += own;
+            let stack = match prefix.as_str() {
+                "" => stack,
+                prefix => format!("{};{}", prefix, stack),
+            };
+            *self.folded.entry(stack).or_default() += own;
+        }
+        let mut allocated = 0;
+        for (key, inner_entry) in inner.entries {
+            allocated += inner_entry.allocations;
+            // The total time of active procedures already includes the inner calls
+            let recursive = self.stack.iter().any(|a| a.key == key);
+            let entry = self.entries.entry(key).or_default();
+            entry.calls += inner_entry.calls;
+            entry.own += inner_entry.own;
+            entry.allocations += inner_entry.allocations;
+            if !recursive {
+                entry.total += inner_entry.total;
+            }
+        }
+        if let Some(parent) = self.stack.last_mut() {
+            parent.children += elapsed;
+            parent.children_allocations += allocated;
+        }
+    }
+
+    /// Statistics by procedure, by decreasing total time.
+    pub fn entries(&self) -> Vec<(&str, &ProfileEntry)> {
+        let mut entries: Vec<(&str, &ProfileEntry)> =
+            self.entries.iter().map(|(k, v)| (&**k, v)).collect();
+        entries.sort_by(|a, b| b.1.total.cmp(&a.1.total).then(a.0.cmp(b.0)));
+        entries
+    }
+
+    /// Table of [`Profiler::entries`].
+    pub fn report(&self) -> String {
+        let mut report = format!(
+            "{:>10} {:>12} {:>12} {:>10}  procedure\n",
+            "calls", "total ms", "own ms", "allocs"
+        );
+        for (name, entry) in self.entries() {
+            let _ = writeln!(
+                report,
+                "{:>10} {:>12.3} {:>12.3} {:>10}  {}",
+                entry.calls,
+                entry.total.as_secs_f64() * 1000.0,
+                entry.own.as_secs_f64() * 1000.0,
+                entry.allocations,
+                name
+            );
+        }
+        report
+    }
+
+    /// Own time in microseconds by call stack, in the folded format of flamegraph
+    /// tools (`outer;inner 42`), one line per stack.
+    pub fn folded(&self) -> String {
+        let mut stacks: Vec<(&String, &Duration)> = self.folded.iter().collect();
+        stacks.sort();
+        let mut folded = String::new();
+        for (stack, own) in stacks {
+            let _ = writeln!(folded, "{} {}", stack, own.as_micros());
+        }
+        folded
+    }
+}
+
+/// Call `f` with evaluated `args`, recording the call if profiling.
+pub(crate) fn call<F>(val: &JVal, args: JValRef, state: &mut JState, f: F) -> JResult
+where
+    F: FnOnce(JValRef, &mut JState) -> JResult,
+{
+    let key = match state.profiler_mut() {
+        Some(_) => key(val),
+        None => None,
+    };
+    let key = match key {
+        Some(key) => key,
+        None => return f(args, state),
+    };
+    state.profiler_mut().unwrap().enter(key);
+    let res = f(args, state);
+    if let Some(profiler) = state.profiler_mut() {
+        profiler.exit();
+    }
+    res
+}
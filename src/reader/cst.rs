@@ -0,0 +1,273 @@
+//! Lossless concrete syntax tree, for tooling.
+//!
+//! Unlike the values produced by [`Parser`](crate::Parser), the tree keeps whitespace,
+//! comments and punctuation, and the source span of every node: writing out the
+//! text of all leaves in order gives back the source exactly.
+use std::fmt;
+
+use crate::reader::tokenizer::{Tokenizer, Trivia};
+use crate::reader::ParserError;
+use crate::*;
+
+/// Byte range of a node in the source, and the position of its start.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub pos: PositionTag,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CstKind {
+    /// The whole source.
+    Root,
+    Whitespace,
+    /// `; ...`, up to the end of the line.
+    LineComment,
+    /// `#| ... |#`
+    BlockComment,
+    /// `#;` and the commented out node.
+    DatumComment,
+    List,
+    Vector,
+    Quote,
+    Quasiquote,
+    Unquote,
+    UnquoteSplice,
+    /// Parens, `#` of vectors, quote characters and `#;`.
+    Punct,
+    Atom(TokenValue),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CstNode {
+    pub kind: CstKind,
+    pub span: Span,
+    /// Source text of a leaf, empty for nodes with children.
+    pub text: String,
+    pub children: Vec<CstNode>,
+}
+
+impl CstNode {
+    fn leaf(kind: CstKind, text: &str, span: Span) -> Self {
+        Self {
+            kind,
+            span,
+            text: text.to_string(),
+            children: vec![],
+        }
+    }
+    fn branch(kind: CstKind, children: Vec<CstNode>) -> Self {
+        let first = &children[0].span;
+        let span = Span {
+            start: first.start,
+            end: children[children.len() - 1].span.end,
+            pos: first.pos.clone(),
+        };
+        Self {
+            kind,
+            span,
+            text: String::new(),
+            children,
+        }
+    }
+    pub fn is_leaf(&self) -> bool {
+        self.children.is_empty() && self.kind != CstKind::Root
+    }
+    /// Whether the node has no meaning to the parser (whitespace and comments).
+    pub fn is_trivia(&self) -> bool {
+        use CstKind::*;
+        matches!(
+            self.kind,
+            Whitespace | LineComment | BlockComment | DatumComment
+        )
+    }
+    /// Children which are neither trivia nor punctuation, e.g. the items of a list.
+    pub fn items(&self) -> impl Iterator<Item = &CstNode> {
+        self.children
+            .iter()
+            .filter(|n| !n.is_trivia() && n.kind != CstKind::Punct)
+    }
+    /// Innermost node whose span contains the byte offset.
+    pub fn node_at(&self, offset: usize) -> Option<&CstNode> {
+        if offset < self.span.start || offset >= self.span.end {
+            return None;
+        }
+        match self.children.iter().find_map(|n| n.node_at(offset)) {
+            Some(node) => Some(node),
+            None => Some(self),
+        }
+    }
+}
+
+impl fmt::Display for CstNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)?;
+        for child in &self.children {
+            write!(f, "{}", child)?;
+        }
+        Ok(())
+    }
+}
+
+fn token_error(te: TokenError) -> ParserError {
+    ParserError::new(te.pos, &te.reason)
+}
+
+/// Split the source into leaves.
+fn lex(
+    tokenizer: &mut Tokenizer,
+    source: &str,
+    state: &mut JState,
+) -> Result<Vec<CstNode>, ParserError> {
+    let mut leaves = vec![];
+    loop {
+        let start = tokenizer.offset();
+        let pos = tokenizer.position();
+        let kind = match tokenizer.next_trivia().map_err(token_error)? {
+            Some(Trivia::Whitespace) => CstKind::Whitespace,
+            Some(Trivia::LineComment) => CstKind::LineComment,
+            Some(Trivia::BlockComment) => CstKind::BlockComment,
+            None => match tokenizer.next_token(state).map_err(token_error)?.value {
+                TokenValue::Eof => break,
+                TokenValue::DatumComment
+                | TokenValue::Char('(' | ')' | '#' | '\'' | '`' | ',' | '@') => CstKind::Punct,
+                value => CstKind::Atom(value),
+            },
+        };
+        let end = tokenizer.offset();
+        leaves.push(CstNode::leaf(
+            kind,
+            &source[start..end],
+            Span { start, end, pos },
+        ));
+    }
+    Ok(leaves)
+}
+
+struct CstBuilder {
+    leaves: std::iter::Peekable<std::vec::IntoIter<CstNode>>,
+    eof: PositionTag,
+}
+
+impl CstBuilder {
+    fn peek_punct(&mut self, text: &str) -> bool {
+        matches!(self.leaves.peek(), Some(n) if n.kind == CstKind::Punct && n.text == text)
+    }
+    fn next(&mut self) -> Result<CstNode, ParserError> {
+        self.leaves
+            .next()
+            .ok_or_else(|| ParserError::new(self.eof.clone(), "unexpected end of input"))
+    }
+    fn expect(&mut self, text: &str) -> Result<CstNode, ParserError> {
+        let next = self.next()?;
+        if next.kind == CstKind::Punct && next.text == text {
+            Ok(next)
+        } else {
+            Err(ParserError::new(
+                next.span.pos,
+                &format!("expected {}, got {}", text, next.text),
+            ))
+        }
+    }
+    /// Move whitespace, comments and datum comments to `children`.
+    fn trivia(&mut self, children: &mut Vec<CstNode>) -> Result<(), ParserError> {
+        loop {
+            if matches!(self.leaves.peek(), Some(n) if n.is_trivia()) {
+                children.push(self.next()?);
+            } else if self.peek_punct("#;") {
+                let mut comment = vec![self.next()?];
+                self.trivia(&mut comment)?;
+                comment.push(self.node()?);
+                children.push(CstNode::branch(CstKind::DatumComment, comment));
+            } else {
+                return Ok(());
+            }
+        }
+    }
+    fn seq(&mut self, kind: CstKind, mut children: Vec<CstNode>) -> Result<CstNode, ParserError> {
+        loop {
+            self.trivia(&mut children)?;
+            if self.peek_punct(")") {
+                children.push(self.next()?);
+                return Ok(CstNode::branch(kind, children));
+            }
+            children.push(self.node()?);
+        }
+    }
+    fn prefixed(
+        &mut self,
+        kind: CstKind,
+        mut children: Vec<CstNode>,
+    ) -> Result<CstNode, ParserError> {
+        self.trivia(&mut children)?;
+        children.push(self.node()?);
+        Ok(CstNode::branch(kind, children))
+    }
+    fn node(&mut self) -> Result<CstNode, ParserError> {
+        let next = self.next()?;
+        match next.kind {
+            CstKind::Atom(TokenValue::Char(_)) => (),
+            CstKind::Atom(_) => return Ok(next),
+            _ => (),
+        }
+        match next.text.as_str() {
+            "(" => self.seq(CstKind::List, vec![next]),
+            "#" => {
+                let mut children = vec![next];
+                self.trivia(&mut children)?;
+                children.push(self.expect("(")?);
+                self.seq(CstKind::Vector, children)
+            }
+            "'" => self.prefixed(CstKind::Quote, vec![next]),
+            "`" => self.prefixed(CstKind::Quasiquote, vec![next]),
+            "," => {
+                let mut children = vec![next];
+                self.trivia(&mut children)?;
+                if self.peek_punct("@") {
+                    children.push(self.next()?);
+                    self.prefixed(CstKind::UnquoteSplice, children)
+                } else {
+                    self.prefixed(CstKind::Unquote, children)
+                }
+            }
+            _ => Err(ParserError::new(
+                next.span.pos,
+                &format!("unexpected token {}", next.text),
+            )),
+        }
+    }
+}
+
+/// Parse source text to a [`CstKind::Root`] node.
+pub(crate) fn parse_cst(
+    filename: &str,
+    source: &str,
+    state: &mut JState,
+) -> Result<CstNode, ParserError> {
+    let mut tokenizer =
+        Tokenizer::new(filename.to_string(), source.to_string()).without_char_macros();
+    let leaves = lex(&mut tokenizer, source, state)?;
+    let mut builder = CstBuilder {
+        leaves: leaves.into_iter().peekable(),
+        eof: tokenizer.position(),
+    };
+    let mut children = vec![];
+    loop {
+        builder.trivia(&mut children)?;
+        if builder.leaves.peek().is_none() {
+            break;
+        }
+        children.push(builder.node()?);
+    }
+    Ok(CstNode {
+        kind: CstKind::Root,
+        span: Span {
+            start: 0,
+            end: source.len(),
+            pos: PositionTag::new(filename, 1, 1),
+        },
+        text: String::new(),
+        children,
+    })
+}
@@ -2,8 +2,10 @@ use std::fmt;
 
 use crate::{JError, SyntaxError};
 
+pub mod cst;
 pub mod parser;
 pub mod readermacro;
+pub mod readtable;
 pub mod tokenizer;
 
 #[derive(Debug, PartialEq, Clone)]
@@ -1,35 +1,68 @@
+use std::cell::RefCell;
+
+use crate::reader::cst::{self, CstNode};
 use crate::reader::ParserError;
 use crate::*;
 
 pub struct Parser<'a> {
     tokens: Box<dyn TokenProducer>,
-    peek: Token,
+    /// Tokens are only read when needed, so that a form can be evaluated
+    /// before the tokens following it are available.
+    peek: Option<Token>,
     state: &'a mut JState,
+    // Reading quoted data, whose lists are not recorded for coverage
+    quoted: bool,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(tokens: Box<dyn TokenProducer>, state: &'a mut JState) -> Self {
-        let mut this = Self {
+        Self {
             tokens,
-            // Dummy value until we read the first real token
-            peek: Token::new(TokenValue::Eof, PositionTag::new("", 0, 0)),
+            peek: None,
             state,
-        };
-        this.next().unwrap();
-        this
+            quoted: false,
+        }
+    }
+
+    /// Parse source text to a lossless concrete syntax tree, which keeps whitespace,
+    /// comments and the source span of every node. Token reader macros are not applied,
+    /// and char macro handlers are not called: their keys are read as punctuation.
+    pub fn parse_cst(
+        filename: &str,
+        source: &str,
+        state: &mut JState,
+    ) -> Result<CstNode, ParserError> {
+        cst::parse_cst(filename, source, state)
+    }
+
+    /// The state used to construct values, e.g. to evaluate forms as they are parsed.
+    pub fn state(&mut self) -> &mut JState {
+        self.state
     }
 
     fn error(&self, pos: PositionTag, reason: &str) -> ParserError {
         ParserError::new(pos, reason)
     }
 
+    fn read(&mut self) -> Result<Token, ParserError> {
+        match self.tokens.next_token(self.state) {
+            Ok(tok) => Ok(tok),
+            Err(te) => Err(self.error(te.pos, &te.reason)),
+        }
+    }
+
+    fn peek(&mut self) -> Result<&TokenValue, ParserError> {
+        if self.peek.is_none() {
+            self.peek = Some(self.read()?);
+        }
+        Ok(&self.peek.as_ref().unwrap().value)
+    }
+
     fn next(&mut self) -> Result<Token, ParserError> {
-        let next = match self.tokens.next_token(self.state) {
-            Ok(tok) => tok,
-            Err(te) => return Err(self.error(te.pos, &te.reason)),
-        };
-        let cur = std::mem::replace(&mut self.peek, next);
-        Ok(cur)
+        match self.peek.take() {
+            Some(tok) => Ok(tok),
+            None => self.read(),
+        }
     }
 
     fn expect(&mut self, tok: TokenValue) -> Result<Token, ParserError> {
@@ -44,12 +77,23 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Skip expressions commented out with `#;`.
+    fn skip_datum_comments(&mut self) -> Result<(), ParserError> {
+        while *self.peek()? == TokenValue::DatumComment {
+            self.next()?;
+            self.expr()?;
+        }
+        Ok(())
+    }
+
     fn expr(&mut self) -> Result<JValRef, ParserError> {
-        match self.peek.value {
+        self.skip_datum_comments()?;
+        match self.peek()? {
             TokenValue::Char('(') => self.list(),
             TokenValue::Char('\'') => self.quote(),
             TokenValue::Char('`') => self.quasiquote(),
             TokenValue::Char(',') => self.unquote(),
+            TokenValue::Char('#') => self.vector(),
             _ => self.atom(),
         }
     }
@@ -57,50 +101,83 @@ impl<'a> Parser<'a> {
     fn atom(&mut self) -> Result<JValRef, ParserError> {
         let next = self.next()?;
         match next.value {
+            TokenValue::Bool(b) => Ok(self.state.bool(b)),
             TokenValue::Int(n) => Ok(self.state.int(n)),
             TokenValue::Ident(s) => Ok(self.state.symbol(s)),
             TokenValue::String(s) => Ok(self.state.string(s)),
             TokenValue::Float(x) => Ok(self.state.float(x)),
+            TokenValue::Datum(v) => Ok(v),
             _ => Err(self.error(next.pos, &format!("unexpected token {:?}", next.value))),
         }
     }
 
     fn list(&mut self) -> Result<JValRef, ParserError> {
-        self.expect(TokenValue::Char('('))?;
+        let open = self.expect(TokenValue::Char('('))?;
         let mut list = vec![];
-        while self.peek.value != TokenValue::Char(')') {
+        self.skip_datum_comments()?;
+        while *self.peek()? != TokenValue::Char(')') {
             list.push(self.expr()?);
+            self.skip_datum_comments()?;
         }
         self.expect(TokenValue::Char(')'))?;
-        Ok(self.state.list(list))
+        let list = self.state.list(list);
+        if !self.quoted {
+            crate::coverage::record(self.state, &list, &open.pos);
+        }
+        if self.state.debugging() {
+            crate::debugger::record(self.state, &list, open.pos);
+        }
+        Ok(list)
+    }
+
+    fn vector(&mut self) -> Result<JValRef, ParserError> {
+        self.expect(TokenValue::Char('#'))?;
+        self.expect(TokenValue::Char('('))?;
+        let mut items = vec![];
+        self.skip_datum_comments()?;
+        while *self.peek()? != TokenValue::Char(')') {
+            items.push(self.expr()?);
+            self.skip_datum_comments()?;
+        }
+        self.expect(TokenValue::Char(')'))?;
+        Ok(JVal::Vector(RefCell::new(items)).into_ref())
+    }
+
+    /// Parse an expression, setting whether it is quoted data.
+    fn expr_quoted(&mut self, quoted: bool) -> Result<JValRef, ParserError> {
+        let outer = std::mem::replace(&mut self.quoted, quoted);
+        let res = self.expr();
+        self.quoted = outer;
+        res
     }
 
     fn quote(&mut self) -> Result<JValRef, ParserError> {
         self.expect(TokenValue::Char('\''))?;
-        Ok(JVal::Quote(self.expr()?).into_ref())
+        Ok(JVal::Quote(self.expr_quoted(true)?).into_ref())
     }
 
     fn quasiquote(&mut self) -> Result<JValRef, ParserError> {
         self.expect(TokenValue::Char('`'))?;
-        Ok(JVal::Quasiquote(self.expr()?).into_ref())
+        Ok(JVal::Quasiquote(self.expr_quoted(true)?).into_ref())
     }
 
     fn unquote(&mut self) -> Result<JValRef, ParserError> {
         self.expect(TokenValue::Char(','))?;
-        match self.peek.value {
+        match self.peek()? {
             TokenValue::Char('@') => {
                 self.next()?;
-                Ok(JVal::UnquoteSplice(self.expr()?).into_ref())
+                Ok(JVal::UnquoteSplice(self.expr_quoted(false)?).into_ref())
             }
-            _ => Ok(JVal::Unquote(self.expr()?).into_ref()),
+            _ => Ok(JVal::Unquote(self.expr_quoted(false)?).into_ref()),
         }
     }
 
     pub fn parse_form(&mut self) -> Result<Option<(PositionTag, JValRef)>, ParserError> {
-        if self.peek.value == TokenValue::Eof {
+        self.skip_datum_comments()?;
+        if *self.peek()? == TokenValue::Eof {
             return Ok(None);
         }
-        let spos = self.peek.pos.clone();
+        let spos = self.peek.as_ref().unwrap().pos.clone();
         match self.expr() {
             Ok(val) => Ok(Some((spos, val))),
             Err(e) => Err(e),
@@ -119,6 +196,7 @@ impl<'a> Parser<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::reader::cst::CstKind;
     use crate::reader::tokenizer::Tokenizer;
 
     fn test_parser(state: &mut JState, input: &str, expected: JValRef) {
@@ -210,4 +288,95 @@ mod tests {
         let expected = state.float(2.025);
         test_parser(&mut state, "2.025", expected);
     }
+
+    #[test]
+    fn test_parser_8() {
+        let mut state = JState::default();
+        let items = vec![
+            state.bool(true),
+            state.int(1),
+            state.string("a".to_string()),
+        ];
+        let expected = JVal::Vector(RefCell::new(items)).into_ref();
+        test_parser(&mut state, "#(#t 1 \"a\")", expected);
+    }
+
+    #[test]
+    fn test_parser_repr_round_trip() {
+        let mut state = JState::default();
+        let input = r#"(#t #f 1 -17 1.0 1e100 -2.5e-7 +inf.0 -inf.0 "say \"hi\"\n\\" sym
+            #(1 #(2.0) ()) (1 . 2) '(a `(b ,c ,@d)))"#;
+        let mut parser = Parser::new(
+            Box::new(Tokenizer::new("test".to_string(), input.to_string())),
+            &mut state,
+        );
+        let val = parser.expr().unwrap();
+        let text = repr(&val);
+        let mut parser = Parser::new(
+            Box::new(Tokenizer::new("test".to_string(), text)),
+            &mut state,
+        );
+        assert_eq!(val, parser.expr().unwrap());
+
+        let nan = state.float(JTFloat::NAN);
+        let mut parser = Parser::new(
+            Box::new(Tokenizer::new("test".to_string(), repr(&nan))),
+            &mut state,
+        );
+        assert!(parser.expr().unwrap().to_float().unwrap().is_nan());
+    }
+
+    #[test]
+    fn test_parser_datum_comment() {
+        let mut state = JState::default();
+        let lst = vec![state.int(1), state.int(4)];
+        let expected = state.list(lst);
+        test_parser(&mut state, "#;0 (1 #;2 #;#;(3 3) 3 4 #;5)", expected);
+
+        let mut parser = Parser::new(
+            Box::new(Tokenizer::new("test".to_string(), "1 #;2".to_string())),
+            &mut state,
+        );
+        assert_eq!(1, parser.parse_forms().unwrap().len());
+    }
+
+    #[test]
+    fn test_parser_cst() {
+        let mut state = JState::default();
+        let source = r#"; header
+(defn f (x) #| block
+  |# `(,x ,@'(1 #;2)) ; trailing
+  #(1 "s" #t))
+
+#;(ignored) 3.5"#;
+        let cst = Parser::parse_cst("test", source, &mut state).unwrap();
+        assert_eq!(source, cst.to_string());
+        let items: Vec<&CstNode> = cst.items().collect();
+        assert_eq!(2, items.len());
+        assert_eq!(CstKind::List, items[0].kind);
+        assert_eq!(PositionTag::new("test", 2, 1), items[0].span.pos);
+        assert_eq!(CstKind::Atom(TokenValue::Float(3.5)), items[1].kind);
+        assert_eq!(source.len() - 3, items[1].span.start);
+
+        let defn: Vec<&CstNode> = items[0].items().collect();
+        assert_eq!(5, defn.len());
+        assert_eq!(CstKind::Quasiquote, defn[3].kind);
+        assert_eq!("`(,x ,@'(1 #;2))", defn[3].to_string());
+        assert_eq!(CstKind::Vector, defn[4].kind);
+        assert!(items[0]
+            .children
+            .iter()
+            .any(|n| n.kind == CstKind::BlockComment));
+
+        let offset = source.find("\"s\"").unwrap();
+        let node = cst.node_at(offset).unwrap();
+        assert_eq!(
+            CstKind::Atom(TokenValue::String("s".to_string())),
+            node.kind
+        );
+
+        for bad in ["(1 2", "1)", "{1}", "#1"] {
+            assert!(Parser::parse_cst("test", bad, &mut state).is_err());
+        }
+    }
 }
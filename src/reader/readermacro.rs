@@ -2,6 +2,8 @@ use std::collections::VecDeque;
 use std::fmt;
 use std::rc::Rc;
 
+use regex::Regex;
+
 use crate::apply::apply_lambda;
 use crate::*;
 
@@ -20,30 +22,100 @@ impl<T: PartialEq> Matcher<T> {
     }
 }
 
+/// Regex matched against the whole text of a token.
+#[derive(Clone)]
+pub struct TokenRegex {
+    pattern: String,
+    re: Regex,
+}
+
+impl TokenRegex {
+    pub fn new(pattern: &str) -> Result<Self, JError> {
+        match Regex::new(&format!("^(?:{})$", pattern)) {
+            Ok(re) => Ok(Self {
+                pattern: pattern.to_string(),
+                re,
+            }),
+            Err(e) => Err(JError::new(
+                Other("RegexError".to_string()),
+                &format!("{}", e),
+            )),
+        }
+    }
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+    pub fn matches(&self, tv: &TokenValue) -> bool {
+        let text = match tv {
+            TokenValue::Char(c) => c.to_string(),
+            TokenValue::Bool(b) => (if *b { "#t" } else { "#f" }).to_string(),
+            TokenValue::Int(n) => n.to_string(),
+            TokenValue::Float(x) => x.to_string(),
+            TokenValue::Ident(s) | TokenValue::String(s) => s.clone(),
+            TokenValue::DatumComment | TokenValue::Datum(_) | TokenValue::Eof => return false,
+        };
+        self.re.is_match(&text)
+    }
+}
+
+impl PartialEq for TokenRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern == other.pattern
+    }
+}
+
+impl fmt::Debug for TokenRegex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TokenRegex({:?})", self.pattern)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenMatcher {
     Any,
     Char(char),
+    Bool(Matcher<bool>),
     Int(Matcher<JTInt>),
     Float(Matcher<JTFloat>),
     Ident(Matcher<String>),
     String(Matcher<String>),
     Eof,
+    Regex(TokenRegex),
     Or(Box<TokenMatcher>, Box<TokenMatcher>),
+    // Matchers of variable length
+    /// A single token, or a balanced parenthesized group of tokens.
+    Group,
+    Seq(Vec<TokenMatcher>),
+    /// Repeated at least min times, and at most max times (if any), greedily.
+    /// Without a max, tokens are read ahead for as long as they match, up to the
+    /// end of the input for a matcher like `Any`.
+    Repeat(Box<TokenMatcher>, usize, Option<usize>),
 }
 
 impl TokenMatcher {
     pub fn or(self, other: TokenMatcher) -> Self {
         TokenMatcher::Or(Box::new(self), Box::new(other))
     }
+    /// Whether the matcher always matches exactly one token.
+    pub fn is_single(&self) -> bool {
+        use TokenMatcher::*;
+        match self {
+            Or(tm1, tm2) => tm1.is_single() && tm2.is_single(),
+            Group | Seq(_) | Repeat(..) => false,
+            _ => true,
+        }
+    }
+    /// Match a single token; always false for matchers of variable length.
     pub fn matches(&self, tv: &TokenValue) -> bool {
         use TokenMatcher::*;
         match (self, tv) {
             (Or(tm1, tm2), _) => tm1.matches(tv) || tm2.matches(tv),
+            (Regex(re), _) => re.matches(tv),
             (Any, TokenValue::Eof) => false,
             (Any, _) => true,
             (Eof, TokenValue::Eof) => true,
             (Char(c1), TokenValue::Char(c2)) => c1 == c2,
+            (Bool(m), TokenValue::Bool(b)) => m.matches(b),
             (Int(m), TokenValue::Int(n)) => m.matches(n),
             (Float(m), TokenValue::Float(n)) => m.matches(n),
             (Ident(m), TokenValue::Ident(s)) => m.matches(s),
@@ -51,6 +123,106 @@ impl TokenMatcher {
             _ => false,
         }
     }
+    /// Possible end positions of matches starting at `start`, preferred first.
+    fn ends(&self, la: &mut Lookahead, start: usize) -> Result<Vec<usize>, TokenError> {
+        use TokenMatcher::*;
+        let mut ends = vec![];
+        match self {
+            Or(tm1, tm2) if !self.is_single() => {
+                for end in tm1.ends(la, start)?.into_iter().chain(tm2.ends(la, start)?) {
+                    push_new(&mut ends, end);
+                }
+            }
+            Group => {
+                let mut depth = 0;
+                let mut pos = start;
+                loop {
+                    match la.get(pos)?.value {
+                        TokenValue::Char('(') => depth += 1,
+                        TokenValue::Char(')') if depth > 0 => depth -= 1,
+                        TokenValue::Char(')') | TokenValue::Eof => break,
+                        _ => (),
+                    }
+                    pos += 1;
+                    if depth == 0 {
+                        ends.push(pos);
+                        break;
+                    }
+                }
+            }
+            Seq(tms) => {
+                ends.push(start);
+                for tm in tms {
+                    let mut next = vec![];
+                    for pos in ends {
+                        for end in tm.ends(la, pos)? {
+                            push_new(&mut next, end);
+                        }
+                    }
+                    ends = next;
+                }
+            }
+            Repeat(tm, min, max) => {
+                let mut levels = vec![vec![start]];
+                let mut seen = vec![start];
+                while levels.len() <= max.unwrap_or(usize::MAX) {
+                    let mut next = vec![];
+                    for pos in levels.last().unwrap() {
+                        for end in tm.ends(la, *pos)? {
+                            if !seen.contains(&end) {
+                                seen.push(end);
+                                next.push(end);
+                            }
+                        }
+                    }
+                    if next.is_empty() {
+                        break;
+                    }
+                    levels.push(next);
+                }
+                for (n, level) in levels.into_iter().enumerate().rev() {
+                    if n >= *min {
+                        ends.extend(level);
+                    }
+                }
+            }
+            _ => {
+                if self.matches(&la.get(start)?.value) {
+                    ends.push(start + 1);
+                }
+            }
+        }
+        Ok(ends)
+    }
+}
+
+fn push_new(ends: &mut Vec<usize>, end: usize) {
+    if !ends.contains(&end) {
+        ends.push(end);
+    }
+}
+
+/// Tokens read ahead from a producer, to match rules of variable length.
+struct Lookahead<'a> {
+    buffer: &'a mut VecDeque<Token>,
+    tokens: &'a mut dyn TokenProducer,
+    state: &'a mut JState,
+}
+
+impl Lookahead<'_> {
+    fn get(&mut self, i: usize) -> Result<&Token, TokenError> {
+        while self.buffer.len() <= i {
+            if let Some(Token {
+                value: TokenValue::Eof,
+                ..
+            }) = self.buffer.back()
+            {
+                return Ok(self.buffer.back().unwrap());
+            }
+            self.buffer.push_back(self.tokens.next_token(self.state)?);
+        }
+        Ok(&self.buffer[i])
+    }
 }
 
 impl fmt::Display for TokenMatcher {
@@ -58,6 +230,8 @@ impl fmt::Display for TokenMatcher {
         use TokenMatcher::*;
         match &self {
             Any => write!(f, "#ANY"),
+            Bool(Matcher::Any) => write!(f, "BOOL(#ANY)"),
+            Bool(Matcher::Exact(b)) => write!(f, "BOOL({})", if *b { "#t" } else { "#f" }),
             Int(Matcher::Any) => write!(f, "INT(#ANY)"),
             Int(Matcher::Exact(n)) => write!(f, "INT({})", n),
             Float(Matcher::Any) => write!(f, "FLOAT(#ANY)"),
@@ -68,7 +242,19 @@ impl fmt::Display for TokenMatcher {
             String(Matcher::Any) => write!(f, "String(#ANY)"),
             Eof => write!(f, "EOF"),
             Char(c) => write!(f, "CHAR('{}')", c),
+            Regex(re) => write!(f, "REGEX({})", re.pattern()),
             Or(tm1, tm2) => write!(f, "{}|{}", tm1, tm2),
+            Group => write!(f, "GROUP"),
+            Seq(tms) => write!(
+                f,
+                "SEQ({})",
+                tms.iter()
+                    .map(|tm| tm.to_string())
+                    .collect::<Vec<std::string::String>>()
+                    .join(" ")
+            ),
+            Repeat(tm, min, Some(max)) => write!(f, "REPEAT({}, {}, {})", tm, min, max),
+            Repeat(tm, min, None) => write!(f, "REPEAT({}, {})", tm, min),
         }
     }
 }
@@ -84,38 +270,65 @@ impl ReaderMacro {
     pub fn new(rule: Vec<TokenMatcher>, lambda: JLambda, env: JEnvRef) -> Self {
         Self { rule, lambda, env }
     }
-    pub fn rule_len(&self) -> usize {
-        self.rule.len()
+    pub fn rule(&self) -> &[TokenMatcher] {
+        &self.rule
+    }
+    pub fn lambda(&self) -> &JLambda {
+        &self.lambda
+    }
+    pub fn env(&self) -> JEnvRef {
+        Rc::clone(&self.env)
     }
     pub fn apply(&self, tokens: Box<dyn TokenProducer>) -> ReaderMacroProducer {
         ReaderMacroProducer::new(tokens, self.clone())
     }
-    pub fn matches_rule(&self, tokens: &[Token]) -> bool {
-        if tokens.len() != self.rule.len() {
-            return false;
+    /// Match the rule from `pos`, returning the end position of each matcher.
+    fn match_rule(
+        &self,
+        la: &mut Lookahead,
+        idx: usize,
+        pos: usize,
+    ) -> Result<Option<Vec<usize>>, TokenError> {
+        if idx == self.rule.len() {
+            return Ok(Some(vec![]));
         }
-        for (tok, matcher) in tokens.iter().zip(self.rule.iter()) {
-            if !matcher.matches(&tok.value) {
-                return false;
+        for end in self.rule[idx].ends(la, pos)? {
+            if let Some(mut ends) = self.match_rule(la, idx + 1, end)? {
+                ends.insert(0, end);
+                return Ok(Some(ends));
             }
         }
-        true
-    }
-    pub fn apply_rule(&self, tokens: Vec<Token>, state: &mut JState) -> Result<Vec<Token>, JError> {
-        apply_lambda(
-            &self.lambda,
-            state.list(
-                tokens
-                    .into_iter()
-                    .map(|t| JVal::Token(t).into_ref())
-                    .collect(),
-            ),
-            Rc::clone(&self.env),
-            state,
-        )?
-        .iter_list()?
-        .map(|v| v.to_token().map(|t| t.clone()))
-        .collect()
+        Ok(None)
+    }
+    /// Apply the transformer to matched tokens. Each matcher of the rule gives one
+    /// argument: a token for single token matchers, or a list of tokens.
+    pub fn apply_rule(
+        &self,
+        mut tokens: Vec<Token>,
+        ends: &[usize],
+        state: &mut JState,
+    ) -> Result<Vec<Token>, JError> {
+        let mut starts = vec![0];
+        starts.extend_from_slice(&ends[..ends.len() - 1]);
+        let mut args = vec![];
+        for (tm, start) in self.rule.iter().zip(starts).rev() {
+            let matched: Vec<JValRef> = tokens
+                .split_off(start)
+                .into_iter()
+                .map(|t| JVal::Token(t).into_ref())
+                .collect();
+            // Arguments are evaluated by apply_lambda, so lists must be quoted
+            args.push(if tm.is_single() {
+                Rc::clone(&matched[0])
+            } else {
+                state.quote(state.list(matched))
+            });
+        }
+        args.reverse();
+        apply_lambda(&self.lambda, state.list(args), Rc::clone(&self.env), state)?
+            .iter_list()?
+            .map(|v| v.to_token().cloned())
+            .collect()
     }
 }
 
@@ -128,11 +341,10 @@ pub struct ReaderMacroProducer {
 
 impl ReaderMacroProducer {
     pub fn new(tokens: Box<dyn TokenProducer>, rm: ReaderMacro) -> Self {
-        let rl = rm.rule_len();
         Self {
             tokens,
             rm,
-            buffer_in: VecDeque::with_capacity(rl),
+            buffer_in: VecDeque::new(),
             buffer_out: VecDeque::new(),
         }
     }
@@ -141,26 +353,35 @@ impl ReaderMacroProducer {
 impl TokenProducer for ReaderMacroProducer {
     fn next_token(&mut self, state: &mut JState) -> Result<Token, TokenError> {
         loop {
-            if !self.buffer_out.is_empty() {
-                return Ok(self.buffer_out.pop_front().unwrap());
-            }
-            while self.buffer_in.len() < self.rm.rule.len() {
-                self.buffer_in.push_back(self.tokens.next_token(state)?)
+            if let Some(tok) = self.buffer_out.pop_front() {
+                return Ok(tok);
             }
-            let buf = self.buffer_in.make_contiguous();
-            if self.rm.matches_rule(buf) {
-                let toks_in: Vec<Token> = std::mem::replace(
-                    &mut self.buffer_in,
-                    VecDeque::with_capacity(self.rm.rule.len()),
-                )
-                .into();
-                let spos = toks_in[0].pos.clone();
-                self.buffer_out = match self.rm.apply_rule(toks_in, state) {
-                    Ok(toks_out) => toks_out.into(),
-                    Err(je) => return Err(TokenError::new(&format!("{}", je), spos)),
-                };
-            } else {
-                return Ok(self.buffer_in.pop_front().unwrap());
+            let ends = self.rm.match_rule(
+                &mut Lookahead {
+                    buffer: &mut self.buffer_in,
+                    tokens: self.tokens.as_mut(),
+                    state,
+                },
+                0,
+                0,
+            )?;
+            match ends {
+                // Empty matches are ignored, they would never advance
+                Some(ends) if ends.last().is_some_and(|end| *end > 0) => {
+                    let toks_in: Vec<Token> =
+                        self.buffer_in.drain(..ends.last().unwrap()).collect();
+                    let spos = toks_in[0].pos.clone();
+                    self.buffer_out = match self.rm.apply_rule(toks_in, &ends, state) {
+                        Ok(toks_out) => toks_out.into(),
+                        Err(je) => return Err(TokenError::new(&format!("{}", je), spos)),
+                    };
+                }
+                _ => {
+                    if self.buffer_in.is_empty() {
+                        self.buffer_in.push_back(self.tokens.next_token(state)?);
+                    }
+                    return Ok(self.buffer_in.pop_front().unwrap());
+                }
             }
         }
     }
@@ -0,0 +1,214 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::apply::apply_lambda;
+use crate::reader::parser::Parser;
+use crate::reader::tokenizer::Tokenizer;
+use crate::*;
+
+/// A character level reader macro: when the tokenizer reaches its key, a single
+/// character or a `#` dispatch pair, the handler is called with a [`CharStream`]
+/// positioned after the key, and the datum it returns is used as is.
+#[derive(Clone)]
+pub struct CharMacro {
+    key: String,
+    lambda: JLambda,
+    env: JEnvRef,
+}
+
+impl CharMacro {
+    pub fn new(key: &str, lambda: JLambda, env: JEnvRef) -> Result<Self, JError> {
+        let mut chars = key.chars();
+        let valid = match (chars.next(), chars.next(), chars.next()) {
+            (Some('#'), Some(c), None) => !c.is_whitespace(),
+            (Some(c), None, None) => !c.is_whitespace() && c != '#',
+            _ => false,
+        };
+        if !valid {
+            return Err(JError::new(
+                TypeError,
+                &format!("invalid char macro key {:?}", key),
+            ));
+        }
+        Ok(Self {
+            key: key.to_string(),
+            lambda,
+            env,
+        })
+    }
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+    pub fn lambda(&self) -> &JLambda {
+        &self.lambda
+    }
+    pub fn env(&self) -> JEnvRef {
+        Rc::clone(&self.env)
+    }
+    pub(crate) fn call(&self, stream: &Rc<CharStream>, state: &mut JState) -> JResult {
+        let stream = JVal::Foreign(JForeign::from_rc(state.char_stream_type(), stream)).into_ref();
+        apply_lambda(
+            &self.lambda,
+            state.list(vec![stream]),
+            Rc::clone(&self.env),
+            state,
+        )
+    }
+}
+
+/// Character level reader macros, by key.
+#[derive(Clone, Default)]
+pub struct Readtable {
+    macros: HashMap<String, CharMacro>,
+}
+
+impl Readtable {
+    pub fn set(&mut self, cm: CharMacro) {
+        self.macros.insert(cm.key.clone(), cm);
+    }
+    pub fn remove(&mut self, key: &str) -> Option<CharMacro> {
+        self.macros.remove(key)
+    }
+    /// Find the macro for the start of `input`; dispatch pairs have priority.
+    pub fn lookup(&self, input: &str) -> Option<&CharMacro> {
+        if self.macros.is_empty() {
+            return None;
+        }
+        let mut chars = input.char_indices();
+        let first = chars.next()?.1;
+        if first == '#' {
+            let end = chars.nth(0).map_or(input.len(), |(i, c)| i + c.len_utf8());
+            if let Some(cm) = self.macros.get(&input[..end]) {
+                return Some(cm);
+            }
+        }
+        self.macros.get(&input[..first.len_utf8()])
+    }
+    /// Macros sorted by key.
+    pub fn macros(&self) -> Vec<&CharMacro> {
+        let mut macros: Vec<&CharMacro> = self.macros.values().collect();
+        macros.sort_by(|a, b| a.key.cmp(&b.key));
+        macros
+    }
+}
+
+/// The input of a readtable handler, exposed to `jibi` as a foreign `char-stream`.
+pub struct CharStream {
+    tokenizer: RefCell<Tokenizer>,
+    /// Whether the handler tried to read past the end of the input.
+    eof: Cell<bool>,
+}
+
+impl CharStream {
+    pub(crate) fn new(tokenizer: Tokenizer) -> Self {
+        Self {
+            tokenizer: RefCell::new(tokenizer),
+            eof: Cell::new(false),
+        }
+    }
+    pub(crate) fn tokenizer(&self) -> Tokenizer {
+        self.tokenizer.borrow().clone()
+    }
+    pub(crate) fn at_eof(&self) -> bool {
+        self.eof.get()
+    }
+    pub fn peek_char(&self) -> Option<char> {
+        let mut tokenizer = self.tokenizer.borrow_mut();
+        let mut c = tokenizer.peek_char();
+        // Read errors are reported as the end of the input
+        while c.is_none() && tokenizer.fill(None).unwrap_or(false) {
+            c = tokenizer.peek_char();
+        }
+        self.eof.set(self.eof.get() || c.is_none());
+        c
+    }
+    pub fn read_char(&self) -> Option<char> {
+        self.peek_char()?;
+        self.tokenizer.borrow_mut().next_char()
+    }
+    /// Read data until the `close` character, e.g. `}` for `#{1 2 3}`.
+    pub fn read_delimited(
+        self: &Rc<Self>,
+        close: char,
+        state: &mut JState,
+    ) -> Result<Vec<JValRef>, JError> {
+        let mut items = vec![];
+        loop {
+            let mut tokenizer = self.tokenizer.borrow_mut();
+            let mark = tokenizer.mark();
+            if let Err(e) = tokenizer.skip_blank() {
+                // Read the rest of a block comment from the source, if there is one
+                if e.incomplete {
+                    tokenizer.reset(mark);
+                    if tokenizer.fill(Some("|#")).unwrap_or(false) {
+                        continue;
+                    }
+                }
+                self.eof.set(self.eof.get() || e.incomplete);
+                return Err(JError::new(SyntaxError, &e.reason));
+            }
+            drop(tokenizer);
+            match self.peek_char() {
+                Some(c) if c == close => {
+                    self.read_char();
+                    return Ok(items);
+                }
+                Some(_) => match self.read(state)? {
+                    Some(val) => items.push(val),
+                    None => break,
+                },
+                None => break,
+            }
+        }
+        Err(JError::new(
+            SyntaxError,
+            &format!("expected {}, got end of input", close),
+        ))
+    }
+    /// Read the next datum, or None at the end of the input.
+    pub fn read(self: &Rc<Self>, state: &mut JState) -> Result<Option<JValRef>, JError> {
+        let eof = self.eof.replace(false);
+        let tokens = StreamTokens {
+            stream: Rc::clone(self),
+        };
+        match Parser::new(Box::new(tokens), state).parse_form() {
+            Ok(Some((_, val))) => {
+                self.eof.set(eof);
+                Ok(Some(val))
+            }
+            Ok(None) => {
+                self.eof.set(true);
+                Ok(None)
+            }
+            Err(pe) => {
+                self.eof.set(eof || self.eof.get());
+                Err(pe.into())
+            }
+        }
+    }
+}
+
+/// Tokens of a char stream.
+struct StreamTokens {
+    stream: Rc<CharStream>,
+}
+
+impl TokenProducer for StreamTokens {
+    fn next_token(&mut self, state: &mut JState) -> Result<Token, TokenError> {
+        // Not borrowed while tokenizing, handlers of nested macros may read the stream
+        let mut tokenizer = self.stream.tokenizer.borrow().clone();
+        let res = tokenizer.next_token(state);
+        match &res {
+            Ok(Token {
+                value: TokenValue::Eof,
+                ..
+            }) => self.stream.eof.set(true),
+            Err(e) if e.incomplete => self.stream.eof.set(true),
+            _ => (),
+        }
+        // The tokenizer may have read more input from its source
+        *self.stream.tokenizer.borrow_mut() = tokenizer;
+        res
+    }
+}
@@ -1,19 +1,30 @@
+use std::cell::RefCell;
 use std::fmt;
+use std::io::BufRead;
+use std::marker::PhantomData;
+use std::rc::Rc;
 
 use lazy_static::lazy_static;
 use regex::Regex;
 
+use crate::reader::readtable::CharStream;
+use crate::repr::repr;
 use crate::state::JState;
-use crate::types::{JTFloat, JTInt};
+use crate::types::{JTFloat, JTInt, JValRef};
 use crate::PositionTag;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenValue {
     Char(char),
+    Bool(bool),
     Int(JTInt),
     Float(JTFloat),
     Ident(String),
     String(String),
+    /// `#;`, comments out the next expression.
+    DatumComment,
+    /// A datum read by a readtable handler.
+    Datum(JValRef),
     Eof,
 }
 
@@ -39,10 +50,13 @@ impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use TokenValue::*;
         match &self.value {
+            Bool(b) => write!(f, "BOOL({})", if *b { "#t" } else { "#f" }),
             Int(n) => write!(f, "INT({})", n),
             Float(x) => write!(f, "FLOAT({})", x),
             Ident(s) => write!(f, "IDENT({})", s),
             String(s) => write!(f, "STRING(\"{}\")", s),
+            DatumComment => write!(f, "DATUM_COMMENT"),
+            Datum(v) => write!(f, "DATUM({})", repr(v)),
             Eof => write!(f, "EOF"),
             Char(c) => write!(f, "CHAR('{}')", c),
         }
@@ -59,9 +73,26 @@ lazy_static! {
         "
     )
     .unwrap();
-    static ref RE_STRING: Regex = Regex::new(r#"^"([^"]|\\")*""#).unwrap();
-    static ref RE_FLOAT: Regex = Regex::new(r"^-?([.][0-9]+|[0-9]+[.][0-9]*)").unwrap();
+    static ref RE_STRING: Regex = Regex::new(r#"^"([^"\\]|\\.)*""#).unwrap();
+    static ref RE_TRIPLE_STRING: Regex = Regex::new(r#"^"""(?s:[^\\]|\\.)*?""""#).unwrap();
+    static ref RE_FLOAT: Regex = Regex::new(
+        r"(?x)
+            ^
+            (
+                [+-](inf|nan)[.]0
+                |
+                -?([.][0-9]+|[0-9]+[.][0-9]*)([eE][+-]?[0-9]+)?
+                |
+                -?[0-9]+[eE][+-]?[0-9]+
+            )
+        "
+    )
+    .unwrap();
     static ref RE_INT: Regex = Regex::new(r"^-?[0-9]+").unwrap();
+    static ref RE_RADIX_INT: Regex =
+        Regex::new(r"^#([xX][+-]?[0-9a-fA-F]+|[bB][+-]?[01]+|[oO][+-]?[0-7]+|[dD][+-]?[0-9]+)")
+            .unwrap();
+    static ref RE_BOOL: Regex = Regex::new(r"^#[a-zA-Z]+").unwrap();
     static ref RE_COMMENT: Regex = Regex::new(r"^;[^\n]*").unwrap();
     static ref RE_CHAR: Regex = Regex::new(r"^.").unwrap();
 }
@@ -75,7 +106,34 @@ fn t_int(val: &str) -> TResult {
     }
 }
 
+fn t_radix_int(val: &str) -> TResult {
+    let radix = match &val[1..2] {
+        "x" | "X" => 16,
+        "b" | "B" => 2,
+        "o" | "O" => 8,
+        _ => 10,
+    };
+    match JTInt::from_str_radix(&val[2..], radix) {
+        Ok(n) => Ok(TokenValue::Int(n)),
+        Err(e) => Err(format!("int error: {}", e)),
+    }
+}
+
+fn t_bool(val: &str) -> TResult {
+    match val {
+        "#t" | "#true" => Ok(TokenValue::Bool(true)),
+        "#f" | "#false" => Ok(TokenValue::Bool(false)),
+        _ => Err(format!("invalid literal {}", val)),
+    }
+}
+
 fn t_float(val: &str) -> TResult {
+    match val {
+        "+inf.0" => return Ok(TokenValue::Float(JTFloat::INFINITY)),
+        "-inf.0" => return Ok(TokenValue::Float(JTFloat::NEG_INFINITY)),
+        "+nan.0" | "-nan.0" => return Ok(TokenValue::Float(JTFloat::NAN)),
+        _ => (),
+    }
     match val.parse::<JTFloat>() {
         Ok(n) => Ok(TokenValue::Float(n)),
         Err(e) => Err(format!("float error: {}", e)),
@@ -86,20 +144,118 @@ fn t_ident(val: &str) -> TResult {
     Ok(TokenValue::Ident(val.to_string()))
 }
 
+/// Replace escape sequences; unknown escapes are kept as is, e.g. `\d` stays `\d`.
+fn unescape(val: &str) -> TResult {
+    let mut s = String::with_capacity(val.len());
+    let mut chars = val.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            s.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => s.push('\n'),
+            Some('t') => s.push('\t'),
+            Some('r') => s.push('\r'),
+            Some('0') => s.push('\0'),
+            Some('"') => s.push('"'),
+            Some('\\') => s.push('\\'),
+            Some(c) => {
+                s.push('\\');
+                s.push(c);
+            }
+            None => s.push('\\'),
+        }
+    }
+    Ok(TokenValue::String(s))
+}
+
 fn t_string(val: &str) -> TResult {
-    Ok(TokenValue::String(val[1..val.len() - 1].to_string()))
+    unescape(&val[1..val.len() - 1])
+}
+
+/// Strip the newline following the opening quotes, the last line if it is blank
+/// (the indentation of the closing quotes), and the common indentation of all lines.
+fn dedent(val: &str) -> String {
+    let val = val.strip_prefix('\n').unwrap_or(val);
+    let mut lines: Vec<&str> = val.split('\n').collect();
+    if lines.len() > 1 && lines.last().unwrap().trim().is_empty() {
+        lines.pop();
+    }
+    let indent_len = |l: &str| l.len() - l.trim_start_matches([' ', '\t']).len();
+    let indent = lines
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| indent_len(l))
+        .min()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .map(|l| &l[indent.min(indent_len(l))..])
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+fn t_triple_string(val: &str) -> TResult {
+    unescape(&dedent(&val[3..val.len() - 3]))
 }
 
 fn t_char(s: &str) -> TResult {
-    Ok(TokenValue::Char(s.chars().into_iter().next().unwrap()))
+    Ok(TokenValue::Char(s.chars().next().unwrap()))
 }
 
+/// Lines read on demand by a tokenizer, see [`StreamTokenizer`].
+struct LineSource {
+    reader: Box<dyn BufRead>,
+    done: bool,
+}
+
+impl LineSource {
+    /// Read lines up to one containing `until`, or a single line if it is `None`.
+    fn read(&mut self, until: Option<&str>) -> std::io::Result<String> {
+        let mut lines = String::new();
+        while !self.done {
+            let start = lines.len();
+            if self.reader.read_line(&mut lines)? == 0 {
+                self.done = true;
+            } else if until.is_none() || lines[start..].contains(until.unwrap()) {
+                break;
+            }
+        }
+        Ok(lines)
+    }
+}
+
+#[derive(Clone)]
 pub struct Tokenizer {
     filename: String,
-    input: String,
+    input: Rc<str>,
+    /// Length of the input already consumed and dropped, see `Tokenizer::push_input`.
+    offset: usize,
+    pos: usize,
+    lineno: usize,
+    /// Start of the current line, including the dropped input.
+    line_start: usize,
+    /// Whether readtable handlers are called, see `Tokenizer::without_char_macros`.
+    char_macros: bool,
+    /// Where more input is read from when the input ends in the middle of a token.
+    source: Option<Rc<RefCell<LineSource>>>,
+}
+
+/// Input skipped between tokens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Trivia {
+    Whitespace,
+    LineComment,
+    BlockComment,
+}
+
+/// A position in the input of a tokenizer, valid until more input is pushed.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Mark {
     pos: usize,
     lineno: usize,
-    last_newline_pos: usize,
+    line_start: usize,
 }
 
 impl Tokenizer {
@@ -109,17 +265,119 @@ impl Tokenizer {
     pub fn with_lineno(filename: String, input: String, lineno: usize) -> Self {
         Self {
             filename,
-            input,
+            input: input.into(),
+            offset: 0,
             pos: 0,
             lineno,
-            last_newline_pos: 0,
+            line_start: 0,
+            char_macros: true,
+            source: None,
+        }
+    }
+    /// Do not call readtable handlers, which may have side effects: the characters
+    /// of their keys are read as punctuation. Used to lex source that is not
+    /// evaluated, e.g. to validate or format it.
+    pub fn without_char_macros(mut self) -> Self {
+        self.char_macros = false;
+        self
+    }
+    pub(crate) fn mark(&self) -> Mark {
+        Mark {
+            pos: self.pos,
+            lineno: self.lineno,
+            line_start: self.line_start,
+        }
+    }
+    pub(crate) fn reset(&mut self, mark: Mark) {
+        self.pos = mark.pos;
+        self.lineno = mark.lineno;
+        self.line_start = mark.line_start;
+    }
+    /// Append to the input, dropping the input consumed so far.
+    fn push_input(&mut self, s: &str) {
+        let mut input = String::with_capacity(self.input.len() - self.pos + s.len());
+        input.push_str(&self.input[self.pos..]);
+        input.push_str(s);
+        self.input = input.into();
+        self.offset += self.pos;
+        self.pos = 0;
+    }
+    /// Whether the rest of the input contains a complete line: input is read one
+    /// or more lines at a time, only the last line of the input can be incomplete.
+    fn has_line(&self) -> bool {
+        self.pos < self.input.len() && self.input.ends_with('\n')
+    }
+    /// Whether all the input was read from the source, if there is one.
+    fn done(&self) -> bool {
+        match &self.source {
+            Some(source) => source.borrow().done,
+            None => true,
+        }
+    }
+    /// Read more input from the source, up to a line containing `until`, if any.
+    /// Returns false at the end of the input.
+    pub(crate) fn fill(&mut self, until: Option<&str>) -> Result<bool, TokenError> {
+        let source = match &self.source {
+            Some(source) => Rc::clone(source),
+            None => return Ok(false),
+        };
+        let lines = source
+            .borrow_mut()
+            .read(until)
+            .map_err(|e| TokenError::new(&format!("read error: {}", e), self.ptag(self.pos)))?;
+        self.push_input(&lines);
+        Ok(!lines.is_empty())
+    }
+    /// Text which must be read for the incomplete token at the current position to
+    /// be complete, e.g. the closing quote of a string.
+    fn terminator(&self) -> Option<&'static str> {
+        let mut tokenizer = self.clone();
+        if matches!(tokenizer.skip_blank(), Err(e) if e.incomplete) {
+            return Some("|#");
         }
+        let rest = tokenizer.input[tokenizer.pos..].trim_start_matches('#');
+        match rest.starts_with('"') || rest.starts_with("r\"") {
+            true => Some("\""),
+            false => None,
+        }
+    }
+    /// Skip the next whitespace or comment, and return its kind.
+    pub(crate) fn next_trivia(&mut self) -> Result<Option<Trivia>, TokenError> {
+        Ok(if self.eat_whitespace() {
+            Some(Trivia::Whitespace)
+        } else if self.eat_comment() {
+            Some(Trivia::LineComment)
+        } else if self.eat_block_comment()? {
+            Some(Trivia::BlockComment)
+        } else {
+            None
+        })
+    }
+    /// Byte offset of the current position in the input.
+    pub(crate) fn offset(&self) -> usize {
+        self.offset + self.pos
+    }
+    pub(crate) fn position(&self) -> PositionTag {
+        self.ptag(self.pos)
+    }
+    /// Skip whitespace and comments.
+    pub(crate) fn skip_blank(&mut self) -> Result<(), TokenError> {
+        while self.eat_whitespace() || self.eat_comment() || self.eat_block_comment()? {}
+        Ok(())
+    }
+    pub(crate) fn peek_char(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+    pub(crate) fn next_char(&mut self) -> Option<char> {
+        let c = self.peek_char()?;
+        self.advance(self.pos + c.len_utf8());
+        Some(c)
     }
     fn ptag(&self, pos: usize) -> PositionTag {
         PositionTag {
             filename: self.filename.clone(),
             lineno: self.lineno,
-            col: pos - self.last_newline_pos,
+            col: self.offset + pos - self.line_start + 1,
         }
     }
 
@@ -133,41 +391,118 @@ impl Tokenizer {
         }
     }
 
+    /// Advance position to `end`, keeping track of newlines.
+    fn advance(&mut self, end: usize) {
+        for (p, c) in self.input[self.pos..end].char_indices() {
+            if c == '\n' {
+                self.lineno += 1;
+                self.line_start = self.offset + self.pos + p + 1;
+            }
+        }
+        self.pos = end;
+    }
+
     fn eat_whitespace(&mut self) -> bool {
         match RE_WS.find(&self.input[self.pos..]) {
             Some(mat) => {
-                let spos = self.pos;
-                self.pos += mat.end();
-                let mut newline_count = 0;
-                for (p, c) in mat.as_str().chars().enumerate() {
-                    if c == '\n' {
-                        newline_count += 1;
-                        self.last_newline_pos = spos + p;
-                    }
-                }
-                self.lineno += newline_count;
+                self.advance(self.pos + mat.end());
                 true
             }
             None => false,
         }
     }
 
+    /// Eat a (possibly nested) `#| ... |#` comment.
+    fn eat_block_comment(&mut self) -> Result<bool, TokenError> {
+        if !self.input[self.pos..].starts_with("#|") {
+            return Ok(false);
+        }
+        let spos = self.ptag(self.pos);
+        let mut depth = 0;
+        let mut p = self.pos;
+        while p < self.input.len() {
+            let rest = &self.input[p..];
+            if rest.starts_with("#|") {
+                depth += 1;
+                p += 2;
+            } else if rest.starts_with("|#") {
+                depth -= 1;
+                p += 2;
+                if depth == 0 {
+                    self.advance(p);
+                    return Ok(true);
+                }
+            } else {
+                p += rest.chars().next().unwrap().len_utf8();
+            }
+        }
+        Err(TokenError::incomplete("unterminated block comment", spos))
+    }
+
     fn try_token<T>(&mut self, re: &Regex, cons: T) -> Result<Option<Token>, TokenError>
     where
         T: Fn(&str) -> TResult,
     {
         match re.find(&self.input[self.pos..]) {
             Some(mat) => {
-                let spos = self.pos;
-                self.pos += mat.end();
-                match cons(mat.as_str()) {
-                    Ok(tokval) => Ok(Some(Token::new(tokval, self.ptag(spos)))),
-                    Err(reason) => Err(TokenError::new(&reason, self.ptag(spos))),
+                let ptag = self.ptag(self.pos);
+                let tokval = cons(mat.as_str());
+                self.advance(self.pos + mat.end());
+                match tokval {
+                    Ok(tokval) => Ok(Some(Token::new(tokval, ptag))),
+                    Err(reason) => Err(TokenError::new(&reason, ptag)),
                 }
             }
             None => Ok(None),
         }
     }
+
+    /// Raw strings have no escapes; they are delimited by `#r"` and `"#`, with the same
+    /// number of leading and trailing `#`, e.g. `##r"contains "#"##`.
+    fn try_raw_string(&mut self) -> Result<Option<Token>, TokenError> {
+        let rest = &self.input[self.pos..];
+        let hashes = rest.chars().take_while(|c| *c == '#').count();
+        if hashes == 0 || !rest[hashes..].starts_with("r\"") {
+            return Ok(None);
+        }
+        let start = hashes + 2;
+        let delim = format!("\"{}", "#".repeat(hashes));
+        let ptag = self.ptag(self.pos);
+        match rest[start..].find(&delim) {
+            Some(len) => {
+                let value = rest[start..start + len].to_string();
+                self.advance(self.pos + start + len + delim.len());
+                Ok(Some(Token::new(TokenValue::String(value), ptag)))
+            }
+            None => Err(TokenError::incomplete("unterminated raw string", ptag)),
+        }
+    }
+
+    /// Call the readtable handler for the character (or dispatch pair) at the
+    /// current position, if there is one.
+    fn try_char_macro(&mut self, state: &mut JState) -> Result<Option<Token>, TokenError> {
+        let cm = match state.readtable().lookup(&self.input[self.pos..]) {
+            Some(cm) => cm.clone(),
+            None => return Ok(None),
+        };
+        let ptag = self.ptag(self.pos);
+        if !self.char_macros {
+            let c = self.next_char().unwrap();
+            return Ok(Some(Token::new(TokenValue::Char(c), ptag)));
+        }
+        let mut after = self.clone();
+        after.advance(self.pos + cm.key().len());
+        let stream = Rc::new(CharStream::new(after));
+        match cm.call(&stream, state) {
+            Ok(val) => {
+                // The handler may have read more input from the source
+                *self = stream.tokenizer();
+                Ok(Some(Token::new(TokenValue::Datum(val), ptag)))
+            }
+            Err(je) if stream.at_eof() => Err(TokenError::incomplete(&format!("{}", je), ptag)),
+            Err(je) => Err(TokenError::new(&format!("{}", je), ptag)),
+        }
+    }
 }
 
 pub trait TokenProducer {
@@ -208,24 +543,83 @@ impl<T: TokenProducer> TokenToIter for T {
 }
 
 impl TokenProducer for Tokenizer {
-    fn next_token(&mut self, _state: &mut JState) -> Result<Token, TokenError> {
-        while self.eat_whitespace() || self.eat_comment() {}
+    fn next_token(&mut self, state: &mut JState) -> Result<Token, TokenError> {
+        if self.source.is_none() {
+            return self.scan_token(state);
+        }
+        loop {
+            if !self.done() && !self.has_line() {
+                self.fill(None)?;
+                continue;
+            }
+            let mark = self.mark();
+            match self.scan_token(state) {
+                // Wait for more input, the token may continue on the next lines
+                Ok(Token {
+                    value: TokenValue::Eof,
+                    ..
+                }) if !self.done() => (),
+                Err(e) if e.incomplete && !self.done() => (),
+                res => return res,
+            }
+            self.reset(mark);
+            let until = self.terminator();
+            self.fill(until)?;
+        }
+    }
+}
+
+impl Tokenizer {
+    fn scan_token(&mut self, state: &mut JState) -> Result<Token, TokenError> {
+        self.skip_blank()?;
 
         if self.pos >= self.input.len() {
             return Ok(Token::new(TokenValue::Eof, self.ptag(self.pos)));
         }
+        if let Some(token) = self.try_char_macro(state)? {
+            return Ok(token);
+        }
+        if self.input[self.pos..].starts_with("#;") {
+            let spos = self.pos;
+            self.pos += 2;
+            return Ok(Token::new(TokenValue::DatumComment, self.ptag(spos)));
+        }
         if let Some(token) = self.try_token(&RE_FLOAT, t_float)? {
             return Ok(token);
         }
         if let Some(token) = self.try_token(&RE_INT, t_int)? {
             return Ok(token);
         }
+        if let Some(token) = self.try_raw_string()? {
+            return Ok(token);
+        }
+        if let Some(token) = self.try_token(&RE_RADIX_INT, t_radix_int)? {
+            return Ok(token);
+        }
+        if let Some(token) = self.try_token(&RE_BOOL, t_bool)? {
+            return Ok(token);
+        }
         if let Some(token) = self.try_token(&RE_IDENT, t_ident)? {
             return Ok(token);
         }
+        if let Some(token) = self.try_token(&RE_TRIPLE_STRING, t_triple_string)? {
+            return Ok(token);
+        }
+        if self.input[self.pos..].starts_with("\"\"\"") {
+            return Err(TokenError::incomplete(
+                "unterminated string",
+                self.ptag(self.pos),
+            ));
+        }
         if let Some(token) = self.try_token(&RE_STRING, t_string)? {
             return Ok(token);
         }
+        if self.input[self.pos..].starts_with('"') {
+            return Err(TokenError::incomplete(
+                "unterminated string",
+                self.ptag(self.pos),
+            ));
+        }
         if let Some(token) = self.try_token(&RE_CHAR, t_char)? {
             return Ok(token);
         }
@@ -239,6 +633,37 @@ impl TokenProducer for Tokenizer {
     }
 }
 
+/// Tokenizer reading its input incrementally, one line at a time, from a reader.
+///
+/// Tokens are produced as soon as they are complete, so forms can be evaluated
+/// before the rest of the input is available (e.g. a pipe or a socket). Tokens
+/// and char macros spanning several lines read more lines as they need them,
+/// rather than being read again from their start.
+pub struct StreamTokenizer<R: BufRead> {
+    tokenizer: Tokenizer,
+    reader: PhantomData<R>,
+}
+
+impl<R: BufRead + 'static> StreamTokenizer<R> {
+    pub fn new(filename: String, reader: R) -> Self {
+        let mut tokenizer = Tokenizer::new(filename, String::new());
+        tokenizer.source = Some(Rc::new(RefCell::new(LineSource {
+            reader: Box::new(reader),
+            done: false,
+        })));
+        Self {
+            tokenizer,
+            reader: PhantomData,
+        }
+    }
+}
+
+impl<R: BufRead> TokenProducer for StreamTokenizer<R> {
+    fn next_token(&mut self, state: &mut JState) -> Result<Token, TokenError> {
+        self.tokenizer.next_token(state)
+    }
+}
+
 impl TokenProducer for std::vec::IntoIter<Token> {
     fn next_token(&mut self, _: &mut JState) -> Result<Token, TokenError> {
         match self.next() {
@@ -250,8 +675,8 @@ impl TokenProducer for std::vec::IntoIter<Token> {
 
 pub struct TokenValidator<'a> {
     filename: String,
-    balance: Vec<TokenValue>,
-    tokens: Vec<Token>,
+    input: String,
+    start_lineno: usize,
     lineno: usize,
     state: &'a mut JState,
 }
@@ -261,36 +686,58 @@ impl<'a> TokenValidator<'a> {
     pub fn new(filename: &str, state: &'a mut JState) -> Self {
         Self {
             filename: filename.to_string(),
-            balance: vec![],
-            tokens: vec![],
+            input: String::new(),
+            start_lineno: 1,
             lineno: 0,
             state,
         }
     }
-    /// Returns None when more input is expected based on counting parens.
-    /// Returns tokens when it looks like it may form a complete expression.
-    pub fn input(&mut self, s: String) -> Result<Option<Vec<Token>>, TokenError> {
+    /// Returns None when more input is expected based on counting parens, or
+    /// because of an unterminated block comment.
+    /// Returns a tokenizer of the input when it looks like it may form a complete
+    /// expression. Char macros are not called while validating, only when the
+    /// returned tokenizer is read.
+    pub fn input(&mut self, s: String) -> Result<Option<Tokenizer>, TokenError> {
         self.lineno += 1;
-        let mut tokenizer = Tokenizer::with_lineno(self.filename.clone(), s, self.lineno);
-        let new_toks: Vec<Token> = tokenizer
+        if self.input.is_empty() {
+            self.start_lineno = self.lineno;
+        }
+        self.input.push_str(&s);
+        self.input.push('\n');
+        let tokenizer =
+            Tokenizer::with_lineno(self.filename.clone(), self.input.clone(), self.start_lineno);
+        let tokens = match tokenizer
+            .clone()
+            .without_char_macros()
             .to_iter(self.state)
-            .collect::<Result<Vec<Token>, TokenError>>()?;
-        for tok in new_toks {
+            .collect::<Result<Vec<Token>, TokenError>>()
+        {
+            Ok(tokens) => tokens,
+            Err(e) if e.incomplete => return Ok(None),
+            Err(e) => {
+                self.input.clear();
+                return Err(e);
+            }
+        };
+        let mut depth = 0;
+        for tok in &tokens {
             match tok.value {
-                TokenValue::Char('(') => self.balance.push(TokenValue::Char('(')),
-                TokenValue::Char(')') => match self.balance.pop() {
-                    Some(TokenValue::Char('(')) => (),
-                    _ => return Err(TokenError::new("unexpected closing parens", tok.pos)),
-                },
+                TokenValue::Char('(') => depth += 1,
+                TokenValue::Char(')') if depth == 0 => {
+                    self.input.clear();
+                    return Err(TokenError::new(
+                        "unexpected closing parens",
+                        tok.pos.clone(),
+                    ));
+                }
+                TokenValue::Char(')') => depth -= 1,
                 _ => (),
             }
-            self.tokens.push(tok);
         }
-        Ok(if self.balance.is_empty() {
-            Some(std::mem::take(&mut self.tokens))
+        Ok(if depth == 0 {
+            self.input.clear();
+            Some(tokenizer)
         } else {
-            // Remove the last EOF token
-            self.tokens.pop();
             None
         })
     }
@@ -300,6 +747,8 @@ impl<'a> TokenValidator<'a> {
 pub struct TokenError {
     pub pos: PositionTag,
     pub reason: String,
+    /// The error may be resolved by more input (e.g. unterminated comment).
+    pub incomplete: bool,
 }
 
 impl fmt::Display for TokenError {
@@ -313,6 +762,13 @@ impl TokenError {
         Self {
             pos,
             reason: reason.to_string(),
+            incomplete: false,
+        }
+    }
+    pub fn incomplete(reason: &str, pos: PositionTag) -> Self {
+        Self {
+            incomplete: true,
+            ..Self::new(reason, pos)
         }
     }
 }
@@ -430,4 +886,170 @@ mod tests {
             ],
         );
     }
+
+    #[test]
+    fn test_tokenizer_8() {
+        test_tokenizer(
+            "#t #f #true #false #xff #b-101 #o17 #d10",
+            vec![
+                TokenValue::Bool(true),
+                TokenValue::Bool(false),
+                TokenValue::Bool(true),
+                TokenValue::Bool(false),
+                TokenValue::Int(255),
+                TokenValue::Int(-5),
+                TokenValue::Int(15),
+                TokenValue::Int(10),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_tokenizer_9() {
+        test_tokenizer(
+            "1e10 -1.5E-3 2.e2 +inf.0 -inf.0 -inf",
+            vec![
+                TokenValue::Float(1e10),
+                TokenValue::Float(-1.5e-3),
+                TokenValue::Float(200.0),
+                TokenValue::Float(JTFloat::INFINITY),
+                TokenValue::Float(JTFloat::NEG_INFINITY),
+                TokenValue::Ident("-inf".to_string()),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_tokenizer_10() {
+        test_tokenizer(
+            r#""a \"quoted\" \\ string\n""#,
+            vec![TokenValue::String("a \"quoted\" \\ string\n".to_string())],
+        );
+        test_tokenizer(
+            r#""\d+ \x""#,
+            vec![TokenValue::String("\\d+ \\x".to_string())],
+        );
+    }
+
+    #[test]
+    fn test_tokenizer_block_comment() {
+        let input = "(a #| outer #| inner |# still\ncommented |# b)\n#| x |#c";
+        let mut tokenizer = Tokenizer::new("test".to_string(), input.to_string());
+        let mut state = JState::new();
+        let tokens = tokenizer
+            .to_iter(&mut state)
+            .collect::<Result<Vec<Token>, TokenError>>()
+            .unwrap();
+        let tokvalues: Vec<TokenValue> = tokens.iter().map(|t| t.value.clone()).collect();
+        assert_eq!(
+            vec![
+                TokenValue::Char('('),
+                TokenValue::Ident("a".to_string()),
+                TokenValue::Ident("b".to_string()),
+                TokenValue::Char(')'),
+                TokenValue::Ident("c".to_string()),
+            ],
+            tokvalues
+        );
+        assert_eq!(PositionTag::new("test", 2, 14), tokens[2].pos);
+        assert_eq!(PositionTag::new("test", 3, 8), tokens[4].pos);
+
+        let mut tokenizer = Tokenizer::new("test".to_string(), "#| #| |#".to_string());
+        let err = tokenizer.next_token(&mut state).unwrap_err();
+        assert!(err.incomplete);
+    }
+
+    #[test]
+    fn test_tokenizer_datum_comment() {
+        test_tokenizer(
+            "#;(a) b",
+            vec![
+                TokenValue::DatumComment,
+                TokenValue::Char('('),
+                TokenValue::Ident("a".to_string()),
+                TokenValue::Char(')'),
+                TokenValue::Ident("b".to_string()),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_token_validator() {
+        let mut state = JState::new();
+        let mut validator = TokenValidator::new("test", &mut state);
+        assert!(validator.input("(foo #| (".to_string()).unwrap().is_none());
+        assert!(validator
+            .input(") |# #;(bar".to_string())
+            .unwrap()
+            .is_none());
+        let mut tokenizer = validator.input(") baz)".to_string()).unwrap().unwrap();
+        let tokens = tokenizer
+            .to_iter(&mut state)
+            .collect::<Result<Vec<Token>, TokenError>>()
+            .unwrap();
+        assert_eq!(8, tokens.len());
+        assert_eq!(PositionTag::new("test", 3, 6), tokens[7].pos);
+        let mut validator = TokenValidator::new("test", &mut state);
+        assert!(validator.input(")".to_string()).is_err());
+        assert!(validator.input("1".to_string()).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_tokenizer_raw_string() {
+        test_tokenizer(
+            r###"#r"C:\path\"# ##r"say "#hi"#"## #r"""#"###,
+            vec![
+                TokenValue::String(r"C:\path\".to_string()),
+                TokenValue::String(r##"say "#hi"#"##.to_string()),
+                TokenValue::String(r#"""#.to_string()),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_tokenizer_triple_string() {
+        let input = "(f \"\"\"\n    SELECT *\n      FROM \"t\"\\n\n    \"\"\" x)";
+        let mut tokenizer = Tokenizer::new("test".to_string(), input.to_string());
+        let mut state = JState::new();
+        let tokens = tokenizer
+            .to_iter(&mut state)
+            .collect::<Result<Vec<Token>, TokenError>>()
+            .unwrap();
+        assert_eq!(
+            TokenValue::String("SELECT *\n  FROM \"t\"\n".to_string()),
+            tokens[2].value
+        );
+        assert_eq!(PositionTag::new("test", 4, 9), tokens[3].pos);
+
+        let mut tokenizer = Tokenizer::new("test".to_string(), "\"\"\"abc".to_string());
+        assert!(tokenizer.next_token(&mut state).unwrap_err().incomplete);
+    }
+
+    #[test]
+    fn test_stream_tokenizer() {
+        let input = "(a #| multi\nline |# \"str\ning\")\n\n  #r\"\n\"# \"\"\"\n  x\n  \"\"\" b";
+        let mut state = JState::new();
+        let expected = Tokenizer::new("test".to_string(), input.to_string())
+            .to_iter(&mut state)
+            .collect::<Result<Vec<Token>, TokenError>>()
+            .unwrap();
+        let tokens = StreamTokenizer::new("test".to_string(), input.as_bytes())
+            .to_iter(&mut state)
+            .collect::<Result<Vec<Token>, TokenError>>()
+            .unwrap();
+        assert_eq!(expected, tokens);
+        assert_eq!(
+            expected
+                .iter()
+                .map(|t| &t.pos)
+                .collect::<Vec<&PositionTag>>(),
+            tokens.iter().map(|t| &t.pos).collect::<Vec<&PositionTag>>()
+        );
+        assert_eq!(PositionTag::new("test", 8, 7), tokens[6].pos);
+
+        let mut tokenizer = StreamTokenizer::new("test".to_string(), "(a \"b".as_bytes());
+        tokenizer.next_token(&mut state).unwrap();
+        tokenizer.next_token(&mut state).unwrap();
+        assert!(tokenizer.next_token(&mut state).unwrap_err().incomplete);
+    }
 }
@@ -1,13 +1,13 @@
 use crate::*;
 
 pub fn repr(expr: &JVal) -> String {
-    match &*expr {
+    match expr {
         JVal::Nil => "()".to_string(),
         JVal::Int(n) => format!("{}", n),
-        JVal::Float(x) => format!("{}", x),
-        JVal::Bool(b) => (if *b { "true" } else { "false" }).to_string(),
+        JVal::Float(x) => repr_float(*x),
+        JVal::Bool(b) => (if *b { "#t" } else { "#f" }).to_string(),
         JVal::Symbol(s) => s.to_string(),
-        JVal::String(s) => format!("\"{}\"", s),
+        JVal::String(s) => repr_string(s),
         JVal::Error(e) => format!("#[error {}]", e),
         JVal::Builtin(b) => format!("#[function {}]", b),
         JVal::SpecialForm(b) => format!("#[specialform {}]", b),
@@ -15,16 +15,46 @@ pub fn repr(expr: &JVal) -> String {
         JVal::Macro(l) => format!("#[macro {}]", l),
         JVal::Pair(c) => repr_pair(c),
         JVal::Vector(v) => repr_vec(v),
-        JVal::Quote(val) => format!("'{}", repr(&*val)),
-        JVal::Quasiquote(val) => format!("`{}", repr(&*val)),
-        JVal::Unquote(val) => format!(",{}", repr(&*val)),
-        JVal::UnquoteSplice(val) => format!(",@{}", repr(&*val)),
+        JVal::Quote(val) => format!("'{}", repr(val)),
+        JVal::Quasiquote(val) => format!("`{}", repr(val)),
+        JVal::Unquote(val) => format!(",{}", repr(val)),
+        JVal::UnquoteSplice(val) => format!(",@{}", repr(val)),
         JVal::Env(env) => format!("{}", env),
         JVal::Token(t) => format!("#[token {}]", t),
         JVal::TokenMatcher(tm) => format!("#[tokenmatcher {}]", tm),
+        JVal::Foreign(f) => f.repr(),
     }
 }
 
+fn repr_float(x: JTFloat) -> String {
+    if x.is_nan() {
+        "+nan.0".to_string()
+    } else if x.is_infinite() {
+        (if x > 0.0 { "+inf.0" } else { "-inf.0" }).to_string()
+    } else {
+        // Debug formatting always includes a decimal point or an exponent
+        format!("{:?}", x)
+    }
+}
+
+fn repr_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\0' => out.push_str("\\0"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
 fn repr_vec(v: &JVector) -> String {
     let vecref = v.borrow();
     format!(
@@ -0,0 +1,280 @@
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+
+use crate::*;
+
+/// Deserialize Rust values from `jibi` values, reversing the mapping of
+/// [`Serializer`](crate::Serializer).
+///
+/// Vectors are accepted wherever lists are expected.
+pub struct Deserializer {
+    val: JValRef,
+}
+
+impl Deserializer {
+    pub fn new(val: JValRef) -> Self {
+        Self { val }
+    }
+}
+
+/// Convert a `jibi` value to a Rust value.
+pub fn from_jval<T: DeserializeOwned>(val: JValRef) -> Result<T, JError> {
+    T::deserialize(Deserializer::new(val))
+}
+
+impl de::Error for JError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        JError::new(Other("SerdeError".to_string()), &msg.to_string())
+    }
+}
+
+fn seq_items(val: &JVal) -> Result<Vec<JValRef>, JError> {
+    match val {
+        JVal::Vector(v) => Ok(v.borrow().iter().map(Rc::clone).collect()),
+        _ => Ok(val.iter_list()?.collect()),
+    }
+}
+
+fn visit_int<'de, V: Visitor<'de>>(n: JTInt, visitor: V) -> Result<V::Value, JError> {
+    if let Ok(n) = i64::try_from(n) {
+        visitor.visit_i64(n)
+    } else if let Ok(n) = u64::try_from(n) {
+        visitor.visit_u64(n)
+    } else {
+        visitor.visit_i128(n)
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+    type Error = JError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, JError> {
+        match &*self.val {
+            JVal::Nil => visitor.visit_unit(),
+            JVal::Bool(b) => visitor.visit_bool(*b),
+            JVal::Int(n) => visit_int(*n, visitor),
+            JVal::Float(x) => visitor.visit_f64(*x),
+            JVal::String(s) => visitor.visit_str(s),
+            JVal::Symbol(s) => visitor.visit_str(s),
+            JVal::Pair(_) | JVal::Vector(_) => self.deserialize_seq(visitor),
+            _ => Err(JError::new(
+                TypeError,
+                &format!("cannot deserialize {}", repr(&self.val)),
+            )),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, JError> {
+        match &*self.val {
+            JVal::Nil => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, JError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, JError> {
+        let items = seq_items(&self.val)?;
+        visitor.visit_seq(SeqAccess {
+            items: items.into_iter(),
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, JError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, JError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, JError> {
+        let entries = seq_items(&self.val)?
+            .iter()
+            .map(|e| e.to_pair().map(|p| (p.car(), p.cdr())))
+            .collect::<Result<Vec<(JValRef, JValRef)>, JError>>()?;
+        visitor.visit_map(MapAccess {
+            entries: entries.into_iter(),
+            value: None,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, JError> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, JError> {
+        match &*self.val {
+            JVal::Symbol(s) | JVal::String(s) => visitor.visit_enum(s.as_str().into_deserializer()),
+            JVal::Pair(p) => {
+                let variant = p.car().to_symbol()?.to_string();
+                let fields: Vec<JValRef> = p.cdr().iter_list()?.collect();
+                visitor.visit_enum(EnumAccess { variant, fields })
+            }
+            _ => Err(JError::new(
+                TypeError,
+                "expected a symbol or a tagged list for enum",
+            )),
+        }
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, JError> {
+        match &*self.val {
+            JVal::Symbol(s) | JVal::String(s) => visitor.visit_str(s),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct ignored_any
+    }
+}
+
+struct SeqAccess {
+    items: std::vec::IntoIter<JValRef>,
+}
+
+impl<'de> de::SeqAccess<'de> for SeqAccess {
+    type Error = JError;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, JError> {
+        match self.items.next() {
+            Some(val) => seed.deserialize(Deserializer::new(val)).map(Some),
+            None => Ok(None),
+        }
+    }
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+struct MapAccess {
+    entries: std::vec::IntoIter<(JValRef, JValRef)>,
+    value: Option<JValRef>,
+}
+
+impl<'de> de::MapAccess<'de> for MapAccess {
+    type Error = JError;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, JError> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(Deserializer::new(key)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, JError> {
+        match self.value.take() {
+            Some(value) => seed.deserialize(Deserializer::new(value)),
+            None => Err(de::Error::custom("map value without a key")),
+        }
+    }
+}
+
+struct EnumAccess {
+    variant: String,
+    fields: Vec<JValRef>,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess {
+    type Error = JError;
+    type Variant = VariantAccess;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, VariantAccess), JError> {
+        let variant: de::value::StrDeserializer<JError> = self.variant.as_str().into_deserializer();
+        let value = seed.deserialize(variant)?;
+        Ok((
+            value,
+            VariantAccess {
+                fields: self.fields,
+            },
+        ))
+    }
+}
+
+struct VariantAccess {
+    fields: Vec<JValRef>,
+}
+
+impl<'de> de::VariantAccess<'de> for VariantAccess {
+    type Error = JError;
+
+    fn unit_variant(self) -> Result<(), JError> {
+        if self.fields.is_empty() {
+            Ok(())
+        } else {
+            Err(de::Error::custom("unexpected values for unit variant"))
+        }
+    }
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(
+        mut self,
+        seed: T,
+    ) -> Result<T::Value, JError> {
+        if self.fields.len() != 1 {
+            return Err(de::Error::custom("expected 1 value for newtype variant"));
+        }
+        seed.deserialize(Deserializer::new(self.fields.pop().unwrap()))
+    }
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, JError> {
+        visitor.visit_seq(SeqAccess {
+            items: self.fields.into_iter(),
+        })
+    }
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, JError> {
+        let entries = self
+            .fields
+            .iter()
+            .map(|e| e.to_pair().map(|p| (p.car(), p.cdr())))
+            .collect::<Result<Vec<(JValRef, JValRef)>, JError>>()?;
+        visitor.visit_map(MapAccess {
+            entries: entries.into_iter(),
+            value: None,
+        })
+    }
+}
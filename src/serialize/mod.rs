@@ -0,0 +1,6 @@
+//! Conversions between Rust values and `jibi` values with `serde`.
+mod de;
+mod ser;
+
+pub use de::{from_jval, Deserializer};
+pub use ser::{to_jval, Serializer};
@@ -0,0 +1,360 @@
+use std::cell::RefCell;
+use std::convert::TryFrom;
+
+use serde::ser::{self, Serialize};
+
+use crate::*;
+
+/// Serialize Rust values to `jibi` values.
+///
+/// * Structs and maps become association lists: `((key . value) ...)`,
+///   struct field names are symbols.
+/// * Sequences and tuples become lists (or vectors, see [`Serializer::vectors`]).
+/// * Unit variants become symbols, other enum variants become lists tagged by the
+///   variant name: `(Variant value ...)`.
+/// * `None` and unit become `nil`.
+///
+/// `Some` of a value which becomes `nil`, e.g. `Some(vec![])`, can not be told
+/// apart from `None` and deserializes as `None`. Serialize sequences as vectors to
+/// keep empty sequences distinct.
+pub struct Serializer<'a> {
+    state: &'a mut JState,
+    seq_as_vector: bool,
+}
+
+impl<'a> Serializer<'a> {
+    pub fn new(state: &'a mut JState) -> Self {
+        Self {
+            state,
+            seq_as_vector: false,
+        }
+    }
+    /// Serialize sequences as vectors instead of lists.
+    pub fn vectors(mut self) -> Self {
+        self.seq_as_vector = true;
+        self
+    }
+    fn seq(&mut self, vals: Vec<JValRef>) -> JValRef {
+        if self.seq_as_vector {
+            JVal::Vector(RefCell::new(vals)).into_ref()
+        } else {
+            self.state.list(vals)
+        }
+    }
+    fn tagged(&mut self, variant: &str, mut vals: Vec<JValRef>) -> JValRef {
+        vals.insert(0, self.state.symbol(variant.to_string()));
+        self.state.list(vals)
+    }
+}
+
+/// Convert a Rust value to a `jibi` value.
+pub fn to_jval<T: Serialize + ?Sized>(value: &T, state: &mut JState) -> JResult {
+    value.serialize(&mut Serializer::new(state))
+}
+
+impl ser::Error for JError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        JError::new(Other("SerdeError".to_string()), &msg.to_string())
+    }
+}
+
+pub struct SeqSerializer<'s, 'a> {
+    ser: &'s mut Serializer<'a>,
+    variant: Option<&'static str>,
+    vals: Vec<JValRef>,
+}
+
+pub struct MapSerializer<'s, 'a> {
+    ser: &'s mut Serializer<'a>,
+    variant: Option<&'static str>,
+    key: Option<JValRef>,
+    vals: Vec<JValRef>,
+}
+
+impl<'s, 'a> ser::Serializer for &'s mut Serializer<'a> {
+    type Ok = JValRef;
+    type Error = JError;
+    type SerializeSeq = SeqSerializer<'s, 'a>;
+    type SerializeTuple = SeqSerializer<'s, 'a>;
+    type SerializeTupleStruct = SeqSerializer<'s, 'a>;
+    type SerializeTupleVariant = SeqSerializer<'s, 'a>;
+    type SerializeMap = MapSerializer<'s, 'a>;
+    type SerializeStruct = MapSerializer<'s, 'a>;
+    type SerializeStructVariant = MapSerializer<'s, 'a>;
+
+    fn serialize_bool(self, v: bool) -> JResult {
+        Ok(self.state.bool(v))
+    }
+    fn serialize_i8(self, v: i8) -> JResult {
+        Ok(self.state.int(v.into()))
+    }
+    fn serialize_i16(self, v: i16) -> JResult {
+        Ok(self.state.int(v.into()))
+    }
+    fn serialize_i32(self, v: i32) -> JResult {
+        Ok(self.state.int(v.into()))
+    }
+    fn serialize_i64(self, v: i64) -> JResult {
+        Ok(self.state.int(v.into()))
+    }
+    fn serialize_i128(self, v: i128) -> JResult {
+        Ok(self.state.int(v))
+    }
+    fn serialize_u8(self, v: u8) -> JResult {
+        Ok(self.state.int(v.into()))
+    }
+    fn serialize_u16(self, v: u16) -> JResult {
+        Ok(self.state.int(v.into()))
+    }
+    fn serialize_u32(self, v: u32) -> JResult {
+        Ok(self.state.int(v.into()))
+    }
+    fn serialize_u64(self, v: u64) -> JResult {
+        Ok(self.state.int(v.into()))
+    }
+    fn serialize_u128(self, v: u128) -> JResult {
+        let n = JTInt::try_from(v)
+            .map_err(|_| JError::new(Other("IntError".to_string()), "overflow"))?;
+        Ok(self.state.int(n))
+    }
+    fn serialize_f32(self, v: f32) -> JResult {
+        Ok(self.state.float(v.into()))
+    }
+    fn serialize_f64(self, v: f64) -> JResult {
+        Ok(self.state.float(v))
+    }
+    fn serialize_char(self, v: char) -> JResult {
+        Ok(self.state.string(v.to_string()))
+    }
+    fn serialize_str(self, v: &str) -> JResult {
+        Ok(self.state.string(v.to_string()))
+    }
+    fn serialize_bytes(self, v: &[u8]) -> JResult {
+        let vals = v.iter().map(|b| self.state.int((*b).into())).collect();
+        Ok(self.seq(vals))
+    }
+    fn serialize_none(self) -> JResult {
+        Ok(self.state.nil())
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> JResult {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> JResult {
+        Ok(self.state.nil())
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> JResult {
+        Ok(self.state.nil())
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> JResult {
+        Ok(self.state.symbol(variant.to_string()))
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> JResult {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> JResult {
+        let val = value.serialize(&mut *self)?;
+        Ok(self.tagged(variant, vec![val]))
+    }
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, JError> {
+        Ok(SeqSerializer {
+            ser: self,
+            variant: None,
+            vals: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, JError> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, JError> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, JError> {
+        Ok(SeqSerializer {
+            ser: self,
+            variant: Some(variant),
+            vals: Vec::with_capacity(len),
+        })
+    }
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, JError> {
+        Ok(MapSerializer {
+            ser: self,
+            variant: None,
+            key: None,
+            vals: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, JError> {
+        self.serialize_map(Some(len))
+    }
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, JError> {
+        Ok(MapSerializer {
+            ser: self,
+            variant: Some(variant),
+            key: None,
+            vals: Vec::with_capacity(len),
+        })
+    }
+}
+
+impl SeqSerializer<'_, '_> {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), JError> {
+        let val = value.serialize(&mut *self.ser)?;
+        self.vals.push(val);
+        Ok(())
+    }
+    fn finish(self) -> JResult {
+        match self.variant {
+            Some(variant) => Ok(self.ser.tagged(variant, self.vals)),
+            None => Ok(self.ser.seq(self.vals)),
+        }
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer<'_, '_> {
+    type Ok = JValRef;
+    type Error = JError;
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), JError> {
+        self.push(value)
+    }
+    fn end(self) -> JResult {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer<'_, '_> {
+    type Ok = JValRef;
+    type Error = JError;
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), JError> {
+        self.push(value)
+    }
+    fn end(self) -> JResult {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer<'_, '_> {
+    type Ok = JValRef;
+    type Error = JError;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), JError> {
+        self.push(value)
+    }
+    fn end(self) -> JResult {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer<'_, '_> {
+    type Ok = JValRef;
+    type Error = JError;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), JError> {
+        self.push(value)
+    }
+    fn end(self) -> JResult {
+        self.finish()
+    }
+}
+
+impl MapSerializer<'_, '_> {
+    fn push_entry(&mut self, key: JValRef, value: JValRef) {
+        let entry = self.ser.state.pair(key, value);
+        self.vals.push(entry);
+    }
+    fn push_field<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<(), JError> {
+        let key = self.ser.state.symbol(key.to_string());
+        let value = value.serialize(&mut *self.ser)?;
+        self.push_entry(key, value);
+        Ok(())
+    }
+    fn finish(self) -> JResult {
+        match self.variant {
+            Some(variant) => Ok(self.ser.tagged(variant, self.vals)),
+            None => Ok(self.ser.state.list(self.vals)),
+        }
+    }
+}
+
+impl ser::SerializeMap for MapSerializer<'_, '_> {
+    type Ok = JValRef;
+    type Error = JError;
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), JError> {
+        self.key = Some(key.serialize(&mut *self.ser)?);
+        Ok(())
+    }
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), JError> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| <JError as ser::Error>::custom("map value without a key"))?;
+        let value = value.serialize(&mut *self.ser)?;
+        self.push_entry(key, value);
+        Ok(())
+    }
+    fn end(self) -> JResult {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer<'_, '_> {
+    type Ok = JValRef;
+    type Error = JError;
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), JError> {
+        self.push_field(key, value)
+    }
+    fn end(self) -> JResult {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for MapSerializer<'_, '_> {
+    type Ok = JValRef;
+    type Error = JError;
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), JError> {
+        self.push_field(key, value)
+    }
+    fn end(self) -> JResult {
+        self.finish()
+    }
+}
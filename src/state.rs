@@ -1,9 +1,17 @@
 use std::collections::HashMap;
+use std::io::{BufReader, Write};
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
+use crate::bench::BenchSession;
+use crate::coverage::Coverage;
+use crate::debugger::Debugger;
 use crate::import::find_module;
 use crate::intern::Interned;
+use crate::profiler::Profiler;
+use crate::reader::readtable::Readtable;
+use crate::testing::TestSession;
+use crate::trace::Tracer;
 use crate::*;
 
 const STR_INTERN_MAX_LEN: usize = 1024;
@@ -36,6 +44,17 @@ pub struct JState {
     traceback: Vec<TracebackFrame>,
     modules: HashMap<PathBuf, JEnvRef>,
     reader_macros: Vec<ReaderMacro>,
+    readtable: Readtable,
+    char_stream_type: Rc<JForeignType>,
+    builtin_keys: HashMap<usize, String>,
+    builtins: HashMap<String, JValRef>,
+    debugger: Option<Box<Debugger>>,
+    output: Option<Box<dyn Write>>,
+    tracer: Tracer,
+    profiler: Option<Box<Profiler>>,
+    coverage: Option<Box<Coverage>>,
+    test_session: Option<Box<TestSession>>,
+    bench_session: Option<Box<BenchSession>>,
 }
 
 impl JState {
@@ -55,9 +74,23 @@ impl JState {
             traceback: vec![],
             modules: HashMap::new(),
             reader_macros: vec![],
+            readtable: Readtable::default(),
+            char_stream_type: JForeignType::new("char-stream").into_ref(),
+            builtin_keys: HashMap::new(),
+            builtins: HashMap::new(),
+            debugger: None,
+            output: None,
+            tracer: Tracer::default(),
+            profiler: None,
+            coverage: None,
+            test_session: None,
+            bench_session: None,
         }
     }
-    fn update_pos(&mut self, pt: Option<&PositionTag>) {
+    pub(crate) fn pos(&self) -> &PositionTag {
+        &self.pos
+    }
+    pub(crate) fn update_pos(&mut self, pt: Option<&PositionTag>) {
         if let Some(pos) = pt {
             self.pos = pos.clone();
         }
@@ -80,6 +113,146 @@ impl JState {
     pub fn reader_macros(&self) -> &Vec<ReaderMacro> {
         &self.reader_macros
     }
+    pub(crate) fn set_reader_macros(&mut self, rms: Vec<ReaderMacro>) {
+        self.reader_macros = rms;
+    }
+    pub fn readtable(&self) -> &Readtable {
+        &self.readtable
+    }
+    pub fn readtable_mut(&mut self) -> &mut Readtable {
+        &mut self.readtable
+    }
+    pub(crate) fn set_readtable(&mut self, readtable: Readtable) {
+        self.readtable = readtable;
+    }
+    pub(crate) fn char_stream_type(&self) -> &Rc<JForeignType> {
+        &self.char_stream_type
+    }
+
+    /// Redirect output of `print` and other printing builtins, or restore stdout
+    /// with `None`.
+    pub fn set_output(&mut self, output: Option<Box<dyn Write>>) {
+        self.output = output;
+    }
+    pub(crate) fn println(&mut self, s: &str) {
+        match &mut self.output {
+            Some(output) => {
+                let _ = output.write_all(format!("{}\n", s).as_bytes());
+            }
+            None => println!("{}", s),
+        }
+    }
+
+    /// Trace calls to all named lambdas defined in a file, see the `trace` builtin.
+    pub fn trace_file(&mut self, filename: &str) {
+        self.tracer.trace_file(filename)
+    }
+    pub(crate) fn tracer(&self) -> &Tracer {
+        &self.tracer
+    }
+    pub(crate) fn tracer_mut(&mut self) -> &mut Tracer {
+        &mut self.tracer
+    }
+
+    /// Start profiling with `profiler`, or stop with `None`.
+    pub fn set_profiler(&mut self, profiler: Option<Profiler>) {
+        self.profiler = profiler.map(Box::new);
+    }
+    /// Stop profiling and return the profiler.
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take().map(|p| *p)
+    }
+    pub(crate) fn profiler_mut(&mut self) -> Option<&mut Profiler> {
+        self.profiler.as_deref_mut()
+    }
+
+    /// Start recording coverage with `coverage`, or stop with `None`.
+    pub fn set_coverage(&mut self, coverage: Option<Coverage>) {
+        self.coverage = coverage.map(Box::new);
+    }
+    /// Stop recording coverage and return it.
+    pub fn take_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take().map(|c| *c)
+    }
+    pub(crate) fn coverage_mut(&mut self) -> Option<&mut Coverage> {
+        self.coverage.as_deref_mut()
+    }
+    pub(crate) fn covering(&self) -> bool {
+        self.coverage.is_some()
+    }
+
+    pub(crate) fn set_test_session(&mut self, session: Option<TestSession>) {
+        self.test_session = session.map(Box::new);
+    }
+    pub(crate) fn take_test_session(&mut self) -> Option<TestSession> {
+        self.test_session.take().map(|s| *s)
+    }
+    pub(crate) fn test_session_mut(&mut self) -> Option<&mut TestSession> {
+        self.test_session.as_deref_mut()
+    }
+    pub(crate) fn running_tests(&self) -> bool {
+        self.test_session.is_some()
+    }
+
+    pub(crate) fn set_bench_session(&mut self, session: Option<BenchSession>) {
+        self.bench_session = session.map(Box::new);
+    }
+    pub(crate) fn take_bench_session(&mut self) -> Option<BenchSession> {
+        self.bench_session.take().map(|s| *s)
+    }
+    pub(crate) fn bench_session_mut(&mut self) -> Option<&mut BenchSession> {
+        self.bench_session.as_deref_mut()
+    }
+
+    /// Attach a debugger to pause evaluation, or detach it with `None`.
+    pub fn set_debugger(&mut self, debugger: Option<Debugger>) {
+        self.debugger = debugger.map(Box::new);
+    }
+    pub(crate) fn debugger_mut(&mut self) -> Option<&mut Debugger> {
+        self.debugger.as_deref_mut()
+    }
+    pub(crate) fn take_debugger(&mut self) -> Option<Debugger> {
+        self.debugger.take().map(|d| *d)
+    }
+    pub(crate) fn debugging(&self) -> bool {
+        self.debugger.is_some()
+    }
+
+    pub(crate) fn modules(&self) -> &HashMap<PathBuf, JEnvRef> {
+        &self.modules
+    }
+    pub(crate) fn set_modules(&mut self, modules: HashMap<PathBuf, JEnvRef>) {
+        self.modules = modules;
+    }
+
+    pub(crate) fn interned_symbols(&self) -> Vec<String> {
+        self.interned_sym.keys()
+    }
+    pub(crate) fn interned_strings(&self) -> Vec<String> {
+        self.interned_str.keys()
+    }
+
+    // Builtins are keyed by name and creation order among builtins of the same name,
+    // so that images can refer to the builtins of the interpreter that loads them.
+    fn register_builtin(&mut self, val: &JValRef) {
+        let b = match &**val {
+            JVal::Builtin(b) | JVal::SpecialForm(b) => b,
+            _ => return,
+        };
+        let mut n = 0;
+        while self.builtins.contains_key(&format!("{}#{}", b.name, n)) {
+            n += 1;
+        }
+        let key = format!("{}#{}", b.name, n);
+        self.builtin_keys.insert(b.id(), key.clone());
+        self.builtins.insert(key, Rc::clone(val));
+    }
+    pub(crate) fn builtin_key(&self, b: &JBuiltin) -> Option<&str> {
+        self.builtin_keys.get(&b.id()).map(|s| s.as_str())
+    }
+    pub(crate) fn builtin_by_key(&self, key: &str) -> Option<JValRef> {
+        self.builtins.get(key).map(Rc::clone)
+    }
 
     pub fn import_module<P: AsRef<Path>>(&mut self, p: P, env: JEnvRef) -> JResult {
         let p: &Path = p.as_ref();
@@ -115,16 +288,22 @@ impl JState {
         for rm in &self.reader_macros {
             tokens = Box::new(rm.apply(tokens));
         }
-        let forms = match Parser::new(tokens, self).parse_forms() {
-            Ok(forms) => forms,
-            Err(pe) => return Err((pe.pos.clone(), pe.into(), self.traceback_take())),
-        };
+        // Each form is evaluated as soon as it is parsed
+        let mut parser = Parser::new(tokens, self);
         let mut last_eval = None;
-        for (pos, expr) in forms {
-            self.update_pos(Some(&pos));
-            last_eval = match eval(expr, Rc::clone(&env), self) {
+        loop {
+            let (pos, expr) = match parser.parse_form() {
+                Ok(Some(form)) => form,
+                Ok(None) => break,
+                Err(pe) => {
+                    return Err((pe.pos.clone(), pe.into(), parser.state().traceback_take()))
+                }
+            };
+            let state = parser.state();
+            state.update_pos(Some(&pos));
+            last_eval = match eval(expr, Rc::clone(&env), state) {
                 Ok(val) => Some(val),
-                Err(je) => return Err((self.pos.clone(), je, self.traceback_take())),
+                Err(je) => return Err((state.pos.clone(), je, state.traceback_take())),
             }
         }
         Ok(last_eval)
@@ -146,8 +325,8 @@ impl JState {
         env: JEnvRef,
     ) -> Result<Option<JValRef>, (PositionTag, JError, Vec<TracebackFrame>)> {
         let path = path.as_ref();
-        let text = match std::fs::read_to_string(path) {
-            Ok(text) => text,
+        let file = match std::fs::File::open(path) {
+            Ok(file) => file,
             Err(e) => {
                 return Err((
                     PositionTag::new("", 0, 0),
@@ -156,7 +335,13 @@ impl JState {
                 ))
             }
         };
-        self.eval_str(&path.to_string_lossy(), &text, env)
+        self.eval_tokens(
+            Box::new(StreamTokenizer::new(
+                path.to_string_lossy().to_string(),
+                BufReader::new(file),
+            )),
+            env,
+        )
     }
 
     // Constructors
@@ -239,19 +424,18 @@ impl JState {
         }))
         .into_ref())
     }
-    pub fn builtin(
-        &self,
-        name: String,
-        f: Rc<dyn Fn(JValRef, JEnvRef, &mut JState) -> JResult>,
-    ) -> JValRef {
-        JVal::Builtin(JBuiltin::new(name, f)).into_ref()
-    }
-    pub fn specialform(
-        &self,
-        name: String,
-        f: Rc<dyn Fn(JValRef, JEnvRef, &mut JState) -> JResult>,
-    ) -> JValRef {
-        JVal::SpecialForm(JBuiltin::new(name, f)).into_ref()
+    pub fn builtin(&mut self, name: String, f: JBuiltinFn) -> JValRef {
+        let val = JVal::Builtin(JBuiltin::new(name, f)).into_ref();
+        self.register_builtin(&val);
+        val
+    }
+    pub fn specialform(&mut self, name: String, f: JBuiltinFn) -> JValRef {
+        let val = JVal::SpecialForm(JBuiltin::new(name, f)).into_ref();
+        self.register_builtin(&val);
+        val
+    }
+    pub fn foreign<T: 'static>(&self, ftype: &Rc<JForeignType>, obj: T) -> JValRef {
+        JVal::Foreign(JForeign::new(Rc::clone(ftype), obj)).into_ref()
     }
     pub fn token(&self, v: TokenValue) -> JResult {
         Ok(JVal::Token(Token::new(v, self.pos.clone())).into_ref())
@@ -0,0 +1,419 @@
+//! Test runner, used by `jibi test`.
+//!
+//! [`TestRunner`] runs each test file in a fresh interpreter, and records the
+//! tests run with the `run-test` special form and skipped with the `skip-test`
+//! builtin (used by the macros of `stl/unittest`): how they ended, with the
+//! traceback of failures, and their output.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::*;
+
+mod report;
+
+pub use report::{junit, tap, ReportFormat};
+
+/// How a test ended.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TestStatus {
+    Passed,
+    /// Traceback and error.
+    Failed(String),
+    /// Reason given to `skip`.
+    Skipped(String),
+    /// Traceback and error of a test marked with `expect-fail`.
+    ExpectedFailure(String),
+}
+
+/// Result of a test.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestResult {
+    /// Name of the `test-suite` running the test.
+    pub suite: Option<String>,
+    pub name: String,
+    pub status: TestStatus,
+    /// Output printed by the test.
+    pub output: String,
+    pub duration: Duration,
+}
+
+impl TestResult {
+    /// Whether the test did not fail: it passed, was skipped, or failed as expected.
+    pub fn passed(&self) -> bool {
+        !matches!(self.status, TestStatus::Failed(_))
+    }
+    /// Traceback and error if the test failed.
+    pub fn failure(&self) -> Option<&str> {
+        match &self.status {
+            TestStatus::Failed(failure) => Some(failure),
+            _ => None,
+        }
+    }
+}
+
+/// Results of the tests of a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileResult {
+    pub path: PathBuf,
+    pub tests: Vec<TestResult>,
+    /// Number of tests not run because their name does not match the filter.
+    pub filtered: usize,
+    /// Traceback and error if the file raised an error outside of tests.
+    pub error: Option<String>,
+    /// Output printed by the file, including the output of its tests.
+    pub output: String,
+    pub duration: Duration,
+}
+
+impl FileResult {
+    pub fn passed(&self) -> bool {
+        self.error.is_none() && self.tests.iter().all(|t| t.passed())
+    }
+}
+
+/// Output of a test file.
+#[derive(Clone, Default)]
+pub(crate) struct Capture(Rc<RefCell<Vec<u8>>>);
+
+impl Capture {
+    fn len(&self) -> usize {
+        self.0.borrow().len()
+    }
+    pub(crate) fn since(&self, start: usize) -> String {
+        String::from_utf8_lossy(&self.0.borrow()[start..]).to_string()
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A file evaluated by a runner, see [`run_captured`].
+pub(crate) struct CapturedRun {
+    /// The interpreter which evaluated the file, e.g. to take its session from.
+    pub(crate) interpreter: Interpreter,
+    /// Traceback and error if the file raised an error.
+    pub(crate) error: Option<String>,
+    pub(crate) output: String,
+    pub(crate) duration: Duration,
+}
+
+/// Evaluate the file `path` in a fresh interpreter capturing its output, after
+/// `setup` (e.g. to start the session recording tests) is called with them.
+pub(crate) fn run_captured<F>(path: &Path, setup: F) -> CapturedRun
+where
+    F: FnOnce(&mut Interpreter, &Capture),
+{
+    let mut interpreter = Interpreter::default();
+    let output = Capture::default();
+    interpreter.set_output(Some(Box::new(output.clone())));
+    setup(&mut interpreter, &output);
+    let start = Instant::now();
+    let res = interpreter.eval_file(path);
+    let duration = start.elapsed();
+    CapturedRun {
+        interpreter,
+        error: res.err().map(|exc| Interpreter::format_exc(&exc)),
+        output: output.since(0),
+        duration,
+    }
+}
+
+/// Write the failures of a run, given by header and output, then the summary
+/// line: the result of the run of `kind` files, `counts` and its duration.
+pub(crate) fn write_report<W: Write>(
+    mut out: W,
+    kind: &str,
+    failures: &[(String, String)],
+    ok: bool,
+    counts: &[String],
+    start: Instant,
+) -> io::Result<()> {
+    if !failures.is_empty() {
+        writeln!(out, "\nfailures:")?;
+    }
+    for (header, output) in failures {
+        writeln!(out, "\n---- {} ----", header)?;
+        write!(out, "{}", output)?;
+    }
+    writeln!(
+        out,
+        "\n{} result: {}. {}; finished in {:.2}s",
+        kind,
+        if ok { "ok" } else { "FAILED" },
+        counts.join("; "),
+        start.elapsed().as_secs_f64()
+    )
+}
+
+/// Tests run in a file, see [`run_test`].
+pub(crate) struct TestSession {
+    filter: Option<String>,
+    output: Capture,
+    results: Vec<TestResult>,
+    filtered: usize,
+}
+
+impl TestSession {
+    /// Start the test `name`, or count it as filtered out.
+    fn start(&mut self, name: &str) -> Option<(usize, Instant)> {
+        match &self.filter {
+            Some(filter) if !name.contains(filter.as_str()) => {
+                self.filtered += 1;
+                None
+            }
+            _ => Some((self.output.len(), Instant::now())),
+        }
+    }
+
+    fn finish(
+        &mut self,
+        suite: Option<&str>,
+        name: &str,
+        status: TestStatus,
+        (output_start, start): (usize, Instant),
+    ) {
+        self.results.push(TestResult {
+            suite: suite.map(|s| s.to_string()),
+            name: name.to_string(),
+            status,
+            output: self.output.since(output_start),
+            duration: start.elapsed(),
+        });
+    }
+}
+
+/// Bindings of an env and its parents, restored after each test so that tests
+/// do not see the definitions and assignments of the tests before them.
+struct Snapshot(Vec<(JEnvRef, HashMap<String, JValRef>)>);
+
+impl Snapshot {
+    fn take(env: &JEnvRef) -> Self {
+        let mut envs = vec![];
+        let mut env = Some(Rc::clone(env));
+        while let Some(e) = env {
+            envs.push((Rc::clone(&e), e.vars()));
+            env = e.parent.clone();
+        }
+        Self(envs)
+    }
+
+    fn restore(self) {
+        for (env, vars) in self.0 {
+            env.set_vars(vars);
+        }
+    }
+}
+
+/// Run the test `name` of `suite` written in `env`, evaluated by `f`: `None` if
+/// it is filtered out, otherwise the error if it failed (or passed when
+/// `expect_fail`).
+pub(crate) fn run_test<F>(
+    suite: Option<&str>,
+    name: &str,
+    expect_fail: bool,
+    env: &JEnvRef,
+    state: &mut JState,
+    f: F,
+) -> Option<Result<(), JError>>
+where
+    F: FnOnce(&mut JState) -> JResult,
+{
+    let start = match state.test_session_mut() {
+        Some(session) => Some(session.start(name)?),
+        None => None,
+    };
+    let snapshot = Snapshot::take(env);
+    state.traceback_take();
+    let res = f(state).map(|_| ());
+    snapshot.restore();
+    let failure = |err: &JError, state: &mut JState| {
+        let traceback = state.traceback_take();
+        Interpreter::format_exc(&(state.pos().clone(), err.clone(), traceback))
+    };
+    let (status, res) = match (res, expect_fail) {
+        (Ok(()), false) => (TestStatus::Passed, Ok(())),
+        (Ok(()), true) => {
+            let err = JError::new(AssertionError, "expected to fail, but passed");
+            (TestStatus::Failed(failure(&err, state)), Err(err))
+        }
+        (Err(err), false) => (TestStatus::Failed(failure(&err, state)), Err(err)),
+        (Err(err), true) => (TestStatus::ExpectedFailure(failure(&err, state)), Ok(())),
+    };
+    if let (Some(start), Some(session)) = (start, state.test_session_mut()) {
+        session.finish(suite, name, status, start);
+    }
+    Some(res)
+}
+
+/// Record the test `name` of `suite` as skipped: false if it is filtered out.
+pub(crate) fn skip_test(suite: Option<&str>, name: &str, reason: &str, state: &mut JState) -> bool {
+    if let Some(session) = state.test_session_mut() {
+        match session.start(name) {
+            Some(start) => {
+                session.finish(suite, name, TestStatus::Skipped(reason.to_string()), start)
+            }
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Test files in `paths`: the files themselves, and the `test-*.jibi` files in
+/// directories and their subdirectories, sorted by path. Hidden directories are
+/// skipped.
+pub fn discover(paths: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    discover_prefixed(paths, "test-")
+}
+
+/// Files in `paths`, and the `{prefix}*.jibi` files in directories, see
+/// [`discover`].
+pub(crate) fn discover_prefixed(paths: &[PathBuf], prefix: &str) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for path in paths {
+        if path.is_dir() {
+            discover_dir(path, prefix, &mut files)?;
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
+fn discover_dir(dir: &Path, prefix: &str, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<_>>()?;
+    entries.sort();
+    for path in entries {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_dir() {
+            if !name.starts_with('.') {
+                discover_dir(&path, prefix, files)?;
+            }
+        } else if name.starts_with(prefix) && name.ends_with(".jibi") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+pub struct TestRunner {
+    filter: Option<String>,
+}
+
+impl TestRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only run the tests whose name contains `filter`.
+    pub fn filter(mut self, filter: &str) -> Self {
+        self.filter = Some(filter.to_string());
+        self
+    }
+
+    /// Run a test file in a fresh interpreter.
+    pub fn run_file(&self, path: &Path) -> FileResult {
+        let mut run = run_captured(path, |interpreter, output| {
+            interpreter.state.set_test_session(Some(TestSession {
+                filter: self.filter.clone(),
+                output: output.clone(),
+                results: vec![],
+                filtered: 0,
+            }))
+        });
+        let session = run.interpreter.state.take_test_session().unwrap();
+        FileResult {
+            path: path.to_path_buf(),
+            tests: session.results,
+            filtered: session.filtered,
+            error: run.error,
+            output: run.output,
+            duration: run.duration,
+        }
+    }
+
+    /// Run test files, writing the result of each test to `out` as files
+    /// complete, then the output and tracebacks of failures and a summary.
+    pub fn run<W: Write>(&self, files: &[PathBuf], mut out: W) -> io::Result<Vec<FileResult>> {
+        let start = Instant::now();
+        let mut results = vec![];
+        for file in files {
+            let result = self.run_file(file);
+            for test in &result.tests {
+                let status = match &test.status {
+                    TestStatus::Passed => "ok".to_string(),
+                    TestStatus::Failed(_) => "FAILED".to_string(),
+                    TestStatus::Skipped(reason) if reason.is_empty() => "skipped".to_string(),
+                    TestStatus::Skipped(reason) => format!("skipped ({})", reason),
+                    TestStatus::ExpectedFailure(_) => "ok (expected failure)".to_string(),
+                };
+                writeln!(out, "test {}: {} ... {}", file.display(), test.name, status)?;
+            }
+            if result.error.is_some() {
+                writeln!(out, "test {} ... FAILED", file.display())?;
+            } else if result.tests.is_empty() && result.filtered == 0 {
+                writeln!(out, "test {} ... ok", file.display())?;
+            }
+            results.push(result);
+        }
+
+        let mut failures = vec![];
+        for result in results.iter().filter(|r| !r.passed()) {
+            for test in result.tests.iter().filter(|t| !t.passed()) {
+                failures.push((
+                    format!("{}: {}", result.path.display(), test.name),
+                    format!("{}{}", test.output, test.failure().unwrap_or_default()),
+                ));
+            }
+            if let Some(error) = &result.error {
+                failures.push((result.path.display().to_string(), error.clone()));
+            }
+        }
+
+        // Files without tests count as one test
+        let (mut passed, mut failed, mut skipped) = (0, 0, 0);
+        for result in &results {
+            for test in &result.tests {
+                match test.status {
+                    TestStatus::Passed | TestStatus::ExpectedFailure(_) => passed += 1,
+                    TestStatus::Failed(_) => failed += 1,
+                    TestStatus::Skipped(_) => skipped += 1,
+                }
+            }
+            if result.error.is_some() {
+                failed += 1;
+            } else if result.tests.is_empty() && result.filtered == 0 {
+                passed += 1;
+            }
+        }
+        write_report(
+            out,
+            "test",
+            &failures,
+            failures.is_empty(),
+            &[
+                format!("{} passed", passed),
+                format!("{} failed", failed),
+                format!("{} skipped", skipped),
+                format!(
+                    "{} filtered out",
+                    results.iter().map(|r| r.filtered).sum::<usize>()
+                ),
+            ],
+            start,
+        )?;
+        Ok(results)
+    }
+}
@@ -0,0 +1,264 @@
+//! Machine-readable test reports.
+use std::fmt::Write;
+use std::str::FromStr;
+use std::time::Duration;
+
+use super::{FileResult, TestStatus};
+
+/// Format of the report of `jibi test`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// Results as they complete, then failures and a summary.
+    Text,
+    /// Test Anything Protocol, version 13.
+    Tap,
+    /// JUnit XML, read by most CI services.
+    Junit,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "tap" => Ok(Self::Tap),
+            "junit" => Ok(Self::Junit),
+            _ => Err(format!(
+                "unknown report format {}, expected text, tap or junit",
+                s
+            )),
+        }
+    }
+}
+
+/// A reported test: a test of a file, or the file itself if it raised an error
+/// outside of tests, or if it has no tests.
+struct Case<'a> {
+    suite: Option<&'a str>,
+    name: String,
+    failure: Option<&'a str>,
+    skipped: Option<&'a str>,
+    expected_failure: bool,
+    output: &'a str,
+    duration: Duration,
+    // The file itself
+    file: bool,
+    // Failure outside of tests
+    error: bool,
+}
+
+impl Case<'_> {
+    /// The error of a failure, without the traceback.
+    fn message(&self) -> Option<&str> {
+        self.failure
+            .map(|f| f.lines().rev().find(|l| !l.is_empty()).unwrap_or_default())
+    }
+}
+
+fn cases(result: &FileResult) -> Vec<Case<'_>> {
+    let mut cases: Vec<Case> = result
+        .tests
+        .iter()
+        .map(|test| Case {
+            suite: test.suite.as_deref(),
+            name: test.name.clone(),
+            failure: test.failure(),
+            skipped: match &test.status {
+                TestStatus::Skipped(reason) => Some(reason),
+                _ => None,
+            },
+            expected_failure: matches!(test.status, TestStatus::ExpectedFailure(_)),
+            output: &test.output,
+            duration: test.duration,
+            file: false,
+            error: false,
+        })
+        .collect();
+    if result.error.is_some() || (result.tests.is_empty() && result.filtered == 0) {
+        cases.push(Case {
+            suite: None,
+            name: result.path.display().to_string(),
+            failure: result.error.as_deref(),
+            skipped: None,
+            expected_failure: false,
+            output: &result.output,
+            duration: result.duration,
+            file: true,
+            error: result.error.is_some(),
+        });
+    }
+    cases
+}
+
+/// Report in the Test Anything Protocol, with durations, messages and
+/// tracebacks in YAML blocks. Skipped tests are reported with a `SKIP`
+/// directive, and expected failures as `not ok` with a `TODO` directive.
+pub fn tap(results: &[FileResult]) -> String {
+    let cases: Vec<(&FileResult, Case)> = results
+        .iter()
+        .flat_map(|r| cases(r).into_iter().map(move |c| (r, c)))
+        .collect();
+    let mut tap = format!("TAP version 13\n1..{}\n", cases.len());
+    for (n, (result, case)) in cases.iter().enumerate() {
+        let path = result.path.display().to_string();
+        let mut description = match (case.file, case.suite) {
+            (true, _) => path,
+            (false, Some(suite)) => format!("{}: {}: {}", path, suite, case.name),
+            (false, None) => format!("{}: {}", path, case.name),
+        };
+        description = description.replace('\\', "\\\\").replace('#', "\\#");
+        let (status, directive) = match (case.failure, case.skipped) {
+            (Some(_), _) => ("not ok", String::new()),
+            (None, Some("")) => ("ok", " # SKIP".to_string()),
+            (None, Some(reason)) => ("ok", format!(" # SKIP {}", reason)),
+            (None, None) if case.expected_failure => {
+                ("not ok", " # TODO expected failure".to_string())
+            }
+            (None, None) => ("ok", String::new()),
+        };
+        let _ = writeln!(tap, "{} {} - {}{}", status, n + 1, description, directive);
+        let _ = writeln!(tap, "  ---");
+        let _ = writeln!(
+            tap,
+            "  duration_ms: {:.3}",
+            case.duration.as_secs_f64() * 1000.0
+        );
+        if let (Some(message), Some(failure)) = (case.message(), case.failure) {
+            let _ = writeln!(tap, "  message: '{}'", message.replace('\'', "''"));
+            let _ = writeln!(tap, "  traceback: |");
+            for line in failure.lines() {
+                let _ = writeln!(tap, "    {}", line);
+            }
+        }
+        let _ = writeln!(tap, "  ...");
+    }
+    tap
+}
+
+fn escape_xml(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Not allowed in XML 1.0
+            c if c.is_control() && !matches!(c, '\n' | '\r' | '\t') => (),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Report in the JUnit XML format, with a `testsuite` for each `test-suite` of
+/// each file. Tests outside of suites, and errors outside of tests, are in a
+/// `testsuite` named after the file. Expected failures are reported as passed.
+pub fn junit(results: &[FileResult]) -> String {
+    let mut suites: Vec<(String, &FileResult, Vec<Case>)> = vec![];
+    for result in results {
+        let path = result.path.display().to_string();
+        for case in cases(result) {
+            let name = case.suite.map(|s| s.to_string()).unwrap_or(path.clone());
+            match suites.last_mut() {
+                Some((suite, r, cases)) if *suite == name && std::ptr::eq(*r, result) => {
+                    cases.push(case)
+                }
+                _ => suites.push((name, result, vec![case])),
+            }
+        }
+    }
+
+    let count = |cases: &[Case], error: bool| {
+        cases
+            .iter()
+            .filter(|c| c.failure.is_some() && c.error == error)
+            .count()
+    };
+    let skipped = |cases: &[Case]| cases.iter().filter(|c| c.skipped.is_some()).count();
+    let time = |cases: &[Case]| {
+        cases
+            .iter()
+            .map(|c| c.duration)
+            .sum::<Duration>()
+            .as_secs_f64()
+    };
+    let all: Vec<&Case> = suites.iter().flat_map(|(_, _, cases)| cases).collect();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<testsuites tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\" time=\"{:.6}\">",
+        all.len(),
+        all.iter()
+            .filter(|c| c.failure.is_some() && !c.error)
+            .count(),
+        all.iter().filter(|c| c.error).count(),
+        suites
+            .iter()
+            .map(|(_, _, cases)| skipped(cases))
+            .sum::<usize>(),
+        results
+            .iter()
+            .map(|r| r.duration)
+            .sum::<Duration>()
+            .as_secs_f64()
+    );
+    for (name, result, cases) in &suites {
+        let _ = writeln!(
+            xml,
+            "  <testsuite name=\"{}\" file=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\" time=\"{:.6}\">",
+            escape_xml(name),
+            escape_xml(&result.path.display().to_string()),
+            cases.len(),
+            count(cases, false),
+            count(cases, true),
+            skipped(cases),
+            time(cases)
+        );
+        for case in cases {
+            let _ = write!(
+                xml,
+                "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.6}\"",
+                escape_xml(&case.name),
+                escape_xml(name),
+                case.duration.as_secs_f64()
+            );
+            if let Some(reason) = case.skipped {
+                let _ = writeln!(xml, ">");
+                let _ = writeln!(xml, "      <skipped message=\"{}\"/>", escape_xml(reason));
+                let _ = writeln!(xml, "    </testcase>");
+                continue;
+            }
+            let (failure, message) = match (case.failure, case.message()) {
+                (Some(failure), Some(message)) => (failure, message),
+                _ => {
+                    let _ = writeln!(xml, "/>");
+                    continue;
+                }
+            };
+            let _ = writeln!(xml, ">");
+            let tag = if case.error { "error" } else { "failure" };
+            let _ = writeln!(
+                xml,
+                "      <{} message=\"{}\">{}</{}>",
+                tag,
+                escape_xml(message),
+                escape_xml(failure.trim_end()),
+                tag
+            );
+            if !case.output.is_empty() {
+                let _ = writeln!(
+                    xml,
+                    "      <system-out>{}</system-out>",
+                    escape_xml(case.output)
+                );
+            }
+            let _ = writeln!(xml, "    </testcase>");
+        }
+        let _ = writeln!(xml, "  </testsuite>");
+    }
+    let _ = writeln!(xml, "</testsuites>");
+    xml
+}
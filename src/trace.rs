@@ -0,0 +1,81 @@
+//! Call tracing, see the `trace` and `untrace` builtins.
+use std::collections::HashSet;
+
+use crate::types::identity::IdentityMap;
+use crate::*;
+
+#[derive(Default)]
+pub(crate) struct Tracer {
+    // Names of traced lambdas and builtins
+    traced: IdentityMap<String>,
+    // Files whose named lambdas are all traced
+    files: HashSet<String>,
+    depth: usize,
+}
+
+impl Tracer {
+    pub fn trace(&mut self, val: &JValRef) -> Result<(), JError> {
+        let name = match &**val {
+            JVal::Lambda(l) => l.name.clone(),
+            JVal::Builtin(b) => Some(b.name.clone()),
+            _ => None,
+        };
+        match name {
+            Some(name) => {
+                self.traced.remove_dropped();
+                self.traced.insert(val, name);
+                Ok(())
+            }
+            None => Err(JError::new(TypeError, "expected a named lambda or builtin")),
+        }
+    }
+    pub fn untrace(&mut self, val: &JValRef) {
+        self.traced.remove(val);
+    }
+    pub fn untrace_all(&mut self) {
+        self.traced.clear();
+        self.files.clear();
+    }
+    pub fn trace_file(&mut self, filename: &str) {
+        self.files.insert(filename.to_string());
+    }
+
+    fn name(&self, val: &JVal) -> Option<String> {
+        if self.traced.is_empty() && self.files.is_empty() {
+            return None;
+        }
+        if let Some(name) = self.traced.get(val) {
+            return Some(name.clone());
+        }
+        match val {
+            JVal::Lambda(l) => match (&l.name, &l.defpos) {
+                (Some(name), Some(pos)) if self.files.contains(&pos.filename) => Some(name.clone()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Call `f` with evaluated `args`, printing the call and its result if `val` is traced.
+pub(crate) fn call<F>(val: &JVal, args: JValRef, state: &mut JState, f: F) -> JResult
+where
+    F: FnOnce(JValRef, &mut JState) -> JResult,
+{
+    let name = match state.tracer().name(val) {
+        Some(name) => name,
+        None => return f(args, state),
+    };
+    let indent = "  ".repeat(state.tracer().depth);
+    let mut call = vec![name];
+    call.extend(args.iter_list()?.map(|arg| repr(&arg)));
+    state.println(&format!("{}({})", indent, call.join(" ")));
+    state.tracer_mut().depth += 1;
+    let res = f(args, state);
+    state.tracer_mut().depth -= 1;
+    match &res {
+        Ok(val) => state.println(&format!("{}=> {}", indent, repr(val))),
+        Err(err) => state.println(&format!("{}!! {}", indent, err)),
+    }
+    res
+}
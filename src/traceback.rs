@@ -11,7 +11,7 @@ pub enum JCallable {
 
 impl JCallable {
     pub fn from_jval(val: &JVal) -> Option<Self> {
-        match &*val {
+        match val {
             JVal::Lambda(l) => Some(Self::Lambda("lambda".to_string(), l.as_ref().clone())),
             JVal::Macro(l) => Some(Self::Lambda("macro".to_string(), l.as_ref().clone())),
             JVal::Builtin(b) => Some(Self::Builtin("builtin".to_string(), b.clone())),
@@ -58,6 +58,14 @@ impl TracebackFrame {
             None => None,
         }
     }
+    /// Definition position of the lambda, `None` for builtins.
+    pub fn pos(&self) -> Option<&PositionTag> {
+        self.pos.as_ref()
+    }
+    /// Closure of the lambda, or environment the builtin was called in.
+    pub fn env(&self) -> JEnvRef {
+        Rc::clone(&self.env)
+    }
 }
 
 impl fmt::Display for TracebackFrame {
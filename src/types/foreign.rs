@@ -0,0 +1,148 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::*;
+
+pub type JForeignReprFn = Rc<dyn Fn(&JForeign) -> String>;
+pub type JForeignMethodFn = Rc<dyn Fn(&JForeign, JValRef, &mut JState) -> JResult>;
+
+/// Description of a host (Rust) type exposed to `jibi` as `foreign` values:
+/// its name, how it is printed, and the methods callable with `foreign-call`.
+///
+/// A type is usually created once by the embedder and shared by all values
+/// of that type.
+pub struct JForeignType {
+    pub name: String,
+    repr: Option<JForeignReprFn>,
+    methods: HashMap<String, JForeignMethodFn>,
+}
+
+impl JForeignType {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            repr: None,
+            methods: HashMap::new(),
+        }
+    }
+    /// Set a custom `repr` for values of this type.
+    pub fn with_repr<T, F>(mut self, f: F) -> Self
+    where
+        T: 'static,
+        F: 'static + Fn(&T) -> String,
+    {
+        self.repr = Some(Rc::new(move |obj: &JForeign| {
+            match obj.downcast_ref::<T>() {
+                Some(v) => f(v),
+                None => obj.default_repr(),
+            }
+        }));
+        self
+    }
+    /// Add a method callable from `jibi` with `(foreign-call obj 'name args ...)`.
+    ///
+    /// The method receives the wrapped object and the list of (evaluated) arguments
+    /// following the method name.
+    pub fn with_method<T, F>(mut self, name: &str, f: F) -> Self
+    where
+        T: 'static,
+        F: 'static + Fn(&T, JValRef, &mut JState) -> JResult,
+    {
+        self.methods.insert(
+            name.to_string(),
+            Rc::new(move |obj: &JForeign, args, state: &mut JState| {
+                f(obj.try_downcast_ref::<T>()?, args, state)
+            }),
+        );
+        self
+    }
+    pub fn into_ref(self) -> Rc<Self> {
+        Rc::new(self)
+    }
+    pub fn method(&self, name: &str) -> Option<JForeignMethodFn> {
+        self.methods.get(name).map(Rc::clone)
+    }
+    pub fn method_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.methods.keys().map(|s| s.as_str()).collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+/// An opaque host (Rust) object.
+#[derive(Clone)]
+pub struct JForeign {
+    pub ftype: Rc<JForeignType>,
+    obj: Rc<dyn Any>,
+}
+
+impl JForeign {
+    pub fn new<T: 'static>(ftype: Rc<JForeignType>, obj: T) -> Self {
+        Self {
+            ftype,
+            obj: Rc::new(obj),
+        }
+    }
+    pub(crate) fn from_rc<T: 'static>(ftype: &Rc<JForeignType>, obj: &Rc<T>) -> Self {
+        Self {
+            ftype: Rc::clone(ftype),
+            obj: Rc::clone(obj) as Rc<dyn Any>,
+        }
+    }
+    pub fn type_name(&self) -> &str {
+        &self.ftype.name
+    }
+    pub fn is<T: 'static>(&self) -> bool {
+        self.obj.is::<T>()
+    }
+    pub fn downcast_ref<T: 'static>(&self) -> Option<&T> {
+        self.obj.downcast_ref::<T>()
+    }
+    pub fn try_downcast_ref<T: 'static>(&self) -> Result<&T, JError> {
+        self.downcast_ref::<T>().ok_or_else(|| {
+            JError::new(
+                TypeError,
+                &format!("foreign {} has an unexpected host type", self.type_name()),
+            )
+        })
+    }
+    /// Get a shared reference to the wrapped object, which may outlive the `jibi` value.
+    pub fn downcast_rc<T: 'static>(&self) -> Option<Rc<T>> {
+        Rc::clone(&self.obj).downcast::<T>().ok()
+    }
+    pub fn call_method(&self, name: &str, args: JValRef, state: &mut JState) -> JResult {
+        match self.ftype.method(name) {
+            Some(f) => f(self, args, state),
+            None => Err(JError::new(
+                NotDefined,
+                &format!("foreign {} has no method {}", self.type_name(), name),
+            )),
+        }
+    }
+    fn default_repr(&self) -> String {
+        format!("#[foreign {}]", self.type_name())
+    }
+    pub fn repr(&self) -> String {
+        match &self.ftype.repr {
+            Some(f) => f(self),
+            None => self.default_repr(),
+        }
+    }
+}
+
+impl PartialEq for JForeign {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.obj, &other.obj)
+    }
+}
+
+impl fmt::Debug for JForeign {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        f.debug_struct("JForeign")
+            .field("type", &self.ftype.name)
+            .field("obj", &format_args!("<Any {:p}>", Rc::as_ptr(&self.obj)))
+            .finish()
+    }
+}
@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+use crate::*;
+
+/// Map keyed by the identity of values, e.g. to attach source positions to forms.
+///
+/// Keys are addresses: each entry holds a weak reference to its value, which keeps
+/// the allocation (but not the value) alive so that the address is not reused by
+/// another value while the entry exists.
+pub(crate) struct IdentityMap<V> {
+    entries: HashMap<usize, (Weak<JVal>, V)>,
+    // Number of entries after the last call to `remove_dropped`
+    live: usize,
+}
+
+impl<V> Default for IdentityMap<V> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            live: 0,
+        }
+    }
+}
+
+fn address(val: &JVal) -> usize {
+    val as *const JVal as usize
+}
+
+impl<V> IdentityMap<V> {
+    pub fn insert(&mut self, val: &JValRef, v: V) {
+        self.entries.insert(address(val), (Rc::downgrade(val), v));
+    }
+    pub fn get(&self, val: &JVal) -> Option<&V> {
+        self.entries.get(&address(val)).map(|(_, v)| v)
+    }
+    pub fn get_mut(&mut self, val: &JVal) -> Option<&mut V> {
+        self.entries.get_mut(&address(val)).map(|(_, v)| v)
+    }
+    pub fn remove(&mut self, val: &JVal) -> Option<V> {
+        self.entries.remove(&address(val)).map(|(_, v)| v)
+    }
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.live = 0;
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.entries.values().map(|(_, v)| v)
+    }
+    /// Remove the entries of values which were dropped. Only scans the entries
+    /// once their number doubled since the last scan, so that calling it after
+    /// each insertion takes amortized constant time.
+    pub fn remove_dropped(&mut self) {
+        if self.entries.len() < 2 * self.live.max(32) {
+            return;
+        }
+        self.entries.retain(|_, (val, _)| val.strong_count() > 0);
+        self.live = self.entries.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identity_map() {
+        let mut map = IdentityMap::default();
+        let kept: Vec<JValRef> = (0..40).map(|n| JVal::Int(n).into_ref()).collect();
+        for (n, val) in kept.iter().enumerate() {
+            map.insert(val, n);
+        }
+        // Equal values are distinct keys
+        let other = JVal::Int(3).into_ref();
+        assert_eq!(Some(&3), map.get(&kept[3]));
+        assert_eq!(None, map.get(&other));
+
+        for n in 0..40usize {
+            map.insert(&JVal::Int(n as JTInt).into_ref(), n);
+        }
+        assert_eq!(80, map.entries.len());
+        map.remove_dropped();
+        assert_eq!(40, map.entries.len());
+        assert_eq!(Some(39), map.remove(&kept[39]));
+        assert_eq!(741, map.values().sum::<usize>());
+    }
+}
@@ -25,4 +25,7 @@ where
         let cons = &self.constructor;
         Rc::clone(self.vals.entry(val.clone()).or_insert_with(|| (cons)(val)))
     }
+    pub fn keys(&self) -> Vec<T> {
+        self.vals.keys().cloned().collect()
+    }
 }
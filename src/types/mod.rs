@@ -1,4 +1,4 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::fmt;
 use std::rc::Rc;
 use std::sync::atomic::{AtomicUsize, Ordering};
@@ -6,9 +6,41 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 use crate::env::JEnvRef;
 use crate::state::JState;
 use crate::*;
+use foreign::JForeign;
 
+pub mod foreign;
+pub(crate) mod identity;
 pub mod intern;
 
+thread_local! {
+    static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Number of live profilers: values are only counted while there is one.
+static COUNTING: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of values allocated by this thread while allocations were counted,
+/// for the profiler.
+pub(crate) fn allocations() -> u64 {
+    ALLOCATIONS.with(|a| a.get())
+}
+
+/// Counts allocations while it is alive, held by profilers.
+pub(crate) struct CountAllocations(());
+
+impl Default for CountAllocations {
+    fn default() -> Self {
+        COUNTING.fetch_add(1, Ordering::Relaxed);
+        Self(())
+    }
+}
+
+impl Drop for CountAllocations {
+    fn drop(&mut self) {
+        COUNTING.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
 pub type JTInt = i128;
 pub type JTFloat = f64;
 
@@ -39,7 +71,7 @@ impl JPair {
             _ => false,
         }
     }
-    pub fn iter(&self) -> Result<JListIterator, JError> {
+    pub fn iter(&self) -> Result<JListIterator<'_>, JError> {
         if !self.is_list() {
             return Err(JError::new(TypeError, "can only iter lists"));
         }
@@ -84,6 +116,9 @@ impl JBuiltin {
             f,
         }
     }
+    pub(crate) fn id(&self) -> usize {
+        self.id
+    }
 }
 
 impl fmt::Display for JBuiltin {
@@ -151,12 +186,19 @@ impl JParams {
             Self::Fixed(params) => format!("{}", params.len()),
         }
     }
-    fn nargs_long(&self) -> String {
+    pub(crate) fn nargs_long(&self) -> String {
         match self {
             Self::Variadic(params, _) => format!("at least {}", params.len()),
             Self::Fixed(params) => format!("{}", params.len()),
         }
     }
+    /// Whether a call with `nargs` arguments binds.
+    pub(crate) fn accepts(&self, nargs: usize) -> bool {
+        match self {
+            Self::Fixed(params) => nargs == params.len(),
+            Self::Variadic(params, _) => nargs >= params.len(),
+        }
+    }
     pub fn bind(&self, args: JValRef, env: JEnvRef) -> Result<(), JError> {
         let params = match self {
             Self::Fixed(params) => params,
@@ -244,6 +286,7 @@ pub enum JVal {
     Env(JEnvRef),
     Token(Token),
     TokenMatcher(TokenMatcher),
+    Foreign(JForeign),
 }
 
 pub type JValRef = Rc<JVal>;
@@ -256,6 +299,9 @@ impl fmt::Display for JVal {
 
 impl JVal {
     pub fn into_ref(self) -> JValRef {
+        if COUNTING.load(Ordering::Relaxed) > 0 {
+            ALLOCATIONS.with(|a| a.set(a.get() + 1));
+        }
         Rc::new(self)
     }
 
@@ -331,6 +377,16 @@ impl JVal {
             _ => Err(JError::new(TypeError, "expected a tokenmatcher")),
         }
     }
+    pub fn to_foreign(&self) -> Result<&JForeign, JError> {
+        match self {
+            Self::Foreign(f) => Ok(f),
+            _ => Err(JError::new(TypeError, "expected a foreign")),
+        }
+    }
+    /// Get a reference to the host object wrapped in a `foreign` value.
+    pub fn downcast_foreign<T: 'static>(&self) -> Result<&T, JError> {
+        self.to_foreign()?.try_downcast_ref::<T>()
+    }
 
     pub fn is_list(&self) -> bool {
         match self {
@@ -339,7 +395,7 @@ impl JVal {
             _ => false,
         }
     }
-    pub fn iter_list(&self) -> Result<JListIterator, JError> {
+    pub fn iter_list(&self) -> Result<JListIterator<'_>, JError> {
         match self {
             JVal::Nil => Ok(JListIterator { head: None }),
             JVal::Pair(p) => p.iter(),
@@ -0,0 +1,152 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use jibi::bench::{discover, Baseline, BenchRunner, Stats};
+
+mod common;
+
+const BENCHES: &str = r#"(import-from "stl/bench" bench set-iterations)
+(def xs (range 1 10))
+(set-iterations 3)
+(bench "map" (map (fn (x) (* x x)) xs))
+(bench "fold" (foldl + 0 xs))
+"#;
+
+fn bench_dir(name: &str) -> PathBuf {
+    common::temp_dir(
+        name,
+        &[
+            ("bench-lists.jibi", BENCHES),
+            ("sub/bench-error.jibi", "(nope)"),
+            ("sub/test-lists.jibi", "(def x 1)"),
+        ],
+    )
+}
+
+#[test]
+fn test_stats() {
+    let ms = Duration::from_millis;
+    let stats = Stats::new(&[ms(4), ms(1), ms(2), ms(5)]);
+    assert_eq!(4, stats.iterations);
+    assert_eq!(ms(3), stats.mean);
+    assert_eq!(ms(3), stats.median);
+    assert_eq!(
+        Duration::from_secs_f64(2.5f64.sqrt() / 1000.0),
+        stats.stddev
+    );
+    assert_eq!(
+        "mean 3.000 ms, median 3.000 ms, stddev 1.581 ms (4 iterations)",
+        stats.to_string()
+    );
+    assert_eq!(ms(2), Stats::new(&[ms(2), ms(1), ms(3)]).median);
+}
+
+#[test]
+fn test_bench_runner() {
+    let dir = bench_dir("jibi-bench-runner");
+    let files = discover(std::slice::from_ref(&dir)).unwrap();
+    assert_eq!(
+        vec![
+            dir.join("bench-lists.jibi"),
+            dir.join("sub/bench-error.jibi")
+        ],
+        files
+    );
+
+    let results = BenchRunner::new().run(&files, std::io::sink()).unwrap();
+    assert_eq!(
+        vec!["map", "fold"],
+        results[0]
+            .benches
+            .iter()
+            .map(|b| &b.name)
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(3, results[0].benches[0].stats.iterations);
+    assert!(results[0].output.starts_with("Bench map: mean "));
+    assert_eq!(None, results[0].error);
+    assert!(results[1]
+        .error
+        .as_ref()
+        .unwrap()
+        .ends_with("NotDefined: nope\n"));
+
+    // The runner sets the iterations, and filters benchmarks
+    let result = BenchRunner::new()
+        .iterations(5)
+        .filter("fold")
+        .run_file(&files[0]);
+    assert_eq!(1, result.filtered);
+    assert_eq!(5, result.benches[0].stats.iterations);
+}
+
+#[test]
+fn test_baseline() {
+    let dir = bench_dir("jibi-bench-baseline");
+    let path = dir.join("bench-lists.jibi");
+    let results = BenchRunner::new()
+        .run(std::slice::from_ref(&path), std::io::sink())
+        .unwrap();
+    let baseline = Baseline::new(&results);
+    assert_eq!(Ok(baseline.clone()), Baseline::parse(&baseline.to_string()));
+    assert_eq!(
+        Some(results[0].benches[1].stats.mean),
+        baseline.get(&path, "fold")
+    );
+    assert!(Baseline::parse("12 a\nb").is_err());
+
+    // Slower than a baseline of 1ns, faster than a baseline of 1s
+    let fast = Baseline::parse(&format!("1 {}: map\n", path.display())).unwrap();
+    let slow = Baseline::parse(&format!("1000000000 {}: map\n", path.display())).unwrap();
+    let mut out = vec![];
+    let runner = BenchRunner::new().baseline(fast);
+    let results = runner.run(std::slice::from_ref(&path), &mut out).unwrap();
+    assert!(runner.regressed(&path, &results[0].benches[0]));
+    assert!(!runner.regressed(&path, &results[0].benches[1]));
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.lines().next().unwrap().ends_with("% REGRESSED"));
+    assert!(out.contains("bench result: FAILED. 2 benchmarks; 1 regressed; 0 failed;"));
+
+    let runner = BenchRunner::new().baseline(slow);
+    let results = runner
+        .run(std::slice::from_ref(&path), std::io::sink())
+        .unwrap();
+    assert!(runner.change(&path, &results[0].benches[0]).unwrap() < -99.0);
+    assert!(!runner.regressed(&path, &results[0].benches[0]));
+}
+
+#[test]
+fn test_baseline_paths() {
+    let dir = bench_dir("jibi-bench-paths");
+    let path = dir.join("bench-lists.jibi");
+    let results = BenchRunner::new()
+        .run(std::slice::from_ref(&path), std::io::sink())
+        .unwrap();
+
+    // Saved relative to the baseline, and matched whatever the path given
+    let saved = dir.join("baseline.txt");
+    Baseline::new(&results).save(&saved).unwrap();
+    let text = std::fs::read_to_string(&saved).unwrap();
+    assert!(text.lines().all(|l| l.contains(" bench-lists.jibi: ")));
+    let baseline = Baseline::load(&saved).unwrap();
+    let mean = Some(results[0].benches[0].stats.mean);
+    assert_eq!(mean, baseline.get(&path, "map"));
+    assert_eq!(
+        mean,
+        baseline.get(&dir.join("sub/../bench-lists.jibi"), "map")
+    );
+    assert_eq!(mean, baseline.get(&dir.join("./bench-lists.jibi"), "map"));
+
+    // Benchmarks missing from the baseline are reported
+    let baseline = Baseline::parse(&format!("1000000000 {}: map\n", path.display())).unwrap();
+    let runner = BenchRunner::new().baseline(baseline);
+    let mut out = vec![];
+    let results = runner.run(std::slice::from_ref(&path), &mut out).unwrap();
+    assert!(!runner.missing(&path, &results[0].benches[0]));
+    assert!(runner.missing(&path, &results[0].benches[1]));
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.lines().nth(1).unwrap().ends_with(", not in baseline"));
+    assert!(out.contains(
+        "bench result: ok. 2 benchmarks; 0 regressed; 0 failed; 0 filtered out; 1 not in baseline;"
+    ));
+}
@@ -0,0 +1,121 @@
+/* Exercise the jibi C API; run with `make test-capi`. */
+#include <assert.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "jibi.h"
+
+static jibi_value *c_add(jibi_state *state, const jibi_value *args, void *userdata) {
+    int64_t *calls = userdata;
+    int64_t sum = 0;
+    jibi_value *head = jibi_value_clone(args);
+    while (jibi_type(head) == JIBI_PAIR) {
+        jibi_value *car = jibi_car(head);
+        jibi_value *cdr = jibi_cdr(head);
+        int64_t n;
+        if (jibi_to_int(car, &n) != 0) {
+            jibi_value_free(car);
+            jibi_value_free(cdr);
+            jibi_value_free(head);
+            jibi_raise("TypeError", "c-add expected integers");
+            return NULL;
+        }
+        sum += n;
+        jibi_value_free(car);
+        jibi_value_free(head);
+        head = cdr;
+    }
+    jibi_value_free(head);
+    (*calls)++;
+    return jibi_int(state, sum);
+}
+
+/* Raises, but returns a value anyway */
+static jibi_value *c_ignore(jibi_state *state, const jibi_value *args, void *userdata) {
+    jibi_raise("ValueError", "ignored");
+    return jibi_int(state, 0);
+}
+
+/* Returns NULL without raising */
+static jibi_value *c_fail(jibi_state *state, const jibi_value *args, void *userdata) {
+    return NULL;
+}
+
+int main(void) {
+    jibi_interpreter *interp = jibi_new();
+    jibi_state *state = jibi_get_state(interp);
+    int64_t n;
+    double x;
+    char *s;
+
+    /* Evaluation */
+    jibi_value *v = jibi_eval_str(interp, "test", "(defn add (x y) (+ x y)) (add 40 2)");
+    assert(v != NULL);
+    assert(jibi_type(v) == JIBI_INT);
+    assert(jibi_to_int(v, &n) == 0 && n == 42);
+    jibi_value_free(v);
+
+    /* Calling procedures with constructed values */
+    jibi_value *args[2] = {jibi_float(state, 1.5), jibi_int(state, 2)};
+    v = jibi_call(interp, "add", (const jibi_value *const *)args, 2);
+    assert(v != NULL);
+    assert(jibi_to_float(v, &x) == 0 && x == 3.5);
+    jibi_value_free(v);
+    jibi_value_free(args[0]);
+    jibi_value_free(args[1]);
+
+    /* Globals and lists */
+    jibi_value *items[2] = {jibi_string(state, "foo"), jibi_symbol(state, "bar")};
+    jibi_value *lst = jibi_list(state, (const jibi_value *const *)items, 2);
+    jibi_def(interp, "lst", lst);
+    s = jibi_repr(lst);
+    assert(strcmp(s, "(\"foo\" bar)") == 0);
+    jibi_string_free(s);
+    v = jibi_eval_str(interp, "test", "(car lst)");
+    s = jibi_to_string(v);
+    assert(strcmp(s, "foo") == 0);
+    jibi_string_free(s);
+    jibi_value_free(v);
+    jibi_value_free(lst);
+    jibi_value_free(items[0]);
+    jibi_value_free(items[1]);
+
+    /* C callbacks */
+    int64_t calls = 0;
+    jibi_register(interp, "c-add", c_add, &calls);
+    v = jibi_eval_str(interp, "test", "(c-add 1 2 3)");
+    assert(jibi_to_int(v, &n) == 0 && n == 6);
+    assert(calls == 1);
+    jibi_value_free(v);
+
+    /* Errors */
+    v = jibi_eval_str(interp, "test", "(c-add 1 \"2\")");
+    assert(v == NULL);
+    assert(strcmp(jibi_error(interp), "TypeError: c-add expected integers") == 0);
+    assert(strstr(jibi_traceback(interp), "builtin c-add") != NULL);
+
+    /* Errors raised by callbacks which return a value are dropped */
+    jibi_register(interp, "c-ignore", c_ignore, NULL);
+    jibi_register(interp, "c-fail", c_fail, NULL);
+    v = jibi_eval_str(interp, "test", "(c-ignore)");
+    assert(jibi_to_int(v, &n) == 0 && n == 0);
+    jibi_value_free(v);
+    v = jibi_eval_str(interp, "test", "(c-fail)");
+    assert(v == NULL);
+    assert(strcmp(jibi_error(interp), "Exception: builtin c-fail failed") == 0);
+
+    v = jibi_eval_str(interp, "test", "(raise (exception \"oops\"))");
+    assert(v == NULL);
+    assert(strcmp(jibi_error(interp), "Exception: oops") == 0);
+
+    v = jibi_eval_str(interp, "test", "true");
+    int b;
+    assert(jibi_error(interp) == NULL);
+    assert(jibi_to_bool(v, &b) == 0 && b == 1);
+    assert(jibi_to_int(v, &n) == -1);
+    jibi_value_free(v);
+
+    jibi_free(interp);
+    printf("capi tests ok\n");
+    return 0;
+}
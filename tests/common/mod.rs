@@ -0,0 +1,15 @@
+use std::path::PathBuf;
+
+/// Create a temporary directory containing `files`, given by path and content,
+/// return its path. The name of the directory includes the process id, so that
+/// concurrent test runs do not share it.
+pub fn temp_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    for (path, source) in files {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, source).unwrap();
+    }
+    dir
+}
@@ -0,0 +1,199 @@
+use std::cell::RefCell;
+use std::io::{Cursor, Write};
+use std::rc::Rc;
+
+use jibi::dap::DebugAdapter;
+use jibi::protocol::{read_message, write_message, Json};
+
+const PROGRAM: &str = r#"(defn square (x)
+    (* x x))
+(print "start")
+(def total (square (nth 2 '(1 2 3))))
+(print (string total))
+"#;
+
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn request(seq: usize, command: &str, arguments: Json) -> Json {
+    Json::object(vec![
+        ("seq", seq.into()),
+        ("type", "request".into()),
+        ("command", command.into()),
+        ("arguments", arguments),
+    ])
+}
+
+/// Run a session launching `PROGRAM`, return the messages of the adapter.
+fn session(name: &str, launch: Json, requests: Vec<(&str, Json)>) -> Vec<Json> {
+    let path = std::env::temp_dir().join(name);
+    std::fs::write(&path, PROGRAM).unwrap();
+    let path = path.to_string_lossy().to_string();
+    let mut launch = launch;
+    if let Json::Object(members) = &mut launch {
+        members.push(("program".to_string(), path.as_str().into()));
+    }
+    let breakpoints = Json::object(vec![
+        ("source", Json::object(vec![("path", path.as_str().into())])),
+        (
+            "breakpoints",
+            vec![Json::object(vec![("line", 2.into())])].into(),
+        ),
+    ]);
+    let mut messages = vec![
+        ("initialize", Json::object(vec![])),
+        ("launch", launch),
+        ("setBreakpoints", breakpoints),
+        ("configurationDone", Json::Null),
+    ];
+    messages.extend(requests);
+    messages.push(("disconnect", Json::Null));
+
+    let mut input = vec![];
+    for (seq, (command, arguments)) in messages.into_iter().enumerate() {
+        write_message(&mut input, &request(seq + 1, command, arguments)).unwrap();
+    }
+    let output = Output::default();
+    DebugAdapter::default()
+        .run(Cursor::new(input), output.clone())
+        .unwrap();
+    let mut output = Cursor::new(output.0.borrow().clone());
+    let mut messages = vec![];
+    while let Some(body) = read_message(&mut output).unwrap() {
+        messages.push(Json::parse(&body).unwrap());
+    }
+    messages
+}
+
+/// Summary of a message: command or event name, and success of responses.
+fn summary(message: &Json) -> String {
+    match message["type"].as_str() {
+        Some("response") => format!(
+            "{} {}",
+            message["command"].as_str().unwrap(),
+            message["success"]
+        ),
+        _ => match message["event"].as_str().unwrap() {
+            "output" => format!("output {}", message["body"]["output"]),
+            "stopped" => format!("stopped {}", message["body"]["reason"]),
+            event => event.to_string(),
+        },
+    }
+}
+
+#[test]
+fn test_dap_breakpoints() {
+    let messages = session(
+        "jibi-dap-breakpoints.jibi",
+        Json::object(vec![]),
+        vec![
+            ("threads", Json::Null),
+            ("stackTrace", Json::object(vec![("threadId", 1.into())])),
+            ("scopes", Json::object(vec![("frameId", 0.into())])),
+            (
+                "variables",
+                Json::object(vec![("variablesReference", 1.into())]),
+            ),
+            (
+                "evaluate",
+                Json::object(vec![
+                    ("expression", "(+ x 1)".into()),
+                    ("frameId", 0.into()),
+                ]),
+            ),
+            (
+                "evaluate",
+                Json::object(vec![("expression", "nope".into()), ("frameId", 1.into())]),
+            ),
+            ("stepOut", Json::Null),
+            ("continue", Json::Null),
+        ],
+    );
+    let summaries: Vec<String> = messages.iter().map(summary).collect();
+    assert_eq!(
+        vec![
+            "initialize true",
+            "initialized",
+            "launch true",
+            "setBreakpoints true",
+            "configurationDone true",
+            "output \"start\\n\"",
+            "stopped \"breakpoint\"",
+            "threads true",
+            "stackTrace true",
+            "scopes true",
+            "variables true",
+            "evaluate true",
+            "evaluate false",
+            "stepOut true",
+            "stopped \"step\"",
+            "continue true",
+            "output \"9\\n\"",
+            "exited",
+            "terminated",
+            "disconnect true",
+        ],
+        summaries
+    );
+
+    let frames: Vec<String> = messages[8]["body"]["stackFrames"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| format!("{} {} {}:{}", f["id"], f["name"], f["line"], f["column"]))
+        .collect();
+    assert_eq!(vec!["0 \"square\" 2:5", "1 \"top-level\" 4:12"], frames);
+    let scopes = &messages[9]["body"]["scopes"];
+    assert_eq!(
+        vec![Some("Locals"), Some("Globals")],
+        scopes
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| s["name"].as_str())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        "[{\"name\":\"x\",\"value\":\"3\",\"variablesReference\":0}]",
+        messages[10]["body"]["variables"].to_string()
+    );
+    assert_eq!(Some("4"), messages[11]["body"]["result"].as_str());
+    assert_eq!(Some("NotDefined: nope"), messages[12]["message"].as_str());
+    assert_eq!(Json::from(0usize), messages[17]["body"]["exitCode"]);
+}
+
+#[test]
+fn test_dap_stop_on_entry() {
+    let messages = session(
+        "jibi-dap-entry.jibi",
+        Json::object(vec![("stopOnEntry", true.into())]),
+        vec![("next", Json::Null), ("terminate", Json::Null)],
+    );
+    let summaries: Vec<String> = messages.iter().map(summary).collect();
+    assert_eq!(
+        vec![
+            "initialize true",
+            "initialized",
+            "launch true",
+            "setBreakpoints true",
+            "configurationDone true",
+            "stopped \"entry\"",
+            "next true",
+            "stopped \"step\"",
+            "terminate true",
+            "exited",
+            "terminated",
+            "disconnect true",
+        ],
+        summaries
+    );
+}
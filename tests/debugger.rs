@@ -0,0 +1,134 @@
+use std::cell::RefCell;
+use std::io::{Cursor, Write};
+use std::rc::Rc;
+
+use jibi::debugger::{Breakpoint, DebugConsole, DebugFrontend, DebugSession, Debugger, Resume};
+use jibi::Interpreter;
+
+const PROGRAM: &str = r#"(defn square (x)
+    (* x x))
+(defn sum-squares (a b)
+    (+ (square a)
+       (square b)))
+(def total (sum-squares 2 3))
+(breakpoint)
+(def done true)
+"#;
+
+/// Resumes with the given commands in order, recording where it paused.
+struct Script {
+    commands: Vec<Resume>,
+    pauses: Rc<RefCell<Vec<String>>>,
+}
+
+impl DebugFrontend for Script {
+    fn paused(&mut self, session: &mut DebugSession) -> Resume {
+        let frame = session.frame();
+        let pos = frame.pos.as_ref().unwrap();
+        self.pauses.borrow_mut().push(format!(
+            "{} {}:{} {}",
+            session.reason(),
+            pos.lineno,
+            pos.col,
+            frame.name
+        ));
+        if self.commands.is_empty() {
+            Resume::Continue
+        } else {
+            self.commands.remove(0)
+        }
+    }
+}
+
+fn pauses(debugger: impl FnOnce(Script) -> Debugger, commands: Vec<Resume>) -> Vec<String> {
+    let pauses = Rc::new(RefCell::new(vec![]));
+    let mut jibi = Interpreter::default();
+    jibi.set_debugger(Some(debugger(Script {
+        commands,
+        pauses: Rc::clone(&pauses),
+    })));
+    jibi.eval_str("test.jibi", PROGRAM).unwrap();
+    let pauses = pauses.borrow().clone();
+    pauses
+}
+
+#[test]
+fn test_debugger_step() {
+    use Resume::*;
+    assert_eq!(
+        vec![
+            "step 1:1 top-level",
+            "step 3:1 top-level",
+            "step 6:1 top-level",
+            "step 6:12 top-level",
+            "step 4:5 sum-squares",
+            "step 4:8 sum-squares",
+            "step 2:5 square",
+            "step 5:8 sum-squares",
+            "step 7:1 top-level",
+            "breakpoint 7:1 top-level",
+        ],
+        pauses(
+            |s| Debugger::new(s).stop_on_entry(true),
+            vec![StepOver, StepOver, StepInto, StepInto, StepInto, StepInto, StepOut, StepOut]
+        )
+    );
+}
+
+#[test]
+fn test_debugger_breakpoints() {
+    assert_eq!(
+        vec![
+            "breakpoint 5:8 sum-squares",
+            "breakpoint 7:1 top-level",
+            "step 8:1 top-level",
+        ],
+        pauses(
+            |s| Debugger::new(s).with_breakpoint(Breakpoint::new("test.jibi", 5)),
+            vec![Resume::Continue, Resume::StepOver]
+        )
+    );
+}
+
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_debugger_console() {
+    let output = Output::default();
+    let input = "b 2\nc\nl\np (* x 10)\nbt\nzzz\nq\n";
+    let console = DebugConsole::new(Cursor::new(input), output.clone());
+    let mut jibi = Interpreter::default();
+    jibi.set_debugger(Some(Debugger::new(console).stop_on_entry(true)));
+
+    let err = jibi.eval_str("test.jibi", PROGRAM).unwrap_err();
+    assert_eq!("EvalError: quit from debugger", err.1.to_string());
+    let output = String::from_utf8(output.0.borrow().clone()).unwrap();
+    assert_eq!(
+        "step at test.jibi:1:1 in top-level
+    (defn square (x) (* x x))
+debug> breakpoint at test.jibi:2
+debug> breakpoint at test.jibi:2:5 in square
+    (* x x)
+debug> x = 2
+debug> 20
+debug> #0 square at test.jibi:2:5
+#1 sum-squares at test.jibi:4:8
+#2 top-level at test.jibi:6:12
+debug> unknown command zzz, type h for help
+debug> ",
+        output
+    );
+
+    // Quitting detaches the debugger
+    jibi.eval_str("test.jibi", "(breakpoint)").unwrap();
+}
@@ -0,0 +1,136 @@
+#![cfg(feature = "derive")]
+use jibi::{FromJVal, Interpreter, JibiRecord, JibiValue, ToJVal};
+
+#[derive(Debug, PartialEq, JibiValue)]
+struct HttpConfig {
+    host: String,
+    max_retries: u32,
+    timeout: Option<f64>,
+}
+
+#[derive(Debug, PartialEq, JibiValue)]
+struct Pair(i64, i64);
+
+#[derive(Debug, PartialEq, JibiValue)]
+struct Point(i64, i64);
+
+#[derive(Debug, PartialEq, JibiValue)]
+struct Tagged<T> {
+    tag: String,
+    value: T,
+}
+
+#[derive(Debug, PartialEq, JibiValue)]
+enum Shape {
+    Empty,
+    Circle { radius: f64 },
+    Segment(Point, Point),
+}
+
+#[test]
+fn test_derive_struct() {
+    let mut jibi = Interpreter::default();
+    HttpConfig::register(&mut jibi).unwrap();
+    let config = HttpConfig {
+        host: "localhost".to_string(),
+        max_retries: 3,
+        timeout: None,
+    };
+    let val = config.to_jval(&mut jibi.state).unwrap();
+    assert_eq!(r#"(type/HttpConfig "localhost" 3 ())"#, format!("{}", val));
+    assert_eq!(config, HttpConfig::from_jval(val.clone()).unwrap());
+
+    jibi.def("config", val);
+    let res = jibi
+        .eval_str(
+            "test",
+            r#"
+            (assert (http-config? config))
+            (assert (equal? "localhost" (http-config-host config)))
+            (assert (= 3 (http-config-max-retries config)))
+            (make-http-config "example.com" 5 1.5)
+            "#,
+        )
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        HttpConfig {
+            host: "example.com".to_string(),
+            max_retries: 5,
+            timeout: Some(1.5),
+        },
+        HttpConfig::from_jval(res).unwrap()
+    );
+
+    // The constructor checks field types
+    assert!(jibi
+        .eval_str("test", "(make-http-config \"example.com\" \"5\" 1.5)")
+        .is_err());
+}
+
+#[test]
+fn test_derive_enum() {
+    let mut jibi = Interpreter::default();
+    Shape::register(&mut jibi).unwrap();
+    for shape in [
+        Shape::Empty,
+        Shape::Circle { radius: 2.0 },
+        Shape::Segment(Point(0, 0), Point(1, 2)),
+    ] {
+        let val = shape.to_jval(&mut jibi.state).unwrap();
+        assert_eq!(shape, Shape::from_jval(val).unwrap());
+    }
+
+    Point::register(&mut jibi).unwrap();
+    let res = jibi
+        .eval_str(
+            "test",
+            r#"
+            (def c (make-shape-circle 1.0))
+            (assert (shape? c))
+            (assert (shape-circle? c))
+            (assert (not (shape-empty? c)))
+            (assert (= 1.0 (shape-circle-radius c)))
+            (assert (not (shape? (make-point 1 2))))
+            (shape-segment-1 (make-shape-segment (make-point 1 2) (make-point 3 4)))
+            "#,
+        )
+        .unwrap()
+        .unwrap();
+    assert_eq!(Point(3, 4), Point::from_jval(res).unwrap());
+
+    // Registering twice fails
+    assert!(Shape::register(&mut jibi).is_err());
+}
+
+#[test]
+fn test_derive_defined_names() {
+    let mut jibi = Interpreter::default();
+    // pair? is defined by the prelude
+    let pair = jibi.lookup("pair?").unwrap();
+    let err = Pair::register(&mut jibi).unwrap_err();
+    assert_eq!("cannot register pair?, it is already defined", err.reason);
+    assert!(std::rc::Rc::ptr_eq(&pair, &jibi.lookup("pair?").unwrap()));
+    assert!(jibi.lookup("make-pair").is_none());
+}
+
+#[test]
+fn test_derive_generic() {
+    let mut jibi = Interpreter::default();
+    Tagged::<i64>::register(&mut jibi).unwrap();
+    let tagged = Tagged {
+        tag: "answer".to_string(),
+        value: vec![Point(4, 2)],
+    };
+    let val = tagged.to_jval(&mut jibi.state).unwrap();
+    assert_eq!(
+        r#"(type/Tagged "answer" ((type/Point 4 2)))"#,
+        format!("{}", val)
+    );
+    assert_eq!(tagged, Tagged::from_jval(val).unwrap());
+    let res = jibi
+        .eval_str("test", "(tagged-value (make-tagged \"x\" 42))")
+        .unwrap()
+        .unwrap();
+    assert_eq!(42, res.to_int().unwrap());
+}
@@ -0,0 +1,417 @@
+use std::cell::{Cell, RefCell};
+use std::io::{BufReader, Read};
+use std::rc::Rc;
+
+use jibi::coverage::Coverage;
+use jibi::profiler::Profiler;
+use jibi::{Interpreter, JErrorKind, JForeignType};
+
+struct Counter {
+    count: RefCell<i128>,
+}
+
+#[test]
+fn test_foreign_round_trip() {
+    let mut jibi = Interpreter::default();
+    let counter_type = JForeignType::new("Counter")
+        .with_repr(|c: &Counter| format!("#[Counter {}]", c.count.borrow()))
+        .with_method("incr!", |c: &Counter, _args, state| {
+            *c.count.borrow_mut() += 1;
+            Ok(state.int(*c.count.borrow()))
+        })
+        .into_ref();
+    let counter = jibi.foreign(
+        &counter_type,
+        Counter {
+            count: RefCell::new(0),
+        },
+    );
+    jibi.def("counter", counter);
+
+    let res = jibi
+        .eval_str(
+            "test",
+            r##"
+            (foreign-call counter 'incr!)
+            (foreign-call counter 'incr!)
+            (assert (foreign? counter))
+            (assert (eq? 'Counter (foreign-type counter)))
+            (assert (equal? '(incr!) (foreign-methods counter)))
+            (assert (equal? "#[Counter 2]" (repr counter)))
+            counter
+            "##,
+        )
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        2,
+        *res.downcast_foreign::<Counter>().unwrap().count.borrow()
+    );
+    assert!(res.downcast_foreign::<String>().is_err());
+
+    let err = jibi
+        .eval_str("test", "(foreign-call counter 'nope)")
+        .unwrap_err();
+    assert_eq!(jibi::JErrorKind::NotDefined, err.1.kind);
+}
+
+#[test]
+fn test_image_round_trip() {
+    let path = std::env::temp_dir().join(format!("jibi-test-{}.img", std::process::id()));
+    let mut jibi = Interpreter::default();
+    jibi.eval_str(
+        "test",
+        r#"
+        (import "stl/math" as math)
+        (defn make-counter ()
+            (let ((n 0)) (fn () (set! n (+ n 1)) n)))
+        (def counter (make-counter))
+        (counter)
+        (defmacro twice (expr) `(begin ,expr ,expr))
+        (def v (vec::new 1 2))
+        (vec::push! v v)
+        (def shared '(1 2 3))
+        (def pair (cons shared shared))
+        (char-macro! "!" (fn (s) (list 'not (read s))))
+        "#,
+    )
+    .unwrap();
+    jibi.save_image(&path).unwrap();
+
+    let mut jibi = Interpreter::load_image(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let res = jibi
+        .eval_str(
+            "test",
+            r#"
+            (twice (counter))
+            (assert (eq? (car pair) (cdr pair)))
+            (assert (eq? v (vec::get v 2)))
+            (assert (= 3 (vec::len v)))
+            (assert (eq? 'foo (quote foo)))
+            (import "stl/math" as m2)
+            (assert (equal? math m2))
+            (assert !#f)
+            (counter)
+            "#,
+        )
+        .unwrap()
+        .unwrap();
+    assert_eq!(4, res.to_int().unwrap());
+    jibi.eval_file("tests/test-lang-functions.jibi").unwrap();
+}
+
+#[test]
+fn test_image_foreign_error() {
+    let path = std::env::temp_dir().join(format!("jibi-test-foreign-{}.img", std::process::id()));
+    let mut jibi = Interpreter::default();
+    let ftype = JForeignType::new("Thing").into_ref();
+    let thing = jibi.foreign(&ftype, ());
+    jibi.def("thing", thing);
+    assert!(jibi.save_image(&path).is_err());
+}
+
+/// Reader producing one line per read, checking how many forms have been
+/// evaluated before each line is read.
+struct CheckedLines {
+    lines: Vec<(usize, &'static str)>,
+    read: usize,
+    evaluated: Rc<Cell<usize>>,
+}
+
+impl Read for CheckedLines {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let line = match self.lines.get(self.read) {
+            Some((evaluated, line)) => {
+                assert_eq!(*evaluated, self.evaluated.get());
+                line.as_bytes()
+            }
+            None => return Ok(0),
+        };
+        self.read += 1;
+        buf[..line.len()].copy_from_slice(line);
+        Ok(line.len())
+    }
+}
+
+#[test]
+fn test_error_kind_names() {
+    use JErrorKind::*;
+    for kind in [
+        Exception,
+        AssertionError,
+        TypeError,
+        EvalError,
+        ApplyError,
+        NotDefined,
+        OsError,
+        SyntaxError,
+        Other("ImageError".to_string()),
+    ] {
+        assert_eq!(kind, JErrorKind::from_name(&kind.to_string()));
+    }
+}
+
+#[test]
+fn test_eval_reader() {
+    let mut jibi = Interpreter::default();
+    let evaluated = Rc::new(Cell::new(0));
+    let counter = Rc::clone(&evaluated);
+    let tick = jibi.builtin("tick!".to_string(), move |_args, _env, state| {
+        counter.set(counter.get() + 1);
+        Ok(state.nil())
+    });
+    jibi.def("tick!", tick);
+    let reader = CheckedLines {
+        lines: vec![
+            (0, "(tick!)\n"),
+            (1, "(def x \"a\n"),
+            (1, "b\") (tick!)\n"),
+            (2, "(tick!) x"),
+        ],
+        read: 0,
+        evaluated: Rc::clone(&evaluated),
+    };
+    let res = jibi
+        .eval_reader("test", BufReader::new(reader))
+        .unwrap()
+        .unwrap();
+    assert_eq!("a\nb", res.to_str().unwrap());
+    assert_eq!(3, evaluated.get());
+
+    let err = jibi
+        .eval_reader("test", "(tick!)\n(tick!))".as_bytes())
+        .unwrap_err();
+    assert_eq!(jibi::JErrorKind::SyntaxError, err.1.kind);
+    assert_eq!(5, evaluated.get());
+}
+
+#[test]
+fn test_eval_reader_multiline() {
+    let mut jibi = Interpreter::default();
+    let source = r##"
+        (def calls 0)
+        (char-macro! "#{" (fn (s) (set! calls (+ calls 1)) (cons 'list (read-delimited s "}"))))
+        (def xs #{1
+          2 #| spanning
+          lines |# 3
+          "a
+          b"})
+        (list calls xs)
+    "##;
+    // One line at a time, each token and char macro is read once
+    let res = jibi
+        .eval_reader("test", BufReader::with_capacity(1, source.as_bytes()))
+        .unwrap()
+        .unwrap();
+    assert_eq!("(1 (1 2 3 \"a\\n          b\"))", res.to_string());
+
+    let long = format!("\"{}\"", "line\n".repeat(10000));
+    let res = jibi
+        .eval_reader("test", std::io::Cursor::new(long))
+        .unwrap()
+        .unwrap();
+    assert_eq!(50000, res.to_str().unwrap().len());
+}
+
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl std::io::Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_trace() {
+    let mut jibi = Interpreter::default();
+    let output = Output::default();
+    jibi.set_output(Some(Box::new(output.clone())));
+    jibi.eval_str(
+        "test",
+        r#"
+        (defn f (x) (+ x 1))
+        (defn g (x) (raise (exception "no")))
+        (trace f)
+        (trace +)
+        (print "traced")
+        (f 2)
+        (trace g)
+        (try (g '(1 "a")) nil)
+        (untrace +)
+        (f 3)
+        (defn s (x) (concat x "b"))
+        (trace s)
+        (s "a")
+        (untrace)
+        (f 4)
+        (try (trace (fn (x) x)) (print (repr err)))
+        "#,
+    )
+    .unwrap();
+    assert_eq!(
+        r#"traced
+(f 2)
+  (+ 2 1)
+  => 3
+=> 3
+(g (1 "a"))
+!! Exception: no
+(f 3)
+=> 4
+(s "a")
+=> "ab"
+#[error TypeError: expected a named lambda or builtin]
+"#,
+        String::from_utf8(output.0.borrow().clone()).unwrap()
+    );
+
+    // Only procedures defined in traced files
+    let output = Output::default();
+    jibi.set_output(Some(Box::new(output.clone())));
+    jibi.trace_file("traced.jibi");
+    jibi.eval_str("traced.jibi", "(defn h (x) (f x)) (h 1)")
+        .unwrap();
+    jibi.eval_str("other.jibi", "(defn k () 1) (k)").unwrap();
+    assert_eq!(
+        "(h 1)\n=> 2\n",
+        String::from_utf8(output.0.borrow().clone()).unwrap()
+    );
+}
+
+#[test]
+fn test_profiler() {
+    let mut jibi = Interpreter::default();
+    jibi.set_profiler(Some(Profiler::new()));
+    jibi.eval_str(
+        "test",
+        r#"
+        (defn g (x) (list x x))
+        (defn f (n) (if (= n 0) nil (g (f (- n 1)))))
+        (f 3)
+        "#,
+    )
+    .unwrap();
+    let profiler = jibi.take_profiler().unwrap();
+    let entries = profiler.entries();
+    let entry = |name: &str| entries.iter().find(|(n, _)| *n == name).unwrap().1;
+    assert_eq!(4, entry("f (test:3:9)").calls);
+    assert_eq!(3, entry("g (test:2:9)").calls);
+    // The argument list of list
+    assert_eq!(6, entry("g (test:2:9)").allocations);
+    assert!(entry("f (test:3:9)").total >= entry("g (test:2:9)").total);
+    assert!(profiler
+        .folded()
+        .lines()
+        .any(|l| l.starts_with("f (test:3:9);f (test:3:9);g (test:2:9) ")));
+
+    let output = Output::default();
+    jibi.set_output(Some(Box::new(output.clone())));
+    let res = jibi
+        .eval_str("test", "(with-profiling (g 1) (g 2))")
+        .unwrap();
+    assert_eq!("(2 2)", res.unwrap().to_string());
+    let output = String::from_utf8(output.0.borrow().clone()).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert!(lines[0].ends_with("procedure"));
+    assert!(lines
+        .iter()
+        .any(|l| l.trim_start().starts_with("2 ") && l.ends_with("g (test:2:9)")));
+    assert!(jibi.take_profiler().is_none());
+
+    // Calls in nested regions are part of the enclosing profile
+    jibi.set_profiler(Some(Profiler::new()));
+    jibi.eval_str("nested", "(defn h () (with-profiling (g 1) (g 2))) (h)")
+        .unwrap()
+        .unwrap();
+    let profiler = jibi.take_profiler().unwrap();
+    let entries = profiler.entries();
+    let entry = |name: &str| entries.iter().find(|(n, _)| *n == name).unwrap().1;
+    assert_eq!(2, entry("g (test:2:9)").calls);
+    assert_eq!(4, entry("g (test:2:9)").allocations);
+    assert!(entry("h (nested:1:1)").total >= entry("g (test:2:9)").total);
+    assert!(profiler
+        .folded()
+        .lines()
+        .any(|l| l.starts_with("h (nested:1:1);g (test:2:9) ")));
+}
+
+#[test]
+fn test_coverage() {
+    let mut jibi = Interpreter::default();
+    jibi.set_coverage(Some(Coverage::new()));
+    jibi.eval_str(
+        "test",
+        r#"(defn f (x)
+    (cond
+        ((= x 0) 'zero)
+        (else
+            (let ((y
+                   2))
+                (g x y)))))
+(defn g (x y)
+    (* x y))
+(def data '((1 2)
+    (3 4)))
+(f 0)
+(f 0)
+"#,
+    )
+    .unwrap();
+    let coverage = jibi.take_coverage().unwrap();
+    let files = coverage.files();
+    assert_eq!(1, files.len());
+    assert_eq!(
+        vec![
+            (1, 1),
+            (2, 2),
+            (3, 2),
+            (5, 0),
+            (7, 0),
+            (8, 1),
+            (9, 0),
+            (10, 1),
+            (12, 1),
+            (13, 1)
+        ],
+        files[0]
+            .lines
+            .iter()
+            .map(|(line, hits)| (*line, *hits))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!((10, 7), (files[0].total(), files[0].covered()));
+    assert_eq!(70.0, coverage.percent());
+    assert!(coverage.lcov().starts_with("SF:test\nDA:1,1\nDA:2,2\n"));
+    assert!(coverage
+        .lcov()
+        .ends_with("DA:13,1\nLF:10\nLH:7\nend_of_record\n"));
+    assert!(coverage
+        .report()
+        .ends_with("      10        7    70.0  total\n"));
+
+    // Parameter lists of named lambdas and macros are not forms
+    jibi.set_coverage(Some(Coverage::new()));
+    jibi.eval_str(
+        "named",
+        r#"(def h (nfn "h"
+    (x y)
+    (+ x y)))
+(def m (nmacro "m"
+    (x)
+    x))
+(h 1 2)
+"#,
+    )
+    .unwrap();
+    let files = jibi.take_coverage().unwrap().files();
+    assert_eq!(
+        vec![1, 3, 4, 7],
+        files[0].lines.keys().copied().collect::<Vec<_>>()
+    );
+}
@@ -0,0 +1,110 @@
+use jibi::lint::Linter;
+
+fn check(source: &str) -> Vec<String> {
+    Linter::default()
+        .check_source("test", source)
+        .iter()
+        .map(|d| d.to_string())
+        .collect()
+}
+
+#[test]
+fn test_lint_clean() {
+    let source = r#"
+        (import "stl/math" as math)
+        (import-from "stl/unittest" test assert-equal)
+        (defn f (x . rest)
+            (defn helper (y) (+ x y))
+            (let ((a 1) (b (helper a)))
+                (try (math::abs b) (print (repr err)))))
+        (test "f" (assert-equal (f 1) 2) (assert-equal (g) 3))
+        (defn g () (cond ((= 1 2) 0) (else 3)))
+        (defmacro m (a) `(list ,a undefined-but-quoted))
+        (m (unknown macro args))
+    "#;
+    let empty: Vec<String> = vec![];
+    assert_eq!(empty, check(source));
+}
+
+#[test]
+fn test_lint_errors() {
+    assert_eq!(
+        vec![
+            "test:1:1: error: undefined symbol `y`",
+            "test:2:2: error: `if` expects 3 argument(s), got 2",
+            "test:3:2: error: `set!` expects 2 argument(s), got 1",
+            "test:4:2: error: `nfn` expects at least 2 argument(s), got 1",
+            "test:5:2: error: `nope` is not defined in module `math`",
+            "test:6:5: error: invalid parameter list",
+        ],
+        check(
+            "y\n(if true 1)\n(set! x)\n(nfn \"f\")\n(math::nope 1)\n(fn x 1)\n(import \"stl/math\" as math)\n(def x 1)"
+        )
+    );
+    assert_eq!(
+        vec![
+            "test:1:14: error: module not found: no/such/module",
+            "test:2:29: error: `nope` is not defined in module stl/math",
+        ],
+        check("(import-from \"no/such/module\" x)\n(import-from \"stl/math\" abs nope)")
+    );
+    assert_eq!(
+        vec!["test:1:12: error: unexpected end of input"],
+        check("(defn f (x)")
+    );
+}
+
+#[test]
+fn test_lint_warnings() {
+    assert_eq!(
+        vec![
+            "test:1:10: warning: `list` shadows a global",
+            "test:2:14: warning: unused binding `b`",
+            "test:3:16: warning: unreachable cond clause",
+        ],
+        check("(defn f (list) (car list))\n(let ((a 1) (b 2) (_c 3)) a)\n(cond (else 1) (true 2))")
+    );
+}
+
+#[test]
+fn test_lint_redefined_special_forms() {
+    // Redefined special forms are regular calls
+    assert_eq!(
+        vec!["test:1:7: warning: `try` shadows a global"],
+        check("(defn try (a) a) (try 1)")
+    );
+    assert_eq!(
+        vec!["test:1:6: warning: `let` shadows a global"],
+        check("(def let (fn () 1)) (let)")
+    );
+    assert_eq!(
+        vec![
+            "test:1:7: warning: `import` shadows a global",
+            "test:1:22: error: `import` expects 1 argument(s), got 2",
+        ],
+        check("(defn import (a) a) (import 1 2)")
+    );
+}
+
+#[test]
+fn test_lint_modified_module() {
+    let dir = std::env::temp_dir().join(format!("jibi-lint-module-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let module = dir.join("module.jibi");
+    std::fs::write(&module, "(def a 1)").unwrap();
+    let source = format!("(import-from \"{}\" a b)", dir.join("module").display());
+    let linter = Linter::default();
+    assert_eq!(1, linter.check_source("test", &source).len());
+
+    // Modules are read again when modified
+    std::fs::write(&module, "(def a 1) (def b 2)").unwrap();
+    let later = std::time::SystemTime::now() + std::time::Duration::from_secs(10);
+    std::fs::File::options()
+        .write(true)
+        .open(&module)
+        .unwrap()
+        .set_modified(later)
+        .unwrap();
+    assert_eq!(0, linter.check_source("test", &source).len());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
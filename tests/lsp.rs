@@ -0,0 +1,185 @@
+use std::io::Cursor;
+
+use jibi::lsp::LanguageServer;
+use jibi::protocol::{read_message, write_message, Json};
+
+const URI: &str = "file:///tmp/test.jibi";
+
+const SOURCE: &str = r#"(import "stl/math" as math)
+(defn square (x)
+    "Multiply x by itself."
+    (* x x))
+(def answer (square 6))
+(math::abs answer)
+(print undefined-thing)
+"#;
+
+fn request(id: usize, method: &str, params: Json) -> Json {
+    Json::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("id", id.into()),
+        ("method", method.into()),
+        ("params", params),
+    ])
+}
+
+fn notification(method: &str, params: Json) -> Json {
+    Json::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("method", method.into()),
+        ("params", params),
+    ])
+}
+
+fn position(line: usize, character: usize) -> Json {
+    Json::object(vec![
+        ("textDocument", Json::object(vec![("uri", URI.into())])),
+        (
+            "position",
+            Json::object(vec![("line", line.into()), ("character", character.into())]),
+        ),
+    ])
+}
+
+/// Run a session opening `SOURCE`, return the messages of the server after
+/// the response to initialize.
+fn session(messages: Vec<Json>) -> Vec<Json> {
+    let open = notification(
+        "textDocument/didOpen",
+        Json::object(vec![(
+            "textDocument",
+            Json::object(vec![
+                ("uri", URI.into()),
+                ("languageId", "jibi".into()),
+                ("version", 1.into()),
+                ("text", SOURCE.into()),
+            ]),
+        )]),
+    );
+    let mut input = vec![];
+    write_message(&mut input, &request(0, "initialize", Json::object(vec![]))).unwrap();
+    write_message(&mut input, &open).unwrap();
+    for message in &messages {
+        write_message(&mut input, message).unwrap();
+    }
+    write_message(&mut input, &request(99, "shutdown", Json::Null)).unwrap();
+    write_message(&mut input, &notification("exit", Json::Null)).unwrap();
+
+    let mut output = vec![];
+    LanguageServer::default()
+        .run(Cursor::new(input), &mut output)
+        .unwrap();
+    let mut output = Cursor::new(output);
+    let mut responses = vec![];
+    while let Some(body) = read_message(&mut output).unwrap() {
+        responses.push(Json::parse(&body).unwrap());
+    }
+    // Responses to initialize and shutdown
+    assert!(responses[0]["result"]["capabilities"]["hoverProvider"] == true.into());
+    assert_eq!(Json::Null, responses.pop().unwrap()["result"]);
+    responses.drain(1..).collect()
+}
+
+#[test]
+fn test_lsp_diagnostics() {
+    let responses = session(vec![]);
+    assert_eq!(1, responses.len());
+    let params = &responses[0]["params"];
+    assert_eq!(
+        Some("textDocument/publishDiagnostics"),
+        responses[0]["method"].as_str()
+    );
+    assert_eq!(Some(URI), params["uri"].as_str());
+    assert_eq!(
+        "[{\"range\":{\"start\":{\"line\":6,\"character\":7},\"end\":{\"line\":6,\"character\":22}},\
+         \"severity\":1,\"source\":\"jibi\",\"message\":\"undefined symbol `undefined-thing`\"}]",
+        params["diagnostics"].to_string()
+    );
+}
+
+#[test]
+fn test_lsp_redefined_special_form() {
+    // Checking a document which redefines a special form does not stop the server
+    let change = notification(
+        "textDocument/didChange",
+        Json::object(vec![
+            ("textDocument", Json::object(vec![("uri", URI.into())])),
+            (
+                "contentChanges",
+                vec![Json::object(vec![(
+                    "text",
+                    "(defn try (a) a) (try 1)".into(),
+                )])]
+                .into(),
+            ),
+        ]),
+    );
+    let responses = session(vec![
+        change,
+        request(1, "textDocument/hover", position(0, 0)),
+    ]);
+    assert_eq!(3, responses.len());
+    assert_eq!(
+        Some("`try` shadows a global"),
+        responses[1]["params"]["diagnostics"].as_array().unwrap()[0]["message"].as_str()
+    );
+}
+
+#[test]
+fn test_lsp_requests() {
+    let responses = session(vec![
+        request(1, "textDocument/hover", position(4, 14)),
+        request(2, "textDocument/definition", position(4, 14)),
+        request(3, "textDocument/completion", position(5, 8)),
+        request(4, "textDocument/documentSymbol", position(0, 0)),
+        request(5, "textDocument/hover", position(5, 8)),
+        request(6, "textDocument/definition", position(0, 10)),
+        request(7, "no/such/method", Json::Null),
+    ]);
+    // Diagnostics on open, then responses
+    assert_eq!(8, responses.len());
+    let responses = &responses[1..];
+
+    let hover = &responses[0]["result"]["contents"]["value"];
+    assert_eq!(
+        Some("```jibi\n(square x)\n```\nprocedure\n\nMultiply x by itself."),
+        hover.as_str()
+    );
+
+    let definition = &responses[1]["result"];
+    assert_eq!(Some(URI), definition["uri"].as_str());
+    assert_eq!(
+        "{\"start\":{\"line\":1,\"character\":6},\"end\":{\"line\":1,\"character\":12}}",
+        definition["range"].to_string()
+    );
+
+    let labels: Vec<&str> = responses[2]["result"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|item| item["label"].as_str())
+        .collect();
+    assert!(labels.contains(&"math::abs"));
+    assert!(labels.iter().all(|l| l.starts_with("math::a")));
+
+    let symbols: Vec<String> = responses[3]["result"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| format!("{} {}", s["name"], s["kind"]))
+        .collect();
+    assert_eq!(
+        vec!["\"math\" 2", "\"square\" 12", "\"answer\" 13"],
+        symbols
+    );
+
+    let hover = responses[4]["result"]["contents"]["value"]
+        .as_str()
+        .unwrap();
+    assert!(hover.starts_with("```jibi\n(math::abs"), "{}", hover);
+
+    let module = responses[5]["result"]["uri"].as_str().unwrap();
+    assert!(module.ends_with("/stl/math.jibi"), "{}", module);
+
+    assert_eq!(Json::Number(-32601.0), responses[6]["error"]["code"]);
+}
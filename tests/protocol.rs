@@ -0,0 +1,40 @@
+use std::io::Cursor;
+
+use jibi::protocol::{read_message, write_message, Json};
+
+#[test]
+fn test_json() {
+    let s = r#"{"a":[1,2.5,"x\né"],"b":null,"c":true}"#;
+    let json = Json::parse(s).unwrap();
+    assert_eq!(Some(1), json["a"].as_array().unwrap()[0].as_u64());
+    assert_eq!(Some("x\né"), json["a"].as_array().unwrap()[2].as_str());
+    assert!(json["b"].is_null() && json["missing"].is_null());
+    assert_eq!(json, Json::parse(&json.to_string()).unwrap());
+    assert!(Json::parse("[1,").is_err());
+    assert!(Json::parse("{} x").is_err());
+
+    // Deep nesting is an error, not a stack overflow
+    let deep = format!("{}{}", "[".repeat(100), "]".repeat(100));
+    assert!(Json::parse(&deep).is_ok());
+    let deeper = format!("{}{}", "[".repeat(100_000), "]".repeat(100_000));
+    assert!(Json::parse(&deeper)
+        .unwrap_err()
+        .starts_with("too deeply nested"));
+}
+
+#[test]
+fn test_messages() {
+    let mut out = vec![];
+    write_message(&mut out, &Json::object(vec![("id", 1.into())])).unwrap();
+    assert_eq!(b"Content-Length: 8\r\n\r\n{\"id\":1}".to_vec(), out);
+    let mut input = Cursor::new(out);
+    assert_eq!(
+        Some("{\"id\":1}".to_string()),
+        read_message(&mut input).unwrap()
+    );
+    assert_eq!(None, read_message(&mut input).unwrap());
+    assert!(read_message(&mut Cursor::new("Other: 1\r\n\r\n")).is_err());
+    // A huge length is not allocated up front
+    let huge = format!("Content-Length: {}\r\n\r\n{{}}", usize::MAX);
+    assert!(read_message(&mut Cursor::new(huge)).is_err());
+}
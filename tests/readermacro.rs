@@ -0,0 +1,188 @@
+use jibi::{format_source, Interpreter, JErrorKind, Parser, TokenValidator};
+
+fn eval(jibi: &mut Interpreter, code: &str) -> String {
+    jibi.eval_str("test", code).unwrap().unwrap().to_string()
+}
+
+#[test]
+fn test_reader_macro_infix() {
+    let mut jibi = Interpreter::default();
+    eval(
+        &mut jibi,
+        r#"
+        (reader-macro!
+            (token-match 'char "{")
+            (token-match 'group)
+            (token-match 'ident)
+            (token-match 'group)
+            (token-match 'char "}")
+            (fn (open a op b close)
+                `(,(token 'char "(") ,op ,@a ,@b ,(token 'char ")"))))
+        "#,
+    );
+    assert_eq!("3", eval(&mut jibi, "{1 + 2}"));
+    assert_eq!("21", eval(&mut jibi, "{(+ 1 2) * (- 10 3)}"));
+    assert_eq!("(1 . 2)", eval(&mut jibi, "{1 cons 2}"));
+}
+
+#[test]
+fn test_reader_macro_repeat() {
+    let mut jibi = Interpreter::default();
+    eval(
+        &mut jibi,
+        r#"
+        (reader-macro!
+            (token-match 'char "[")
+            (token-match-repeat (token-match-or (token-match 'int) (token-match 'float)) 0)
+            (token-match 'char "]")
+            (fn (open xs close)
+                `(,(token 'char "(") ,(token 'ident 'list) ,@xs ,(token 'char ")"))))
+        "#,
+    );
+    assert_eq!("()", eval(&mut jibi, "[]"));
+    assert_eq!("(1 2.5 3)", eval(&mut jibi, "[1 2.5 3]"));
+    assert_eq!("((1 2) (3))", eval(&mut jibi, "(list [1 2] [3])"));
+}
+
+#[test]
+fn test_reader_macro_repeat_greedy() {
+    let mut jibi = Interpreter::default();
+    eval(
+        &mut jibi,
+        r#"
+        (reader-macro!
+            (token-match 'ident 'count-rest)
+            (token-match-repeat (token-match 'any) 0)
+            (fn (kw rest) (list (token 'int (foldl (fn (_ n) (+ n 1)) 0 rest)))))
+        (reader-macro!
+            (token-match 'ident 'count-two)
+            (token-match-repeat (token-match 'any) 0 2)
+            (fn (kw rest) (list (token 'int (foldl (fn (_ n) (+ n 1)) 0 rest)))))
+        "#,
+    );
+    // An unbounded repeat reads up to the end of the input
+    assert_eq!("6", eval(&mut jibi, "1 count-rest (+ 1 2) \"four\""));
+    // A bounded one stops after max tokens
+    assert_eq!("(2 3)", eval(&mut jibi, "(list count-two 1 2 3)"));
+}
+
+#[test]
+fn test_reader_macro_regex() {
+    let mut jibi = Interpreter::default();
+    eval(
+        &mut jibi,
+        r#"
+        (reader-macro!
+            (token-match 'regex "\\$[a-z]+")
+            (fn (var)
+                (let ((name (string (token-value var))))
+                    (list (token 'string (substring name 1 (len name)))))))
+        "#,
+    );
+    assert_eq!("\"home\"", eval(&mut jibi, "$home"));
+    assert_eq!("$Home", eval(&mut jibi, "'$Home"));
+
+    let err = jibi
+        .eval_str("test", r#"(token-match 'regex "(")"#)
+        .unwrap_err();
+    assert_eq!(JErrorKind::Other("RegexError".to_string()), err.1.kind);
+}
+
+#[test]
+fn test_reader_macro_seq() {
+    let mut jibi = Interpreter::default();
+    eval(
+        &mut jibi,
+        r#"
+        (reader-macro!
+            (token-match 'ident 'swap)
+            (token-match-seq (token-match 'int) (token-match 'int))
+            (fn (kw pair) (list (nth 1 pair) (nth 0 pair))))
+        "#,
+    );
+    assert_eq!("(2 1)", eval(&mut jibi, "'(swap 1 2)"));
+    assert_eq!("(swap 1)", eval(&mut jibi, "'(swap 1)"));
+}
+
+#[test]
+fn test_char_macro() {
+    let mut jibi = Interpreter::default();
+    eval(
+        &mut jibi,
+        r##"
+        (char-macro! "@" (fn (s) (list 'deref (read s))))
+        (char-macro! "#{" (fn (s) (cons 'list (read-delimited s "}"))))
+        (char-macro! "#u" (fn (s) (concat (read s) "!")))
+        (defn deref (x) (car x))
+        "##,
+    );
+    assert_eq!("1", eval(&mut jibi, "@'(1 2)"));
+    assert_eq!(
+        "(1 2 (3 4))",
+        eval(&mut jibi, "#{1 #;0 2 ; comment\n #{3 4}}")
+    );
+    assert_eq!("()", eval(&mut jibi, "#{ }"));
+    assert_eq!("\"hi!\"", eval(&mut jibi, "#u\"hi\""));
+    assert_eq!("'(deref x)", eval(&mut jibi, "''@x"));
+    // Dispatch pairs without a handler are read as usual
+    assert_eq!("(#t 16)", eval(&mut jibi, "(list #t #x10)"));
+    assert_eq!("#t", eval(&mut jibi, "(char-macro-remove! \"@\")"));
+    assert_eq!("#f", eval(&mut jibi, "(char-macro-remove! \"@\")"));
+
+    let err = jibi.eval_str("test", "#{1 2").unwrap_err();
+    assert_eq!(JErrorKind::SyntaxError, err.1.kind);
+    let err = jibi
+        .eval_str("test", "(char-macro! \"ab\" read)")
+        .unwrap_err();
+    assert_eq!(JErrorKind::TypeError, err.1.kind);
+}
+
+#[test]
+fn test_char_macro_chars() {
+    let mut jibi = Interpreter::default();
+    eval(
+        &mut jibi,
+        r##"
+        (defn read-until (s end)
+            (let ((c (read-char s)))
+                (if (or (nil? c) (equal? c end))
+                    ""
+                    (concat c (read-until s end)))))
+        (char-macro! "#q" (fn (s) (read-char s) (read-until s "|")))
+        "##,
+    );
+    assert_eq!("(\"a (b\" 1)", eval(&mut jibi, "(list #q|a (b| 1)"));
+
+    let mut validator = TokenValidator::new("test", &mut jibi.state);
+    assert!(validator.input("(list #q|a".to_string()).unwrap().is_none());
+    assert!(validator.input("b| 1)".to_string()).unwrap().is_some());
+}
+
+#[test]
+fn test_char_macro_not_evaluated() {
+    let mut jibi = Interpreter::default();
+    eval(
+        &mut jibi,
+        r#"
+        (def calls 0)
+        (char-macro! "!" (fn (s) (set! calls (+ calls 1)) (read s)))
+        "#,
+    );
+    // Handlers are only called by the reader of evaluated code
+    let mut validator = TokenValidator::new("test", &mut jibi.state);
+    let tokens = validator
+        .input("(list !1 !2)".to_string())
+        .unwrap()
+        .unwrap();
+    assert!(Parser::parse_cst("test", "(list !1 !2)", &mut jibi.state).is_err());
+    format_source("test", "(list !1 !2)").unwrap();
+    assert_eq!("0", eval(&mut jibi, "calls"));
+    assert_eq!(
+        "(1 2)",
+        jibi.eval_tokens(Box::new(tokens))
+            .unwrap()
+            .unwrap()
+            .to_string()
+    );
+    assert_eq!("2", eval(&mut jibi, "calls"));
+}
@@ -0,0 +1,120 @@
+#![cfg(feature = "serde")]
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use jibi::{Deserializer, Interpreter, Serializer};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum Backend {
+    Memory,
+    Disk(String),
+    Remote { host: String, port: u16 },
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Config {
+    name: String,
+    retries: u32,
+    ratio: f64,
+    verbose: bool,
+    tags: Vec<String>,
+    limit: Option<i64>,
+    backends: Vec<Backend>,
+    weights: BTreeMap<String, i32>,
+}
+
+fn config() -> Config {
+    let mut weights = BTreeMap::new();
+    weights.insert("a".to_string(), 1);
+    weights.insert("b".to_string(), 2);
+    Config {
+        name: "prod".to_string(),
+        retries: 3,
+        ratio: 0.5,
+        verbose: false,
+        tags: vec!["x".to_string(), "y".to_string()],
+        limit: None,
+        backends: vec![
+            Backend::Memory,
+            Backend::Disk("/tmp".to_string()),
+            Backend::Remote {
+                host: "localhost".to_string(),
+                port: 8080,
+            },
+        ],
+        weights,
+    }
+}
+
+#[test]
+fn test_serde_round_trip() {
+    let mut jibi = Interpreter::default();
+    let val = jibi.to_jval(&config()).unwrap();
+    let back: Config = jibi.from_jval(val).unwrap();
+    assert_eq!(config(), back);
+}
+
+#[test]
+fn test_serde_jibi_repr() {
+    let mut jibi = Interpreter::default();
+    let val = jibi.to_jval(&config()).unwrap();
+    assert_eq!(
+        r#"((name . "prod") (retries . 3) (ratio . 0.5) (verbose . #f) (tags "x" "y") (limit) (backends Memory (Disk "/tmp") (Remote (host . "localhost") (port . 8080))) (weights ("a" . 1) ("b" . 2)))"#,
+        format!("{}", val)
+    );
+    jibi.def("config", val);
+    let res = jibi
+        .eval_str("test", "(eq? 'name (car (car config)))")
+        .unwrap()
+        .unwrap();
+    assert!(res.to_bool().unwrap());
+}
+
+#[test]
+fn test_serde_from_jibi() {
+    let mut jibi = Interpreter::default();
+    let val = jibi
+        .eval_str(
+            "test",
+            r#"
+            (list
+                (cons 'name "dev")
+                (cons 'retries 0)
+                (cons 'ratio 1.0)
+                (cons 'verbose true)
+                (cons 'tags ((env-lookup vec 'new) "z"))
+                (cons 'limit 100)
+                (cons 'backends '((Disk "/var")))
+                (cons 'weights ()))
+            "#,
+        )
+        .unwrap()
+        .unwrap();
+    let config: Config = jibi.from_jval(val).unwrap();
+    assert_eq!("dev", config.name);
+    assert_eq!(vec!["z".to_string()], config.tags);
+    assert_eq!(Some(100), config.limit);
+    assert_eq!(vec![Backend::Disk("/var".to_string())], config.backends);
+
+    let val = jibi.eval_str("test", "'(1 2 x)").unwrap().unwrap();
+    assert!(jibi.from_jval::<Vec<i32>>(val).is_err());
+}
+
+#[test]
+fn test_serde_some_nil() {
+    // Some of a value serialized as nil is read back as None
+    let mut jibi = Interpreter::default();
+    let val = jibi.to_jval(&Some(Vec::<i32>::new())).unwrap();
+    assert_eq!(None, jibi.from_jval::<Option<Vec<i32>>>(val).unwrap());
+    let val = jibi.to_jval(&Some(vec![1])).unwrap();
+    assert_eq!(Some(vec![1]), jibi.from_jval(val).unwrap());
+
+    // Empty vectors are not nil
+    let val = Some(Vec::<i32>::new())
+        .serialize(&mut Serializer::new(&mut jibi.state).vectors())
+        .unwrap();
+    assert_eq!("#()", val.to_string());
+    let back = Option::<Vec<i32>>::deserialize(Deserializer::new(val)).unwrap();
+    assert_eq!(Some(vec![]), back);
+}
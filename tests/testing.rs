@@ -0,0 +1,305 @@
+use std::path::{Path, PathBuf};
+
+use jibi::testing::{discover, junit, tap, TestRunner, TestStatus};
+
+mod common;
+
+const PASSING: &str = r#"(import-from "stl/unittest" test test-suite assert-equal)
+(test-suite "passing"
+    (test "addition" (assert-equal 2 (+ 1 1)))
+    (test "subtraction" (assert-equal 0 (- 1 1))))
+"#;
+
+const FAILING: &str = r#"(import-from "stl/unittest" test test-suite assert-equal)
+(defn check (x) (assert-equal 1 x))
+(test-suite "failing"
+    (test "one" (check 1))
+    (test "two"
+        (print "checking 2")
+        (check 2)))
+"#;
+
+/// Create a directory of test files, return its path.
+fn test_dir(name: &str) -> PathBuf {
+    common::temp_dir(
+        name,
+        &[
+            ("test-passing.jibi", PASSING),
+            ("sub/test-failing.jibi", FAILING),
+            ("sub/test-empty.jibi", "(def x 1)"),
+            ("sub/helper.jibi", "(def x 1)"),
+            (".hidden/test-hidden.jibi", "(def x 1)"),
+        ],
+    )
+}
+
+fn relative(dir: &Path, files: &[PathBuf]) -> Vec<String> {
+    files
+        .iter()
+        .map(|f| f.strip_prefix(dir).unwrap().to_string_lossy().to_string())
+        .collect()
+}
+
+#[test]
+fn test_discover() {
+    let dir = test_dir("jibi-test-discover");
+    let files = discover(&[dir.clone(), dir.join("sub/helper.jibi")]).unwrap();
+    assert_eq!(
+        vec![
+            "sub/test-empty.jibi",
+            "sub/test-failing.jibi",
+            "test-passing.jibi",
+            "sub/helper.jibi",
+        ],
+        relative(&dir, &files)
+    );
+}
+
+#[test]
+fn test_runner() {
+    let dir = test_dir("jibi-test-runner");
+    let files = discover(std::slice::from_ref(&dir)).unwrap();
+    let mut out = vec![];
+    let results = TestRunner::new().run(&files, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let out = out.replace(&format!("{}/", dir.display()), "");
+    let (out, summary) = out.rsplit_once("finished in").unwrap();
+    assert!(summary.ends_with("s\n"));
+    // Only keep the first frame of tracebacks, the others are in the library
+    let mut frames = 0;
+    let out: String = out
+        .split_inclusive('\n')
+        .filter(
+            |l| match l.starts_with("  File ") || l.starts_with("  In ") {
+                true => {
+                    frames += 1;
+                    frames == 1
+                }
+                false => true,
+            },
+        )
+        .collect();
+    assert_eq!(
+        r##"test sub/test-empty.jibi ... ok
+test sub/test-failing.jibi: one ... ok
+test sub/test-failing.jibi: two ... FAILED
+test test-passing.jibi: addition ... ok
+test test-passing.jibi: subtraction ... ok
+
+failures:
+
+---- sub/test-failing.jibi: two ----
+checking 2
+Traceback:
+  File "sub/test-failing.jibi", line 2, in lambda check
+Exception: 1 is not equal to 2
+
+test result: FAILED. 4 passed; 1 failed; 0 skipped; 0 filtered out; "##,
+        out
+    );
+
+    assert!(results[0].passed());
+    assert!(!results[1].passed());
+    assert_eq!(None, results[1].error);
+    assert_eq!(
+        vec![true, false],
+        results[1]
+            .tests
+            .iter()
+            .map(|t| t.passed())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!("", results[1].tests[0].output);
+    assert_eq!("checking 2\n", results[1].tests[1].output);
+    assert!(results[1].output.starts_with("Testing failing...\n"));
+}
+
+#[test]
+fn test_runner_filter() {
+    let dir = test_dir("jibi-test-filter");
+    let result = TestRunner::new()
+        .filter("sub")
+        .run_file(&dir.join("test-passing.jibi"));
+    assert!(result.passed());
+    assert_eq!(1, result.filtered);
+    assert_eq!(
+        vec!["subtraction"],
+        result.tests.iter().map(|t| &t.name).collect::<Vec<_>>()
+    );
+
+    // Files raising errors outside of tests fail
+    let path = dir.join("test-error.jibi");
+    std::fs::write(&path, "(undefined-procedure)").unwrap();
+    let result = TestRunner::new().run_file(&path);
+    assert!(!result.passed());
+    assert!(result
+        .error
+        .unwrap()
+        .ends_with("NotDefined: undefined-procedure\n"));
+}
+
+/// Remove the `time` attributes of a JUnit report.
+fn without_times(xml: &str) -> String {
+    let mut xml = xml.to_string();
+    while let Some(start) = xml.find(" time=\"") {
+        let end = xml[start + 7..].find('"').unwrap() + start + 8;
+        xml.replace_range(start..end, "");
+    }
+    xml
+}
+
+#[test]
+fn test_reports() {
+    let dir = test_dir("jibi-test-reports");
+    std::fs::write(dir.join("test-error.jibi"), "(print \"<a & b>\") (nope)").unwrap();
+    let files = discover(&[dir.join("test-error.jibi"), dir.join("sub")]).unwrap();
+    let results = TestRunner::new().run(&files, std::io::sink()).unwrap();
+
+    let tap = tap(&results).replace(&format!("{}/", dir.display()), "");
+    let tap: Vec<&str> = tap
+        .lines()
+        .filter(|l| !l.starts_with("  duration_ms: ") && !l.starts_with("     "))
+        .collect();
+    assert_eq!(
+        vec![
+            "TAP version 13",
+            "1..4",
+            "not ok 1 - test-error.jibi",
+            "  ---",
+            "  message: 'NotDefined: nope'",
+            "  traceback: |",
+            "    Traceback:",
+            "    NotDefined: nope",
+            "  ...",
+            "ok 2 - sub/test-empty.jibi",
+            "  ---",
+            "  ...",
+            "ok 3 - sub/test-failing.jibi: failing: one",
+            "  ---",
+            "  ...",
+            "not ok 4 - sub/test-failing.jibi: failing: two",
+            "  ---",
+            "  message: 'Exception: 1 is not equal to 2'",
+            "  traceback: |",
+            "    Traceback:",
+            "    Exception: 1 is not equal to 2",
+            "  ...",
+        ],
+        tap
+    );
+
+    let junit = without_times(&junit(&results)).replace(&format!("{}/", dir.display()), "");
+    let junit: Vec<&str> = junit
+        .lines()
+        .filter(|l| !l.starts_with("  File ") && !l.starts_with("  In "))
+        .collect();
+    assert_eq!(
+        vec![
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+            r#"<testsuites tests="4" failures="1" errors="1" skipped="0">"#,
+            r#"  <testsuite name="test-error.jibi" file="test-error.jibi" tests="1" failures="0" errors="1" skipped="0">"#,
+            r#"    <testcase name="test-error.jibi" classname="test-error.jibi">"#,
+            r#"      <error message="NotDefined: nope">Traceback:"#,
+            r#"NotDefined: nope</error>"#,
+            r#"      <system-out>&lt;a &amp; b&gt;"#,
+            r#"</system-out>"#,
+            r#"    </testcase>"#,
+            r#"  </testsuite>"#,
+            r#"  <testsuite name="sub/test-empty.jibi" file="sub/test-empty.jibi" tests="1" failures="0" errors="0" skipped="0">"#,
+            r#"    <testcase name="sub/test-empty.jibi" classname="sub/test-empty.jibi"/>"#,
+            r#"  </testsuite>"#,
+            r#"  <testsuite name="failing" file="sub/test-failing.jibi" tests="2" failures="1" errors="0" skipped="0">"#,
+            r#"    <testcase name="one" classname="failing"/>"#,
+            r#"    <testcase name="two" classname="failing">"#,
+            r#"      <failure message="Exception: 1 is not equal to 2">Traceback:"#,
+            r#"Exception: 1 is not equal to 2</failure>"#,
+            r#"      <system-out>checking 2"#,
+            r#"</system-out>"#,
+            r#"    </testcase>"#,
+            r#"  </testsuite>"#,
+            r#"</testsuites>"#,
+        ],
+        junit
+    );
+    assert_eq!(Some("failing"), results[2].tests[0].suite.as_deref());
+}
+
+const FIXTURES: &str = r#"(import-from "stl/unittest" test test-suite assert-equal
+    setup teardown setup-suite teardown-suite skip expect-fail test-cases)
+(def counter 0)
+(test-suite "fixtures"
+    (setup-suite (print "suite setup"))
+    (teardown-suite (print "suite teardown"))
+    (setup (set! counter (+ counter 10)))
+    (teardown (print (concat "teardown " (repr counter))))
+    (test "first"
+        (set! counter (+ counter 1))
+        (assert-equal 11 counter))
+    (test "second" (assert-equal 10 counter))
+    (skip "not ready" (test "skipped" (assert-equal 1 2)))
+    (expect-fail (test "expected failure" (assert-equal 1 2)))
+    (expect-fail (test "unexpected pass" (assert-equal 1 1)))
+    (test-cases "add" (a b sum) '((1 1 2) (2 3 5))
+        (assert-equal sum (+ a b))))
+(print (concat "counter " (repr counter)))
+"#;
+
+#[test]
+fn test_fixtures() {
+    let dir = common::temp_dir("jibi-test-fixtures", &[("test-fixtures.jibi", FIXTURES)]);
+    let path = dir.join("test-fixtures.jibi");
+    let result = TestRunner::new().run_file(&path);
+    assert_eq!(None, result.error);
+    assert_eq!(
+        vec![
+            "first",
+            "second",
+            "skipped",
+            "expected failure",
+            "unexpected pass",
+            "add (1 1 2)",
+            "add (2 3 5)",
+        ],
+        result.tests.iter().map(|t| &t.name).collect::<Vec<_>>()
+    );
+    let tests = &result.tests;
+    assert_eq!(TestStatus::Passed, tests[0].status);
+    assert_eq!(TestStatus::Passed, tests[1].status);
+    assert_eq!(
+        TestStatus::Skipped("not ready".to_string()),
+        tests[2].status
+    );
+    assert!(matches!(
+        &tests[3].status,
+        TestStatus::ExpectedFailure(e) if e.ends_with("Exception: 1 is not equal to 2\n")
+    ));
+    assert!(tests[4]
+        .failure()
+        .unwrap()
+        .ends_with("AssertionError: expected to fail, but passed\n"));
+    assert!(tests[5].passed() && tests[6].passed());
+
+    // Teardowns run after each test, whose mutations are restored
+    assert_eq!("teardown 11\n", tests[0].output);
+    assert_eq!("teardown 10\n", tests[1].output);
+    assert_eq!("", tests[2].output);
+    let output: Vec<&str> = result.output.lines().collect();
+    assert_eq!(
+        vec![
+            "Testing fixtures...",
+            "suite setup",
+            "teardown 11",
+            "teardown 10",
+            "Test skipped: skipped (not ready)",
+            "teardown 10",
+            "teardown 10",
+            "Test unexpected pass: failed #[error AssertionError: expected to fail, but passed]",
+            "teardown 10",
+            "teardown 10",
+            "suite teardown",
+            "Test results: 5 ok, 1 failed, 1 skipped",
+            "counter 0",
+        ],
+        output
+    );
+}
@@ -1,3 +1,4 @@
+use jibi::coverage::Coverage;
 use jibi::Interpreter;
 
 macro_rules! jibitest {
@@ -21,5 +22,33 @@ jibitest!(test_lang_functions);
 jibitest!(test_lang_integers);
 jibitest!(test_lang_lists);
 jibitest!(test_lang_strings);
+jibitest!(test_stl_bench);
 jibitest!(test_stl_decimal);
+jibitest!(test_stl_lists);
 jibitest!(test_stl_math);
+jibitest!(test_stl_quickcheck);
+jibitest!(test_stl_unittest);
+
+#[test]
+fn test_stl_coverage() {
+    let mut jibi = Interpreter::default();
+    jibi.set_coverage(Some(Coverage::new()));
+    // Before stl/decimal, which overloads the arithmetic builtins
+    for test in [
+        "tests/test-stl-quickcheck.jibi",
+        "tests/test-stl-bench.jibi",
+        "tests/test-stl-decimal.jibi",
+        "tests/test-stl-math.jibi",
+        "tests/test-stl-unittest.jibi",
+    ] {
+        if let Err((_, e, _)) = jibi.eval_file(test) {
+            panic!("{}", e);
+        }
+    }
+    let coverage = jibi.take_coverage().unwrap();
+    for file in coverage.files() {
+        if file.filename.contains("stl/") {
+            assert!(file.percent() >= 80.0, "{}", coverage.report());
+        }
+    }
+}